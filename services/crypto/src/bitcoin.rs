@@ -0,0 +1,346 @@
+use base58::FromBase58;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bech32::{FromBase32, Variant};
+use libsecp256k1::{recover, verify as verify_ecdsa, Message, PublicKey, RecoveryId, Signature};
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+
+use crate::error::CryptoError;
+
+pub const ENC: &str = "bitcoin";
+
+const MESSAGE_PREFIX: &[u8] = b"\x18Bitcoin Signed Message:\n";
+const BIP322_TAG: &[u8] = b"BIP0322-signed-message";
+
+/// Base58check version bytes, mainnet and testnet
+const VERSION_P2PKH: [u8; 2] = [0x00, 0x6f];
+const VERSION_P2SH: [u8; 2] = [0x05, 0xc4];
+const SEGWIT_HRPS: [&str; 3] = ["bc", "tb", "bcrt"];
+
+const SIGHASH_ALL: u8 = 0x01;
+
+/**
+ * Output types a signature can be checked against
+ */
+#[derive(Debug, PartialEq)]
+pub enum Address {
+    P2pkh([u8; 20]),
+    P2shP2wpkh([u8; 20]),
+    P2wpkh([u8; 20]),
+}
+
+pub fn hash160(bytes: &[u8]) -> [u8; 20] {
+    Ripemd160::digest(Sha256::digest(bytes)).into()
+}
+
+fn double_sha256(bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(Sha256::digest(bytes)).into()
+}
+
+/**
+ * Parses a P2PKH or P2SH base58check address, or a v0 20 byte segwit address
+ */
+pub fn decode_address(address: &str) -> Option<Address> {
+    if let Ok((hrp, data, variant)) = bech32::decode(address) {
+        if !SEGWIT_HRPS.contains(&hrp.as_str()) || variant != Variant::Bech32 {
+            return None;
+        }
+        let (version, program) = data.split_first()?;
+        let program = Vec::<u8>::from_base32(program).ok()?;
+
+        return match (version.to_u8(), program.try_into()) {
+            (0, Ok(hash)) => Some(Address::P2wpkh(hash)),
+            _ => None,
+        };
+    }
+
+    let bytes = address.from_base58().ok()?;
+    if bytes.len() != 25 {
+        return None;
+    }
+    let (payload, checksum) = bytes.split_at(21);
+    if double_sha256(payload)[..4] != *checksum {
+        return None;
+    }
+
+    let hash = payload[1..].try_into().ok()?;
+    if VERSION_P2PKH.contains(&payload[0]) {
+        Some(Address::P2pkh(hash))
+    } else if VERSION_P2SH.contains(&payload[0]) {
+        Some(Address::P2shP2wpkh(hash))
+    } else {
+        None
+    }
+}
+
+/**
+ * Verifies a base64 signature from a Bitcoin wallet against an address.
+ * A 65 byte signature is a BIP-137 (legacy "signmessage") signature, anything
+ * else is read as a BIP-322 simple signature, which we support for P2WPKH.
+ */
+pub fn verify(public_key: &str, signature: &str, message: &str) -> Result<(), CryptoError> {
+    let address =
+        decode_address(public_key).ok_or(CryptoError::InvalidPublicKey(public_key.to_string()))?;
+
+    let sign_decoded = STANDARD
+        .decode(signature)
+        .map_err(|_| CryptoError::InvalidSignature("not base64 encoded".to_string()))?;
+
+    if sign_decoded.len() == 65 {
+        verify_bip137(&address, &sign_decoded, message)
+    } else {
+        verify_bip322(&address, &sign_decoded, message)
+    }
+}
+
+/**
+ * The header byte encodes the recovery id and whether the key is compressed.
+ * Wallets disagree on the header for segwit addresses, so the recovered key
+ * is checked against the address type rather than the header range.
+ */
+fn verify_bip137(address: &Address, signature: &[u8], message: &str) -> Result<(), CryptoError> {
+    let header = signature[0];
+    if !(27..=42).contains(&header) {
+        return Err(CryptoError::InvalidRecoveryId(header as u64));
+    }
+    let compressed = header >= 31;
+
+    let mut framed = MESSAGE_PREFIX.to_vec();
+    framed.extend_from_slice(&varint(message.len()));
+    framed.extend_from_slice(message.as_bytes());
+
+    let ctx_sig = Signature::parse_standard_slice(&signature[1..])
+        .map_err(|_| CryptoError::InvalidSignature("r or s overflows".to_string()))?;
+    let ctx_recovery_id = RecoveryId::parse((header - 27) & 3)
+        .map_err(|_| CryptoError::InvalidRecoveryId(header as u64))?;
+
+    let pubkey = recover(
+        &Message::parse(&double_sha256(&framed)),
+        &ctx_sig,
+        &ctx_recovery_id,
+    )
+    .map_err(|_| CryptoError::InvalidSignature("unable to recover signer".to_string()))?;
+
+    let key_hash = if compressed {
+        hash160(&pubkey.serialize_compressed())
+    } else {
+        hash160(&pubkey.serialize())
+    };
+
+    let matches = match address {
+        Address::P2pkh(hash) => *hash == key_hash,
+        Address::P2wpkh(hash) => compressed && *hash == key_hash,
+        Address::P2shP2wpkh(hash) => compressed && *hash == hash160(&p2wpkh_script(&key_hash)),
+    };
+
+    if !matches {
+        return Err(CryptoError::SignerMismatch(hex::encode(key_hash)));
+    }
+
+    Ok(())
+}
+
+/**
+ * BIP-322 simple signature: the serialized witness spending a virtual
+ * `to_spend` output that commits to the message. For P2WPKH the witness is
+ * `[der_signature || sighash, compressed_pubkey]`, signed with BIP-143.
+ */
+fn verify_bip322(address: &Address, witness: &[u8], message: &str) -> Result<(), CryptoError> {
+    let program = match address {
+        Address::P2wpkh(hash) => hash,
+        _ => {
+            return Err(CryptoError::InvalidSignature(
+                "BIP-322 is only supported for P2WPKH addresses".to_string(),
+            ))
+        }
+    };
+
+    let items = parse_witness(witness).ok_or(CryptoError::InvalidSignature(
+        "malformed witness".to_string(),
+    ))?;
+    let [sig, pubkey] = items.as_slice() else {
+        return Err(CryptoError::InvalidSignature(
+            "expected a two item witness".to_string(),
+        ));
+    };
+
+    let (sighash_type, der) = sig
+        .split_last()
+        .ok_or(CryptoError::InvalidSignature("empty signature".to_string()))?;
+    if *sighash_type != SIGHASH_ALL {
+        return Err(CryptoError::InvalidSignature(format!(
+            "unsupported sighash type {}",
+            sighash_type
+        )));
+    }
+
+    if hash160(pubkey) != *program {
+        return Err(CryptoError::SignerMismatch(hex::encode(hash160(pubkey))));
+    }
+    let ctx_pubkey = PublicKey::parse_slice(pubkey, None)
+        .map_err(|_| CryptoError::InvalidPublicKey(hex::encode(pubkey)))?;
+    let ctx_sig = Signature::parse_der(der)
+        .map_err(|_| CryptoError::InvalidSignature("invalid DER signature".to_string()))?;
+
+    let sighash = bip322_sighash(program, message);
+
+    if !verify_ecdsa(&Message::parse(&sighash), &ctx_sig, &ctx_pubkey) {
+        return Err(CryptoError::InvalidSignature(
+            "signature doesn't verify".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn bip322_message_hash(message: &str) -> [u8; 32] {
+    let tag = Sha256::digest(BIP322_TAG);
+    Sha256::new()
+        .chain_update(tag)
+        .chain_update(tag)
+        .chain_update(message.as_bytes())
+        .finalize()
+        .into()
+}
+
+fn bip322_sighash(program: &[u8; 20], message: &str) -> [u8; 32] {
+    let message_hash = bip322_message_hash(message);
+
+    // to_spend: spends a null outpoint with OP_0 <message_hash>, pays the address
+    let mut to_spend = Vec::new();
+    to_spend.extend_from_slice(&0u32.to_le_bytes());
+    to_spend.push(1);
+    to_spend.extend_from_slice(&[0u8; 32]);
+    to_spend.extend_from_slice(&u32::MAX.to_le_bytes());
+    to_spend.extend_from_slice(&[0x22, 0x00, 0x20]);
+    to_spend.extend_from_slice(&message_hash);
+    to_spend.extend_from_slice(&0u32.to_le_bytes());
+    to_spend.push(1);
+    to_spend.extend_from_slice(&0u64.to_le_bytes());
+    let script_pubkey = p2wpkh_script(program);
+    to_spend.push(script_pubkey.len() as u8);
+    to_spend.extend_from_slice(&script_pubkey);
+    to_spend.extend_from_slice(&0u32.to_le_bytes());
+
+    let mut outpoint = double_sha256(&to_spend).to_vec();
+    outpoint.extend_from_slice(&0u32.to_le_bytes());
+
+    // to_sign: spends to_spend:0 into a single OP_RETURN output, BIP-143 digest
+    let mut preimage = Vec::new();
+    preimage.extend_from_slice(&0u32.to_le_bytes());
+    preimage.extend_from_slice(&double_sha256(&outpoint));
+    preimage.extend_from_slice(&double_sha256(&0u32.to_le_bytes()));
+    preimage.extend_from_slice(&outpoint);
+    preimage.extend_from_slice(&[0x19, 0x76, 0xa9, 0x14]);
+    preimage.extend_from_slice(program);
+    preimage.extend_from_slice(&[0x88, 0xac]);
+    preimage.extend_from_slice(&0u64.to_le_bytes());
+    preimage.extend_from_slice(&0u32.to_le_bytes());
+    let mut output = 0u64.to_le_bytes().to_vec();
+    output.extend_from_slice(&[0x01, 0x6a]);
+    preimage.extend_from_slice(&double_sha256(&output));
+    preimage.extend_from_slice(&0u32.to_le_bytes());
+    preimage.extend_from_slice(&(SIGHASH_ALL as u32).to_le_bytes());
+
+    double_sha256(&preimage)
+}
+
+fn p2wpkh_script(hash: &[u8; 20]) -> Vec<u8> {
+    let mut script = vec![0x00, 0x14];
+    script.extend_from_slice(hash);
+    script
+}
+
+fn varint(n: usize) -> Vec<u8> {
+    match n {
+        0..=0xfc => vec![n as u8],
+        0xfd..=0xffff => {
+            let mut v = vec![0xfd];
+            v.extend_from_slice(&(n as u16).to_le_bytes());
+            v
+        }
+        _ => {
+            let mut v = vec![0xfe];
+            v.extend_from_slice(&(n as u32).to_le_bytes());
+            v
+        }
+    }
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Option<usize> {
+    let first = *bytes.get(*pos)?;
+    *pos += 1;
+
+    let width = match first {
+        0xfd => 2,
+        0xfe => 4,
+        0xff => 8,
+        n => return Some(n as usize),
+    };
+    let value = bytes
+        .get(*pos..*pos + width)?
+        .iter()
+        .rev()
+        .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+    *pos += width;
+
+    Some(value)
+}
+
+fn parse_witness(bytes: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut pos = 0;
+    let count = read_varint(bytes, &mut pos)?;
+
+    let mut items = Vec::new();
+    for _ in 0..count {
+        let length = read_varint(bytes, &mut pos)?;
+        let end = pos.checked_add(length)?;
+        items.push(bytes.get(pos..end)?.to_vec());
+        pos = end;
+    }
+
+    if pos != bytes.len() {
+        return None;
+    }
+
+    Some(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vectors from BIP-322
+    const ADDRESS: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    const SIGNATURE_EMPTY: &str = "AkcwRAIgM2gBAQqvZX15ZiysmKmQpDrG83avLIT492QBzLnQIxYCIBaTpOaD20qRlEylyxFSeEA2ba9YOixpX8z46TSDtS40ASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+    const SIGNATURE_HELLO_WORLD: &str = "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+
+    #[test]
+    fn bip322_message_hashes() {
+        assert_eq!(
+            hex::encode(bip322_message_hash("")),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            hex::encode(bip322_message_hash("Hello World")),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+    }
+
+    #[test]
+    fn bip322_empty_message() {
+        assert!(verify(ADDRESS, SIGNATURE_EMPTY, "").is_ok());
+    }
+
+    #[test]
+    fn bip322_hello_world() {
+        assert!(verify(ADDRESS, SIGNATURE_HELLO_WORLD, "Hello World").is_ok());
+    }
+
+    #[test]
+    fn bip322_wrong_message() {
+        assert!(verify(ADDRESS, SIGNATURE_HELLO_WORLD, "").is_err());
+        assert!(verify(ADDRESS, SIGNATURE_EMPTY, "Hello World").is_err());
+    }
+}
@@ -0,0 +1,329 @@
+use bls12_381::hash_to_curve::{ExpandMsgXmd, HashToCurve};
+use bls12_381::{multi_miller_loop, G1Affine, G1Projective, G2Affine, G2Prepared, G2Projective};
+use bls12_381::{Gt, Scalar};
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256};
+use types::KeyPair;
+
+use crate::error::CryptoError;
+
+pub const ENC: &str = "bls12381";
+
+/// Ciphersuites of the proof of possession scheme, public keys in G1
+const DST_SIGNATURE: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+const DST_POP: &[u8] = b"BLS_POP_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+const KEYGEN_SALT: &[u8] = b"BLS-SIG-KEYGEN-SALT-";
+const KEYGEN_IKM_LENGTH: usize = 32;
+const KEYGEN_OKM_LENGTH: usize = 48;
+
+/**
+ * `pk` is the 0x hex compressed G1 key (48 bytes), `sk` the 0x hex big endian
+ * scalar (32 bytes)
+ */
+pub fn generate_keypair() -> Result<KeyPair, CryptoError> {
+    let mut ikm = [0u8; KEYGEN_IKM_LENGTH];
+    getrandom::getrandom(&mut ikm).map_err(|e| CryptoError::KeyGeneration(e.to_string()))?;
+
+    let secret = key_gen(&ikm)?;
+    let mut sk = secret.to_bytes();
+    sk.reverse();
+
+    Ok(KeyPair {
+        pk: format!("0x{}", hex::encode(public_key(&secret).to_compressed())),
+        sk: format!("0x{}", hex::encode(sk)),
+        enc: ENC.to_string(),
+        ..Default::default()
+    })
+}
+
+pub fn sign(message: &str, private_key: &str) -> Result<String, CryptoError> {
+    let secret = parse_private_key(private_key)?;
+
+    Ok(encode_signature(&sign_with(
+        &secret,
+        message.as_bytes(),
+        DST_SIGNATURE,
+    )))
+}
+
+pub fn verify(public_key: &str, signature: &str, message: &str) -> Result<(), CryptoError> {
+    let key = parse_public_key(public_key)?;
+    let signature = parse_signature(signature)?;
+
+    check_pairings(
+        &[(key, hash(message.as_bytes(), DST_SIGNATURE))],
+        &signature,
+    )
+}
+
+/**
+ * Proof of possession of `private_key`, a signature of its public key. The
+ * proof must be checked with `pop_verify` before the key is used in a fast
+ * aggregate verification.
+ */
+pub fn pop_prove(private_key: &str) -> Result<String, CryptoError> {
+    let secret = parse_private_key(private_key)?;
+    let public_key = public_key(&secret).to_compressed();
+
+    Ok(encode_signature(&sign_with(&secret, &public_key, DST_POP)))
+}
+
+pub fn pop_verify(public_key: &str, proof: &str) -> Result<(), CryptoError> {
+    let key = parse_public_key(public_key)?;
+    let proof = parse_signature(proof)?;
+
+    check_pairings(&[(key, hash(&key.to_compressed(), DST_POP))], &proof)
+}
+
+/**
+ * Sum of the signatures, verified with `aggregate_verify` or
+ * `fast_aggregate_verify`
+ */
+pub fn aggregate(signatures: &[String]) -> Result<String, CryptoError> {
+    if signatures.is_empty() {
+        return Err(CryptoError::InvalidSignature("no signatures".to_string()));
+    }
+
+    let aggregate = signatures
+        .iter()
+        .map(|signature| parse_signature(signature))
+        .try_fold(G2Projective::identity(), |sum, signature| {
+            signature.map(|signature| sum + signature)
+        })?;
+
+    Ok(encode_signature(&G2Affine::from(aggregate)))
+}
+
+/**
+ * Aggregate signature of each key over its own message
+ */
+pub fn aggregate_verify(
+    public_keys: &[String],
+    messages: &[String],
+    signature: &str,
+) -> Result<(), CryptoError> {
+    if public_keys.is_empty() || public_keys.len() != messages.len() {
+        return Err(CryptoError::InvalidSignature(format!(
+            "{} public keys for {} messages",
+            public_keys.len(),
+            messages.len()
+        )));
+    }
+
+    let signature = parse_signature(signature)?;
+    let terms = public_keys
+        .iter()
+        .zip(messages)
+        .map(|(key, message)| {
+            parse_public_key(key).map(|key| (key, hash(message.as_bytes(), DST_SIGNATURE)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    check_pairings(&terms, &signature)
+}
+
+/**
+ * Aggregate signature of every key over the same message. Safe only for keys
+ * whose proof of possession has been verified.
+ */
+pub fn fast_aggregate_verify(
+    public_keys: &[String],
+    message: &str,
+    signature: &str,
+) -> Result<(), CryptoError> {
+    if public_keys.is_empty() {
+        return Err(CryptoError::InvalidPublicKey("no public keys".to_string()));
+    }
+
+    let aggregate = public_keys
+        .iter()
+        .map(|key| parse_public_key(key))
+        .try_fold(G1Projective::identity(), |sum, key| {
+            key.map(|key| sum + key)
+        })?;
+    let signature = parse_signature(signature)?;
+
+    check_pairings(
+        &[(
+            G1Affine::from(aggregate),
+            hash(message.as_bytes(), DST_SIGNATURE),
+        )],
+        &signature,
+    )
+}
+
+/**
+ * KeyGen of the IETF BLS signature draft, HKDF-SHA256 reduced modulo r
+ */
+fn key_gen(ikm: &[u8]) -> Result<Scalar, CryptoError> {
+    let mut salt = KEYGEN_SALT.to_vec();
+    let ikm = [ikm, &[0]].concat();
+    let info = (KEYGEN_OKM_LENGTH as u16).to_be_bytes();
+
+    loop {
+        salt = Sha256::digest(&salt).to_vec();
+
+        let mut okm = [0u8; KEYGEN_OKM_LENGTH];
+        Hkdf::<Sha256>::new(Some(&salt), &ikm)
+            .expand(&info, &mut okm)
+            .map_err(|e| CryptoError::KeyGeneration(e.to_string()))?;
+
+        // big endian okm as a little endian wide integer
+        let mut wide = [0u8; 64];
+        okm.iter()
+            .rev()
+            .enumerate()
+            .for_each(|(i, byte)| wide[i] = *byte);
+
+        let secret = Scalar::from_bytes_wide(&wide);
+        if secret != Scalar::zero() {
+            return Ok(secret);
+        }
+    }
+}
+
+fn public_key(secret: &Scalar) -> G1Affine {
+    G1Affine::from(G1Affine::generator() * secret)
+}
+
+fn sign_with(secret: &Scalar, message: &[u8], dst: &[u8]) -> G2Affine {
+    G2Affine::from(hash(message, dst) * secret)
+}
+
+fn hash(message: &[u8], dst: &[u8]) -> G2Affine {
+    G2Affine::from(<G2Projective as HashToCurve<
+        ExpandMsgXmd<sha2_09::Sha256>,
+    >>::hash_to_curve(message, dst))
+}
+
+/**
+ * Product of e(pk, H(m)) over `terms` equals e(g1, signature)
+ */
+fn check_pairings(terms: &[(G1Affine, G2Affine)], signature: &G2Affine) -> Result<(), CryptoError> {
+    let generator = -G1Affine::generator();
+    let prepared = terms
+        .iter()
+        .map(|(key, point)| (key, G2Prepared::from(*point)))
+        .chain(std::iter::once((&generator, G2Prepared::from(*signature))))
+        .collect::<Vec<_>>();
+    let refs = prepared
+        .iter()
+        .map(|(key, point)| (*key, point))
+        .collect::<Vec<_>>();
+
+    if multi_miller_loop(&refs).final_exponentiation() == Gt::identity() {
+        Ok(())
+    } else {
+        Err(CryptoError::InvalidSignature(
+            "pairing check failed".to_string(),
+        ))
+    }
+}
+
+fn parse_private_key(private_key: &str) -> Result<Scalar, CryptoError> {
+    let invalid = || CryptoError::InvalidPrivateKey("expected 32 bytes hex".to_string());
+
+    let mut bytes: [u8; 32] = hex::decode(private_key.trim_start_matches("0x"))
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(invalid)?;
+    bytes.reverse();
+
+    Option::<Scalar>::from(Scalar::from_bytes(&bytes))
+        .filter(|secret| *secret != Scalar::zero())
+        .ok_or_else(invalid)
+}
+
+/**
+ * Compressed G1 point, in the subgroup and not the identity
+ */
+fn parse_public_key(public_key: &str) -> Result<G1Affine, CryptoError> {
+    let bytes: [u8; 48] = hex::decode(public_key.trim_start_matches("0x"))
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| CryptoError::InvalidPublicKey(public_key.to_string()))?;
+
+    Option::<G1Affine>::from(G1Affine::from_compressed(&bytes))
+        .filter(|key| !bool::from(key.is_identity()))
+        .ok_or_else(|| CryptoError::InvalidPublicKey(public_key.to_string()))
+}
+
+fn parse_signature(signature: &str) -> Result<G2Affine, CryptoError> {
+    let invalid = || CryptoError::InvalidSignature("expected a 96 bytes G2 point".to_string());
+
+    let bytes: [u8; 96] = hex::decode(signature.trim_start_matches("0x"))
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(invalid)?;
+
+    Option::<G2Affine>::from(G2Affine::from_compressed(&bytes)).ok_or_else(invalid)
+}
+
+fn encode_signature(signature: &G2Affine) -> String {
+    format!("0x{}", hex::encode(signature.to_compressed()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vectors from the Ethereum consensus BLS suite (ethereum/bls12-381-tests),
+    // which uses the same proof of possession ciphersuite
+    const PRIVATE_KEYS: [&str; 3] = [
+        "0x263dbd792f5b1be47ed85f8938c0f29586af0d3ac7b977f21c278fe1462040e3",
+        "0x47b8192d77bf871b62e87859d653922725724a5c031afeabc60bcef5ff665138",
+        "0x328388aff0d4a5b7dc9205abd374e7e98f3cd9f3418edb4eafda5fb16473d216",
+    ];
+    const PUBLIC_KEYS: [&str; 3] = [
+        "0xa491d1b0ecd9bb917989f0e74f0dea0422eac4a873e5e2644f368dffb9a6e20fd6e10c1b77654d067c0618f6e5a7f79a",
+        "0xb301803f8b5ac4a1133581fc676dfedc60d891dd5fa99028805e5ea5b08d3491af75d0707adab3b70c6a6a580217bf81",
+        "0xb53d21a4cfd562c469cc81514d4ce5a6b577d8403d32a394dc265dd190b47fa9f829fdd7963afdf972e5e77854051f6f",
+    ];
+    const SIGNATURES: [&str; 3] = [
+        "0xb6ed936746e01f8ecf281f020953fbf1f01debd5657c4a383940b020b26507f6076334f91e2366c96e9ab279fb5158090352ea1c5b0c9274504f4f0e7053af24802e51e4568d164fe986834f41e55c8e850ce1f98458c0cfc9ab380b55285a55",
+        "0xb23c46be3a001c63ca711f87a005c200cc550b9429d5f4eb38d74322144f1b63926da3388979e5321012fb1a0526bcd100b5ef5fe72628ce4cd5e904aeaa3279527843fae5ca9ca675f4f51ed8f83bbf7155da9ecc9663100a885d5dc6df96d9",
+        "0x948a7cb99f76d616c2c564ce9bf4a519f1bea6b0a624a02276443c245854219fabb8d4ce061d255af5330b078d5380681751aa7053da2c98bae898edc218c75f07e24d8802a17cd1f6833b71e58f5eb5b94208b4d0bb3848cecb075ea21be115",
+    ];
+    const AGGREGATE: &str = "0x9683b3e6701f9a4b706709577963110043af78a5b41991b998475a3d3fd62abf35ce03b33908418efc95a058494a8ae504354b9f626231f6b3f3c849dfdeaf5017c4780e2aee1850ceaf4b4d9ce70971a3d2cfcd97b7e5ecf6759f8da5f76d31";
+
+    /// The 32 zero byte message of the suite
+    fn message() -> String {
+        "\0".repeat(32)
+    }
+
+    #[test]
+    fn sign_vectors() {
+        for ((private_key, public_key), signature) in
+            PRIVATE_KEYS.iter().zip(PUBLIC_KEYS).zip(SIGNATURES)
+        {
+            let secret = parse_private_key(private_key).unwrap();
+            assert_eq!(
+                format!(
+                    "0x{}",
+                    hex::encode(super::public_key(&secret).to_compressed())
+                ),
+                public_key
+            );
+            assert_eq!(sign(&message(), private_key).unwrap(), signature);
+            assert!(verify(public_key, signature, &message()).is_ok());
+        }
+    }
+
+    #[test]
+    fn aggregate_vector() {
+        let signatures: Vec<String> = SIGNATURES.iter().map(|s| s.to_string()).collect();
+        assert_eq!(aggregate(&signatures).unwrap(), AGGREGATE);
+    }
+
+    #[test]
+    fn aggregate_verifies() {
+        let public_keys: Vec<String> = PUBLIC_KEYS.iter().map(|k| k.to_string()).collect();
+        let messages = vec![message(); 3];
+
+        assert!(fast_aggregate_verify(&public_keys, &message(), AGGREGATE).is_ok());
+        assert!(aggregate_verify(&public_keys, &messages, AGGREGATE).is_ok());
+        assert!(fast_aggregate_verify(&public_keys[..2], &message(), AGGREGATE).is_err());
+        assert!(fast_aggregate_verify(&public_keys, "other", AGGREGATE).is_err());
+    }
+}
@@ -0,0 +1,475 @@
+use base58::{FromBase58, ToBase58};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use types::{
+    Envelope, EnvelopeRecipient, ENVELOPE_CIPHER, ENVELOPE_VERSION, KEM_SECP256K1, KEM_X25519,
+};
+
+use crate::error::CryptoError;
+use crate::key::{self, ENC_ED25519, ENC_SECP256K1};
+
+const KDF_INFO: &[u8] = b"fdb-envelope-v1";
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+/**
+ * A recipient public key with the form stored in the envelope
+ */
+enum RecipientKey {
+    X25519 {
+        key: String,
+        point: x25519_dalek::PublicKey,
+    },
+    Secp256k1 {
+        key: String,
+        point: libsecp256k1::PublicKey,
+    },
+}
+
+/**
+ * A private key able to open envelope entries addressed to `key`
+ */
+enum PrivateKey {
+    X25519 {
+        key: String,
+        secret: x25519_dalek::StaticSecret,
+    },
+    Secp256k1 {
+        key: String,
+        secret: libsecp256k1::SecretKey,
+    },
+}
+
+/**
+ * Encrypts `plaintext` once with a random content key and wraps that key for
+ * every recipient. Returns the JSON envelope.
+ */
+pub fn encrypt(recipients: &[String], plaintext: &str) -> Result<String, CryptoError> {
+    if recipients.is_empty() {
+        return Err(CryptoError::Encryption("no recipients".to_string()));
+    }
+
+    let content_key = random::<KEY_LENGTH>()?;
+    let nonce = random::<NONCE_LENGTH>()?;
+    let ciphertext = seal(&content_key, &nonce, plaintext.as_bytes())?;
+
+    let recipients = recipients
+        .iter()
+        .map(|recipient| wrap_key(&recipient_key(recipient)?, &content_key))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let envelope = Envelope {
+        version: ENVELOPE_VERSION,
+        cipher: ENVELOPE_CIPHER.to_string(),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+        recipients,
+    };
+
+    serde_json::to_string(&envelope).map_err(|e| CryptoError::Encryption(e.to_string()))
+}
+
+/**
+ * Opens an envelope with the private key of one of its recipients, in the
+ * encoding `generate_keypair` returns for `enc`
+ */
+pub fn decrypt(envelope: &str, private_key: &str, enc: &str) -> Result<String, CryptoError> {
+    let envelope = parse_envelope(envelope)?;
+    let content_key = open_content_key(&envelope, private_key, enc)?;
+
+    let nonce = decode(&envelope.nonce, "nonce")?;
+    let ciphertext = decode(&envelope.ciphertext, "ciphertext")?;
+    let plaintext = open(&content_key, &nonce, &ciphertext)?;
+
+    String::from_utf8(plaintext).map_err(|e| CryptoError::InvalidEnvelope(e.to_string()))
+}
+
+/**
+ * Replaces the recipients of an envelope, the content key is opened with the
+ * private key of a current recipient and wrapped again for `recipients`.
+ * The ciphertext is left as it is.
+ */
+pub fn rewrap(
+    envelope: &str,
+    private_key: &str,
+    enc: &str,
+    recipients: &[String],
+) -> Result<String, CryptoError> {
+    if recipients.is_empty() {
+        return Err(CryptoError::Encryption("no recipients".to_string()));
+    }
+
+    let mut envelope = parse_envelope(envelope)?;
+    let content_key = open_content_key(&envelope, private_key, enc)?;
+
+    envelope.recipients = recipients
+        .iter()
+        .map(|recipient| wrap_key(&recipient_key(recipient)?, &content_key))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    serde_json::to_string(&envelope).map_err(|e| CryptoError::Encryption(e.to_string()))
+}
+
+fn parse_envelope(envelope: &str) -> Result<Envelope, CryptoError> {
+    let envelope: Envelope =
+        serde_json::from_str(envelope).map_err(|e| CryptoError::InvalidEnvelope(e.to_string()))?;
+
+    if envelope.version != ENVELOPE_VERSION || envelope.cipher != ENVELOPE_CIPHER {
+        return Err(CryptoError::InvalidEnvelope(format!(
+            "unsupported version {} or cipher {}",
+            envelope.version, envelope.cipher
+        )));
+    }
+
+    Ok(envelope)
+}
+
+fn open_content_key(
+    envelope: &Envelope,
+    private_key: &str,
+    enc: &str,
+) -> Result<[u8; KEY_LENGTH], CryptoError> {
+    let private_key = parse_private_key(private_key, enc)?;
+    let (own_key, kem) = match &private_key {
+        PrivateKey::X25519 { key, .. } => (key, KEM_X25519),
+        PrivateKey::Secp256k1 { key, .. } => (key, KEM_SECP256K1),
+    };
+
+    let recipient = envelope
+        .recipients
+        .iter()
+        .find(|r| &r.key == own_key && r.kem == kem)
+        .ok_or_else(|| CryptoError::InvalidEnvelope(format!("{} is not a recipient", own_key)))?;
+
+    unwrap_key(&private_key, recipient)
+}
+
+fn recipient_key(public_key: &str) -> Result<RecipientKey, CryptoError> {
+    let invalid = || CryptoError::InvalidPublicKey(format!("cannot encrypt to {}", public_key));
+    let parsed = key::parse(public_key)?;
+
+    if parsed.enc == ENC_ED25519 {
+        let bytes: [u8; 32] = parsed
+            .public_key
+            .from_base58()
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(invalid)?;
+        let verifying_key = VerifyingKey::from_bytes(&bytes).map_err(|_| invalid())?;
+
+        return Ok(RecipientKey::X25519 {
+            key: parsed.public_key,
+            point: x25519_dalek::PublicKey::from(verifying_key.to_montgomery().to_bytes()),
+        });
+    }
+
+    if parsed.enc == ENC_SECP256K1 {
+        // addresses carry no public key, only raw keys can be encrypted to
+        let bytes =
+            hex::decode(parsed.public_key.trim_start_matches("0x")).map_err(|_| invalid())?;
+        let point = libsecp256k1::PublicKey::parse_slice(&bytes, None).map_err(|_| invalid())?;
+
+        return Ok(RecipientKey::Secp256k1 {
+            key: format!("0x{}", hex::encode(point.serialize_compressed())),
+            point,
+        });
+    }
+
+    Err(invalid())
+}
+
+fn parse_private_key(private_key: &str, enc: &str) -> Result<PrivateKey, CryptoError> {
+    if enc == ENC_ED25519 {
+        let decoded = private_key.from_base58().map_err(|_| {
+            CryptoError::InvalidPrivateKey("expected base58 32 or 64 bytes".to_string())
+        })?;
+        let seed: [u8; 32] = decoded
+            .get(..32)
+            .filter(|_| decoded.len() == 32 || decoded.len() == 64)
+            .and_then(|s| s.try_into().ok())
+            .ok_or_else(|| {
+                CryptoError::InvalidPrivateKey("expected base58 32 or 64 bytes".to_string())
+            })?;
+        let signing_key = SigningKey::from_bytes(&seed);
+
+        return Ok(PrivateKey::X25519 {
+            key: signing_key.verifying_key().to_bytes().to_base58(),
+            secret: x25519_dalek::StaticSecret::from(signing_key.to_scalar_bytes()),
+        });
+    }
+
+    if enc.is_empty() || enc == ENC_SECP256K1 {
+        let decoded = hex::decode(private_key.trim_start_matches("0x"))
+            .map_err(|e| CryptoError::InvalidPrivateKey(e.to_string()))?;
+        let secret = libsecp256k1::SecretKey::parse_slice(&decoded)
+            .map_err(|_| CryptoError::InvalidPrivateKey("expected 32 bytes".to_string()))?;
+        let public = libsecp256k1::PublicKey::from_secret_key(&secret);
+
+        return Ok(PrivateKey::Secp256k1 {
+            key: format!("0x{}", hex::encode(public.serialize_compressed())),
+            secret,
+        });
+    }
+
+    Err(CryptoError::NotSupportedEncryptionType(enc.to_string()))
+}
+
+fn wrap_key(
+    recipient: &RecipientKey,
+    content_key: &[u8; KEY_LENGTH],
+) -> Result<EnvelopeRecipient, CryptoError> {
+    let (key, kem, ephemeral, shared, recipient_bytes) = match recipient {
+        RecipientKey::X25519 { key, point } => {
+            let secret = x25519_dalek::StaticSecret::from(random::<KEY_LENGTH>()?);
+            let ephemeral = x25519_dalek::PublicKey::from(&secret);
+            let shared = secret.diffie_hellman(point).to_bytes().to_vec();
+
+            (
+                key,
+                KEM_X25519,
+                ephemeral.to_bytes().to_vec(),
+                shared,
+                point.to_bytes().to_vec(),
+            )
+        }
+        RecipientKey::Secp256k1 { key, point } => {
+            let secret = random_secp256k1_secret()?;
+            let ephemeral = libsecp256k1::PublicKey::from_secret_key(&secret);
+            let shared = secp256k1_shared(point, &secret)?;
+
+            (
+                key,
+                KEM_SECP256K1,
+                ephemeral.serialize_compressed().to_vec(),
+                shared,
+                point.serialize_compressed().to_vec(),
+            )
+        }
+    };
+
+    let kek = derive_kek(&shared, &ephemeral, &recipient_bytes)?;
+    let nonce = random::<NONCE_LENGTH>()?;
+    let wrapped_key = seal(&kek, &nonce, content_key)?;
+
+    Ok(EnvelopeRecipient {
+        key: key.clone(),
+        kem: kem.to_string(),
+        ephemeral: STANDARD.encode(ephemeral),
+        nonce: STANDARD.encode(nonce),
+        wrapped_key: STANDARD.encode(wrapped_key),
+    })
+}
+
+fn unwrap_key(
+    private_key: &PrivateKey,
+    recipient: &EnvelopeRecipient,
+) -> Result<[u8; KEY_LENGTH], CryptoError> {
+    let ephemeral = decode(&recipient.ephemeral, "ephemeral")?;
+    let invalid_ephemeral = || CryptoError::InvalidEnvelope("invalid ephemeral key".to_string());
+
+    let (shared, recipient_bytes) = match private_key {
+        PrivateKey::X25519 { secret, .. } => {
+            let ephemeral: [u8; 32] = ephemeral
+                .as_slice()
+                .try_into()
+                .map_err(|_| invalid_ephemeral())?;
+            let shared = secret.diffie_hellman(&x25519_dalek::PublicKey::from(ephemeral));
+
+            (
+                shared.to_bytes().to_vec(),
+                x25519_dalek::PublicKey::from(secret).to_bytes().to_vec(),
+            )
+        }
+        PrivateKey::Secp256k1 { secret, .. } => {
+            let point = libsecp256k1::PublicKey::parse_slice(&ephemeral, None)
+                .map_err(|_| invalid_ephemeral())?;
+
+            (
+                secp256k1_shared(&point, secret)?,
+                libsecp256k1::PublicKey::from_secret_key(secret)
+                    .serialize_compressed()
+                    .to_vec(),
+            )
+        }
+    };
+
+    let kek = derive_kek(&shared, &ephemeral, &recipient_bytes)?;
+    let content_key = open(
+        &kek,
+        &decode(&recipient.nonce, "nonce")?,
+        &decode(&recipient.wrapped_key, "wrapped_key")?,
+    )?;
+
+    content_key
+        .try_into()
+        .map_err(|_| CryptoError::InvalidEnvelope("invalid content key".to_string()))
+}
+
+/**
+ * x coordinate of the ECDH point
+ */
+fn secp256k1_shared(
+    point: &libsecp256k1::PublicKey,
+    secret: &libsecp256k1::SecretKey,
+) -> Result<Vec<u8>, CryptoError> {
+    let mut shared = *point;
+    shared
+        .tweak_mul_assign(secret)
+        .map_err(|_| CryptoError::Encryption("invalid shared point".to_string()))?;
+
+    Ok(shared.serialize_compressed()[1..].to_vec())
+}
+
+fn random_secp256k1_secret() -> Result<libsecp256k1::SecretKey, CryptoError> {
+    loop {
+        if let Ok(secret) = libsecp256k1::SecretKey::parse(&random::<KEY_LENGTH>()?) {
+            return Ok(secret);
+        }
+    }
+}
+
+/**
+ * HKDF-SHA256 over the shared secret, salted with both public keys so a
+ * wrapped key is bound to its ephemeral key and recipient
+ */
+fn derive_kek(
+    shared: &[u8],
+    ephemeral: &[u8],
+    recipient: &[u8],
+) -> Result<[u8; KEY_LENGTH], CryptoError> {
+    let salt = [ephemeral, recipient].concat();
+    let mut kek = [0u8; KEY_LENGTH];
+
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(KDF_INFO, &mut kek)
+        .map_err(|e| CryptoError::Encryption(e.to_string()))?;
+
+    Ok(kek)
+}
+
+fn seal(key: &[u8; KEY_LENGTH], nonce: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(Nonce::from_slice(nonce), plaintext)
+        .map_err(|e| CryptoError::Encryption(e.to_string()))
+}
+
+fn open(key: &[u8; KEY_LENGTH], nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if nonce.len() != NONCE_LENGTH {
+        return Err(CryptoError::InvalidEnvelope("invalid nonce".to_string()));
+    }
+
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| CryptoError::InvalidEnvelope("unable to decrypt".to_string()))
+}
+
+fn decode(value: &str, name: &str) -> Result<Vec<u8>, CryptoError> {
+    STANDARD
+        .decode(value)
+        .map_err(|_| CryptoError::InvalidEnvelope(format!("{} is not base64", name)))
+}
+
+fn random<const N: usize>() -> Result<[u8; N], CryptoError> {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes).map_err(|e| CryptoError::Encryption(e.to_string()))?;
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ed25519, secp256k1};
+
+    const PLAINTEXT: &str = r#"{"name":"private"}"#;
+
+    /// secp256k1 keypairs carry an address, envelopes need the public key
+    fn secp256k1_recipient(sk: &str) -> String {
+        let secret = libsecp256k1::SecretKey::parse_slice(&hex::decode(&sk[2..]).unwrap()).unwrap();
+        let point = libsecp256k1::PublicKey::from_secret_key(&secret);
+        format!("0x{}", hex::encode(point.serialize_compressed()))
+    }
+
+    fn tamper(envelope: &str, edit: impl FnOnce(&mut Envelope)) -> String {
+        let mut envelope = parse_envelope(envelope).unwrap();
+        edit(&mut envelope);
+        serde_json::to_string(&envelope).unwrap()
+    }
+
+    fn flip_first_byte(value: &str) -> String {
+        let mut bytes = STANDARD.decode(value).unwrap();
+        bytes[0] ^= 0x01;
+        STANDARD.encode(bytes)
+    }
+
+    #[test]
+    fn round_trip_for_every_recipient() {
+        let alice = ed25519::generate_keypair();
+        let bob = secp256k1::generate_keypair().unwrap();
+
+        let envelope =
+            encrypt(&[alice.pk.clone(), secp256k1_recipient(&bob.sk)], PLAINTEXT).unwrap();
+
+        assert_eq!(
+            decrypt(&envelope, &alice.sk, ENC_ED25519).unwrap(),
+            PLAINTEXT
+        );
+        assert_eq!(
+            decrypt(&envelope, &bob.sk, ENC_SECP256K1).unwrap(),
+            PLAINTEXT
+        );
+    }
+
+    #[test]
+    fn rejects_other_keys() {
+        let alice = ed25519::generate_keypair();
+        let eve = ed25519::generate_keypair();
+
+        let envelope = encrypt(&[alice.pk], PLAINTEXT).unwrap();
+
+        assert!(decrypt(&envelope, &eve.sk, ENC_ED25519).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_ciphertext() {
+        let alice = ed25519::generate_keypair();
+        let envelope = encrypt(&[alice.pk], PLAINTEXT).unwrap();
+
+        let tampered = tamper(&envelope, |e| e.ciphertext = flip_first_byte(&e.ciphertext));
+        assert!(decrypt(&tampered, &alice.sk, ENC_ED25519).is_err());
+
+        let tampered = tamper(&envelope, |e| e.nonce = flip_first_byte(&e.nonce));
+        assert!(decrypt(&tampered, &alice.sk, ENC_ED25519).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_wrapped_key() {
+        let bob = secp256k1::generate_keypair().unwrap();
+        let envelope = encrypt(&[secp256k1_recipient(&bob.sk)], PLAINTEXT).unwrap();
+
+        let tampered = tamper(&envelope, |e| {
+            e.recipients[0].wrapped_key = flip_first_byte(&e.recipients[0].wrapped_key)
+        });
+        assert!(decrypt(&tampered, &bob.sk, ENC_SECP256K1).is_err());
+    }
+
+    #[test]
+    fn rewrap_replaces_recipients() {
+        let alice = ed25519::generate_keypair();
+        let bob = ed25519::generate_keypair();
+
+        let envelope = encrypt(&[alice.pk], PLAINTEXT).unwrap();
+        let rewrapped = rewrap(&envelope, &alice.sk, ENC_ED25519, &[bob.pk]).unwrap();
+
+        assert_eq!(
+            decrypt(&rewrapped, &bob.sk, ENC_ED25519).unwrap(),
+            PLAINTEXT
+        );
+        assert!(decrypt(&rewrapped, &alice.sk, ENC_ED25519).is_err());
+    }
+}
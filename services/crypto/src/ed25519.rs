@@ -1,24 +1,116 @@
 #![allow(improper_ctypes)]
 
-use base58::FromBase58;
-use ed25519_compact::{PublicKey, Signature};
+use base58::{FromBase58, ToBase58};
+use ed25519_compact::{KeyPair as Ed25519KeyPair, Noise, PublicKey, SecretKey, Seed, Signature};
+use types::KeyPair;
 
-pub fn verify(public_key: String, signature: String, message: String) -> bool {
-    let p_key_decoded = public_key.clone().from_base58().unwrap();
-    let sign_decoded = signature.clone().from_base58().unwrap();
+use crate::error::CryptoError;
+
+pub const ENC: &str = "ed25519";
+
+pub fn generate_keypair() -> KeyPair {
+    to_keypair(Ed25519KeyPair::generate())
+}
+
+pub fn keypair_from_seed(seed: [u8; 32]) -> KeyPair {
+    to_keypair(Ed25519KeyPair::from_seed(Seed::new(seed)))
+}
+
+fn to_keypair(kp: Ed25519KeyPair) -> KeyPair {
+    KeyPair {
+        pk: kp.pk.as_ref().to_base58(),
+        sk: kp.sk.as_ref().to_base58(),
+        enc: ENC.to_string(),
+        ..Default::default()
+    }
+}
+
+/**
+ * Signs with a base58 secret key, either the 64 byte secret key or its 32
+ * byte seed. The signature is base58 encoded, as `verify` expects.
+ */
+pub fn sign(message: &str, private_key: &str) -> Result<String, CryptoError> {
+    let invalid = || CryptoError::InvalidPrivateKey("expected base58 32 or 64 bytes".to_string());
+    let decoded = private_key.from_base58().map_err(|_| invalid())?;
+
+    let sk = match decoded.len() {
+        32 => Ed25519KeyPair::from_seed(Seed::from_slice(&decoded).map_err(|_| invalid())?).sk,
+        64 => SecretKey::from_slice(&decoded).map_err(|_| invalid())?,
+        _ => return Err(invalid()),
+    };
+
+    let signature = sk.sign(message, Some(Noise::default()));
+
+    Ok(signature.as_ref().to_base58())
+}
+
+pub fn verify(public_key: &str, signature: &str, message: &str) -> Result<(), CryptoError> {
+    let p_key_decoded = public_key
+        .from_base58()
+        .map_err(|_| CryptoError::InvalidPublicKey(public_key.to_string()))?;
+    let sign_decoded = signature
+        .from_base58()
+        .map_err(|_| CryptoError::InvalidSignature("not base58 encoded".to_string()))?;
 
     let pk: [u8; 32] = p_key_decoded
         .try_into()
-        .expect("Error: public_key with incorrect length");
+        .map_err(|_| CryptoError::InvalidPublicKey(public_key.to_string()))?;
 
     let sign: [u8; 64] = sign_decoded
         .try_into()
-        .expect("Error: Sign with incorrect length");
+        .map_err(|_| CryptoError::InvalidSignature("expected 64 bytes".to_string()))?;
+
+    PublicKey::new(pk)
+        .verify(message, &Signature::new(sign))
+        .map_err(|e| CryptoError::InvalidSignature(e.to_string()))
+}
+
+/**
+ * Verifies `(public_key, signature, message)` triples at once. Returns false
+ * when any of them is malformed or invalid, without telling which one.
+ *
+ * `verify` accepts when `sB - hA - R` has small order, which is the same
+ * cofactored equation the batch checks, so both agree on every item the
+ * batch takes. Items where the two libraries could still disagree, a small
+ * order public key (rejected by `verify`) or a non canonical point encoding,
+ * make the batch return false so the caller falls back to `verify`.
+ */
+pub fn verify_batch(items: &[(&str, &str, &str)]) -> bool {
+    let mut keys = Vec::with_capacity(items.len());
+    let mut signatures = Vec::with_capacity(items.len());
+    let mut messages = Vec::with_capacity(items.len());
 
-    let p_key = PublicKey::new(pk);
+    for (public_key, signature, message) in items {
+        let key = public_key
+            .from_base58()
+            .ok()
+            .and_then(|pk| <[u8; 32]>::try_from(pk).ok())
+            .and_then(|pk| canonical_point(&pk))
+            .filter(|key| !key.is_weak());
+        let sign = signature
+            .from_base58()
+            .ok()
+            .and_then(|s| ed25519_dalek::Signature::from_slice(&s).ok())
+            .filter(|s| canonical_point(s.r_bytes()).is_some());
 
-    match p_key.verify(message, &Signature::new(sign)) {
-        Ok(_) => true,
-        Err(_) => false,
+        match (key, sign) {
+            (Some(key), Some(sign)) => {
+                keys.push(key);
+                signatures.push(sign);
+                messages.push(message.as_bytes());
+            }
+            _ => return false,
+        }
     }
+
+    ed25519_dalek::verify_batch(&messages, &signatures, &keys).is_ok()
+}
+
+/**
+ * The point behind `bytes`, when they are its canonical encoding
+ */
+fn canonical_point(bytes: &[u8; 32]) -> Option<ed25519_dalek::VerifyingKey> {
+    ed25519_dalek::VerifyingKey::from_bytes(bytes)
+        .ok()
+        .filter(|point| point.to_edwards().compress().as_bytes() == bytes)
 }
@@ -0,0 +1,31 @@
+use thiserror::Error as ThisError;
+
+#[derive(ThisError, Debug)]
+pub enum CryptoError {
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+    #[error("Invalid public key: {0}")]
+    InvalidPublicKey(String),
+    #[error("Invalid recovery id: {0}")]
+    InvalidRecoveryId(u64),
+    #[error("Signature is not canonical: s value is high")]
+    HighS(),
+    #[error("Signer mismatch: recovered {0}")]
+    SignerMismatch(String),
+    #[error("Unknown key type: {0}")]
+    UnknownKeyType(String),
+    #[error("Invalid private key: {0}")]
+    InvalidPrivateKey(String),
+    #[error("Not supported encryption: {0}")]
+    NotSupportedEncryptionType(String),
+    #[error("Unable to generate key: {0}")]
+    KeyGeneration(String),
+    #[error("Invalid mnemonic: {0}")]
+    InvalidMnemonic(String),
+    #[error("Invalid derivation path: {0}")]
+    InvalidDerivationPath(String),
+    #[error("Invalid envelope: {0}")]
+    InvalidEnvelope(String),
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+}
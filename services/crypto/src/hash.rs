@@ -0,0 +1,26 @@
+use sha2::{Digest, Sha256};
+
+use crate::secp256k1::{eth_message, keccak256_hash};
+
+/**
+ * Digests are returned as lowercase hex without a 0x prefix
+ */
+pub fn sha256(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+pub fn keccak256(data: &[u8]) -> String {
+    hex::encode(keccak256_hash(data))
+}
+
+pub fn blake3(data: &[u8]) -> String {
+    blake3::hash(data).to_hex().to_string()
+}
+
+/**
+ * Keccak-256 of the EIP-191 prefixed message, the digest `personal_sign`
+ * signs
+ */
+pub fn eth_message_hash(message: &str) -> String {
+    hex::encode(eth_message(message.to_string()))
+}
@@ -0,0 +1,294 @@
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac};
+use libsecp256k1::{PublicKey, SecretKey};
+use sha2::Sha512;
+use types::KeyPair;
+
+use crate::ed25519;
+use crate::error::CryptoError;
+use crate::secp256k1;
+
+/// Paths used when none is given, the first Ethereum and Solana accounts
+pub const DEFAULT_PATH_SECP256K1: &str = "m/44'/60'/0'/0/0";
+pub const DEFAULT_PATH_ED25519: &str = "m/44'/501'/0'/0'";
+
+const DEFAULT_WORDS: u32 = 24;
+const HARDENED: u32 = 0x8000_0000;
+
+const BIP32_SEED_KEY: &[u8] = b"Bitcoin seed";
+const SLIP10_ED25519_SEED_KEY: &[u8] = b"ed25519 seed";
+
+/**
+ * A private key and its chain code
+ */
+struct ExtendedKey {
+    key: [u8; 32],
+    chain_code: [u8; 32],
+}
+
+/**
+ * English BIP-39 mnemonic of 12, 15, 18, 21 or 24 words, 24 when `words`
+ * is 0
+ */
+pub fn generate_mnemonic(words: u32) -> Result<String, CryptoError> {
+    let words = if words == 0 { DEFAULT_WORDS } else { words };
+    if !(12..=24).contains(&words) || words % 3 != 0 {
+        return Err(CryptoError::InvalidMnemonic(format!(
+            "{} words, expected 12, 15, 18, 21 or 24",
+            words
+        )));
+    }
+
+    let mut entropy = vec![0u8; (words / 3 * 4) as usize];
+    getrandom::getrandom(&mut entropy).map_err(|e| CryptoError::KeyGeneration(e.to_string()))?;
+
+    Mnemonic::from_entropy(&entropy)
+        .map(|mnemonic| mnemonic.to_string())
+        .map_err(|e| CryptoError::InvalidMnemonic(e.to_string()))
+}
+
+/**
+ * Derives the key of `path` from a BIP-39 mnemonic, with BIP-32 for
+ * secp256k1 and SLIP-0010 for ed25519, the default as for
+ * `generate_keypair`. SLIP-0010 ed25519 paths are hardened only.
+ */
+pub fn derive_keypair(
+    mnemonic: &str,
+    passphrase: &str,
+    path: &str,
+    enc: &str,
+) -> Result<KeyPair, CryptoError> {
+    let seed = Mnemonic::parse(mnemonic)
+        .map_err(|e| CryptoError::InvalidMnemonic(e.to_string()))?
+        .to_seed(passphrase);
+
+    let (keypair, path) = if enc.is_empty() || enc == ed25519::ENC {
+        let path = or_default(path, DEFAULT_PATH_ED25519);
+        (derive_ed25519(&seed, &parse_path(path)?)?, path)
+    } else if enc == secp256k1::ENC {
+        let path = or_default(path, DEFAULT_PATH_SECP256K1);
+        (derive_secp256k1(&seed, &parse_path(path)?)?, path)
+    } else {
+        return Err(CryptoError::NotSupportedEncryptionType(enc.to_string()));
+    };
+
+    Ok(KeyPair {
+        path: path.to_string(),
+        ..keypair
+    })
+}
+
+fn or_default<'a>(path: &'a str, default: &'a str) -> &'a str {
+    if path.is_empty() {
+        default
+    } else {
+        path
+    }
+}
+
+fn derive_secp256k1(seed: &[u8], path: &[u32]) -> Result<KeyPair, CryptoError> {
+    let invalid = || CryptoError::KeyGeneration("derived key is out of range".to_string());
+
+    let mut extended = hmac_sha512(BIP32_SEED_KEY, &[seed]);
+    let mut secret = SecretKey::parse(&extended.key).map_err(|_| invalid())?;
+
+    for index in path {
+        let index_bytes = index.to_be_bytes();
+        extended = if index & HARDENED != 0 {
+            hmac_sha512(&extended.chain_code, &[&[0], &extended.key, &index_bytes])
+        } else {
+            let public_key = PublicKey::from_secret_key(&secret).serialize_compressed();
+            hmac_sha512(&extended.chain_code, &[&public_key, &index_bytes])
+        };
+
+        let mut child = SecretKey::parse(&extended.key).map_err(|_| invalid())?;
+        child.tweak_add_assign(&secret).map_err(|_| invalid())?;
+        extended.key = child.serialize();
+        secret = child;
+    }
+
+    Ok(secp256k1::keypair_from_secret(&secret))
+}
+
+fn derive_ed25519(seed: &[u8], path: &[u32]) -> Result<KeyPair, CryptoError> {
+    let mut extended = hmac_sha512(SLIP10_ED25519_SEED_KEY, &[seed]);
+
+    for index in path {
+        if index & HARDENED == 0 {
+            return Err(CryptoError::InvalidDerivationPath(format!(
+                "ed25519 index {} must be hardened",
+                index
+            )));
+        }
+
+        extended = hmac_sha512(
+            &extended.chain_code,
+            &[&[0], &extended.key, &index.to_be_bytes()],
+        );
+    }
+
+    Ok(ed25519::keypair_from_seed(extended.key))
+}
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> ExtendedKey {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts any key length");
+    data.iter().for_each(|chunk| mac.update(chunk));
+    let output = mac.finalize().into_bytes();
+
+    let mut extended = ExtendedKey {
+        key: [0u8; 32],
+        chain_code: [0u8; 32],
+    };
+    extended.key.copy_from_slice(&output[..32]);
+    extended.chain_code.copy_from_slice(&output[32..]);
+
+    extended
+}
+
+/**
+ * `m/44'/60'/0'/0/0`, hardened indexes are marked with `'` or `h`
+ */
+fn parse_path(path: &str) -> Result<Vec<u32>, CryptoError> {
+    let invalid = || CryptoError::InvalidDerivationPath(path.to_string());
+
+    let mut segments = path.trim().split('/');
+    if segments.next() != Some("m") {
+        return Err(invalid());
+    }
+
+    segments
+        .map(|segment| {
+            let (number, hardened) = match segment.strip_suffix(['\'', 'h', 'H']) {
+                Some(number) => (number, HARDENED),
+                None => (segment, 0),
+            };
+
+            number
+                .parse::<u32>()
+                .ok()
+                .filter(|index| index & HARDENED == 0)
+                .map(|index| index | hardened)
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base58::FromBase58;
+
+    // Test vector 1 of BIP-32 and SLIP-0010
+    const SEED: &str = "000102030405060708090a0b0c0d0e0f";
+
+    const BIP32_VECTORS: [(&str, &str); 6] = [
+        (
+            "m",
+            "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35",
+        ),
+        (
+            "m/0H",
+            "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea",
+        ),
+        (
+            "m/0H/1",
+            "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368",
+        ),
+        (
+            "m/0H/1/2H",
+            "cbce0d719ecf7431d88e6a89fa1483e02e35092af60c042b1df2ff59fa424dca",
+        ),
+        (
+            "m/0H/1/2H/2",
+            "0f479245fb19a38a1954c5c7c0ebab2f9bdfd96a17563ef28a6a4b1a2a764ef4",
+        ),
+        (
+            "m/0H/1/2H/2/1000000000",
+            "471b76e389e528d6de6d816857e012c5455051cad6660850e58372a6c3e6e7c8",
+        ),
+    ];
+
+    const SLIP10_ED25519_VECTORS: [(&str, &str); 6] = [
+        (
+            "m",
+            "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7",
+        ),
+        (
+            "m/0H",
+            "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3",
+        ),
+        (
+            "m/0H/1H",
+            "b1d0bad404bf35da785a64ca1ac54b2617211d2777696fbffaf208f746ae84f2",
+        ),
+        (
+            "m/0H/1H/2H",
+            "92a5b23c0b8a99e37d07df3fb9966917f5d06e02ddbd909c7e184371463e9fc9",
+        ),
+        (
+            "m/0H/1H/2H/2H",
+            "30d1dc7e5fc04c31219ab25a27ae00b50f6fd66622f6e9c913253d6511d1e662",
+        ),
+        (
+            "m/0H/1H/2H/2H/1000000000H",
+            "8f94d394a8e8fd6b1bc2f3f49f5c47e385281d5c17e65324b0f62483e37e8793",
+        ),
+    ];
+
+    // BIP-39 reference vectors, passphrase "TREZOR"
+    const BIP39_VECTORS: [(&str, &str); 2] = [
+        (
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+        ),
+        (
+            "legal winner thank year wave sausage worth useful legal winner thank yellow",
+            "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+        ),
+    ];
+
+    #[test]
+    fn bip32_vectors() {
+        let seed = hex::decode(SEED).unwrap();
+
+        for (path, private_key) in BIP32_VECTORS {
+            let keypair = derive_secp256k1(&seed, &parse_path(path).unwrap()).unwrap();
+            assert_eq!(keypair.sk, format!("0x{}", private_key), "{}", path);
+        }
+    }
+
+    #[test]
+    fn slip10_ed25519_vectors() {
+        let seed = hex::decode(SEED).unwrap();
+
+        for (path, private_key) in SLIP10_ED25519_VECTORS {
+            let keypair = derive_ed25519(&seed, &parse_path(path).unwrap()).unwrap();
+            let secret = keypair.sk.from_base58().unwrap();
+            assert_eq!(hex::encode(&secret[..32]), private_key, "{}", path);
+        }
+    }
+
+    #[test]
+    fn bip39_vectors() {
+        for (mnemonic, seed) in BIP39_VECTORS {
+            let derived = Mnemonic::parse(mnemonic).unwrap().to_seed("TREZOR");
+            assert_eq!(hex::encode(derived), seed);
+        }
+    }
+
+    #[test]
+    fn default_accounts() {
+        let mnemonic = "test test test test test test test test test test test junk";
+
+        let keypair = derive_keypair(mnemonic, "", "", secp256k1::ENC).unwrap();
+        assert_eq!(keypair.path, DEFAULT_PATH_SECP256K1);
+        assert_eq!(
+            keypair.pk.to_lowercase(),
+            "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266"
+        );
+
+        let keypair = derive_keypair(mnemonic, "", "", "").unwrap();
+        assert_eq!(keypair.enc, ed25519::ENC);
+        assert_eq!(keypair.path, DEFAULT_PATH_ED25519);
+    }
+}
@@ -0,0 +1,572 @@
+use base58::FromBase58;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use p256::ecdsa::signature::Verifier;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use types::JwsResult;
+
+use crate::key::{self, ENC_ED25519, ENC_P256, ENC_SECP256K1};
+use crate::webauthn::decode_multibase as decode_p256_multibase;
+
+pub const ALG_ES256K: &str = "ES256K";
+pub const ALG_EDDSA: &str = "EdDSA";
+pub const ALG_ES256: &str = "ES256";
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(default)]
+    kid: String,
+    crit: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    crv: String,
+    #[serde(default)]
+    x: String,
+    #[serde(default)]
+    y: String,
+    #[serde(default)]
+    kid: String,
+    #[serde(default)]
+    alg: String,
+    #[serde(default, rename = "use")]
+    usage: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+/**
+ * A verification key, each curve is used with a single JWS algorithm
+ */
+enum PublicKey {
+    Secp256k1(libsecp256k1::PublicKey),
+    Ed25519(ed25519_dalek::VerifyingKey),
+    P256(p256::ecdsa::VerifyingKey),
+}
+
+impl PublicKey {
+    fn alg(&self) -> &'static str {
+        match self {
+            PublicKey::Secp256k1(_) => ALG_ES256K,
+            PublicKey::Ed25519(_) => ALG_EDDSA,
+            PublicKey::P256(_) => ALG_ES256,
+        }
+    }
+
+    fn verify(&self, signing_input: &[u8], signature: &[u8]) -> Result<(), String> {
+        let invalid = || "invalid signature".to_string();
+
+        match self {
+            PublicKey::Secp256k1(key) => {
+                let mut signature = libsecp256k1::Signature::parse_standard_slice(signature)
+                    .map_err(|_| invalid())?;
+                // JOSE does not require low-s, libsecp256k1 only accepts it
+                signature.normalize_s();
+                let message = libsecp256k1::Message::parse(&Sha256::digest(signing_input).into());
+
+                if libsecp256k1::verify(&message, &signature, key) {
+                    Ok(())
+                } else {
+                    Err(invalid())
+                }
+            }
+            PublicKey::Ed25519(key) => {
+                let signature =
+                    ed25519_dalek::Signature::from_slice(signature).map_err(|_| invalid())?;
+                key.verify_strict(signing_input, &signature)
+                    .map_err(|_| invalid())
+            }
+            PublicKey::P256(key) => {
+                let signature =
+                    p256::ecdsa::Signature::from_slice(signature).map_err(|_| invalid())?;
+                key.verify(signing_input, &signature).map_err(|_| invalid())
+            }
+        }
+    }
+}
+
+/**
+ * Verifies a compact JWS (ES256K, EdDSA or ES256) and the time claims of its
+ * payload. `keys` is a JWKS, a single JWK or a public key in any form
+ * `get_public_key_type` accepts. `issuer` and `audience` are only checked
+ * when not empty.
+ */
+pub fn verify(token: &str, keys: &str, issuer: &str, audience: &str) -> JwsResult {
+    let mut result = JwsResult::default();
+
+    match check(&mut result, token, keys, issuer, audience) {
+        Ok(()) => result.success = true,
+        Err(e) => result.err_msg = e,
+    }
+
+    result
+}
+
+fn check(
+    result: &mut JwsResult,
+    token: &str,
+    keys: &str,
+    issuer: &str,
+    audience: &str,
+) -> Result<(), String> {
+    let mut parts = token.trim().split('.');
+    let (encoded_header, encoded_payload, encoded_signature) =
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(header), Some(payload), Some(signature), None) => (header, payload, signature),
+            _ => return Err("expected a compact JWS of three parts".into()),
+        };
+
+    let header: Header = serde_json::from_slice(&decode(encoded_header, "header")?)
+        .map_err(|e| format!("invalid header: {}", e))?;
+    result.alg = header.alg.clone();
+    result.kid = header.kid.clone();
+
+    let payload = decode(encoded_payload, "payload")?;
+    let claims: Map<String, Value> =
+        serde_json::from_slice(&payload).map_err(|e| format!("invalid claims: {}", e))?;
+    result.claims = String::from_utf8_lossy(&payload).to_string();
+
+    if header.crit.is_some() {
+        return Err("crit header parameters are not supported".into());
+    }
+
+    let key = select_key(keys, &header)?;
+    if key.alg() != header.alg {
+        return Err(format!(
+            "alg {} cannot be used with a {} key",
+            header.alg,
+            key.alg()
+        ));
+    }
+
+    let signature = decode(encoded_signature, "signature")?;
+    let signing_input = format!("{}.{}", encoded_header, encoded_payload);
+    key.verify(signing_input.as_bytes(), &signature)?;
+
+    check_claims(&claims, issuer, audience)
+}
+
+fn check_claims(claims: &Map<String, Value>, issuer: &str, audience: &str) -> Result<(), String> {
+    let now = Utc::now().timestamp() as f64;
+
+    if let Some(exp) = claims.get("exp") {
+        if now >= numeric_date(exp, "exp")? {
+            return Err("token has expired".into());
+        }
+    }
+
+    if let Some(nbf) = claims.get("nbf") {
+        if now < numeric_date(nbf, "nbf")? {
+            return Err("token is not yet valid".into());
+        }
+    }
+
+    if !issuer.is_empty() && claims.get("iss").and_then(Value::as_str) != Some(issuer) {
+        return Err(format!("issuer is not {}", issuer));
+    }
+
+    if !audience.is_empty() {
+        let allowed = match claims.get("aud") {
+            Some(Value::String(aud)) => aud == audience,
+            Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+            _ => false,
+        };
+
+        if !allowed {
+            return Err(format!("audience does not include {}", audience));
+        }
+    }
+
+    Ok(())
+}
+
+fn numeric_date(value: &Value, name: &str) -> Result<f64, String> {
+    value
+        .as_f64()
+        .ok_or_else(|| format!("{} is not a numeric date", name))
+}
+
+/**
+ * With a JWKS the key is picked by `kid`, or the first signing key usable
+ * with `alg` when the token has no `kid`
+ */
+fn select_key(keys: &str, header: &Header) -> Result<PublicKey, String> {
+    let keys = keys.trim();
+
+    if !keys.starts_with('{') {
+        return parse_public_key(keys);
+    }
+
+    if let Ok(jwks) = serde_json::from_str::<Jwks>(keys) {
+        return jwks
+            .keys
+            .iter()
+            .filter(|jwk| header.kid.is_empty() || jwk.kid == header.kid)
+            .filter(|jwk| jwk.alg.is_empty() || jwk.alg == header.alg)
+            .filter(|jwk| jwk.usage.is_empty() || jwk.usage == "sig")
+            .filter_map(|jwk| parse_jwk(jwk).ok())
+            .find(|key| key.alg() == header.alg)
+            .ok_or_else(|| format!("no {} key with kid \"{}\"", header.alg, header.kid));
+    }
+
+    let jwk: Jwk = serde_json::from_str(keys).map_err(|e| format!("invalid jwk: {}", e))?;
+    parse_jwk(&jwk)
+}
+
+fn parse_jwk(jwk: &Jwk) -> Result<PublicKey, String> {
+    let invalid = || format!("invalid {} {} jwk", jwk.kty, jwk.crv);
+
+    match (jwk.kty.as_str(), jwk.crv.as_str()) {
+        ("EC", "secp256k1") | ("EC", "P-256") => {
+            let x = decode(&jwk.x, "x")?;
+            let y = decode(&jwk.y, "y")?;
+            if x.len() != 32 || y.len() != 32 {
+                return Err(invalid());
+            }
+            let sec1 = [&[0x04], x.as_slice(), y.as_slice()].concat();
+
+            if jwk.crv == "secp256k1" {
+                libsecp256k1::PublicKey::parse_slice(&sec1, None)
+                    .map(PublicKey::Secp256k1)
+                    .map_err(|_| invalid())
+            } else {
+                p256::ecdsa::VerifyingKey::from_sec1_bytes(&sec1)
+                    .map(PublicKey::P256)
+                    .map_err(|_| invalid())
+            }
+        }
+        ("OKP", "Ed25519") => {
+            let x: [u8; 32] = decode(&jwk.x, "x")?.try_into().map_err(|_| invalid())?;
+
+            ed25519_dalek::VerifyingKey::from_bytes(&x)
+                .map(PublicKey::Ed25519)
+                .map_err(|_| invalid())
+        }
+        _ => Err(format!("unsupported jwk {} {}", jwk.kty, jwk.crv)),
+    }
+}
+
+/**
+ * Addresses carry no public key and cannot verify a JWS
+ */
+fn parse_public_key(public_key: &str) -> Result<PublicKey, String> {
+    let invalid = || format!("cannot verify a JWS with {}", public_key);
+    let parsed = key::parse(public_key).map_err(|e| e.to_string())?;
+
+    match parsed.enc {
+        ENC_SECP256K1 => {
+            let bytes =
+                hex::decode(parsed.public_key.trim_start_matches("0x")).map_err(|_| invalid())?;
+            libsecp256k1::PublicKey::parse_slice(&bytes, None)
+                .map(PublicKey::Secp256k1)
+                .map_err(|_| invalid())
+        }
+        ENC_ED25519 => {
+            let bytes: [u8; 32] = parsed
+                .public_key
+                .from_base58()
+                .ok()
+                .and_then(|b| b.try_into().ok())
+                .ok_or_else(invalid)?;
+            ed25519_dalek::VerifyingKey::from_bytes(&bytes)
+                .map(PublicKey::Ed25519)
+                .map_err(|_| invalid())
+        }
+        ENC_P256 => {
+            let bytes = decode_p256_multibase(&parsed.public_key).ok_or_else(invalid)?;
+            p256::ecdsa::VerifyingKey::from_sec1_bytes(&bytes)
+                .map(PublicKey::P256)
+                .map_err(|_| invalid())
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn decode(value: &str, name: &str) -> Result<Vec<u8>, String> {
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .map_err(|_| format!("{} is not base64url", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::Signer as _;
+    use serde_json::json;
+
+    const ISSUER: &str = "https://issuer.example";
+    const AUDIENCE: &str = "node";
+
+    enum SigningKey {
+        Secp256k1(libsecp256k1::SecretKey),
+        Ed25519(ed25519_dalek::SigningKey),
+        P256(p256::ecdsa::SigningKey),
+    }
+
+    impl SigningKey {
+        fn secp256k1(seed: u8) -> Self {
+            SigningKey::Secp256k1(libsecp256k1::SecretKey::parse(&[seed; 32]).unwrap())
+        }
+
+        fn ed25519(seed: u8) -> Self {
+            SigningKey::Ed25519(ed25519_dalek::SigningKey::from_bytes(&[seed; 32]))
+        }
+
+        fn p256(seed: u8) -> Self {
+            SigningKey::P256(p256::ecdsa::SigningKey::from_slice(&[seed; 32]).unwrap())
+        }
+
+        fn sign(&self, signing_input: &[u8]) -> Vec<u8> {
+            match self {
+                SigningKey::Secp256k1(key) => {
+                    let message =
+                        libsecp256k1::Message::parse(&Sha256::digest(signing_input).into());
+                    libsecp256k1::sign(&message, key).0.serialize().to_vec()
+                }
+                SigningKey::Ed25519(key) => key.sign(signing_input).to_bytes().to_vec(),
+                SigningKey::P256(key) => {
+                    let signature: p256::ecdsa::Signature = key.sign(signing_input);
+                    signature.to_bytes().to_vec()
+                }
+            }
+        }
+
+        fn jwk(&self, kid: &str) -> Value {
+            match self {
+                SigningKey::Secp256k1(key) => {
+                    let point = libsecp256k1::PublicKey::from_secret_key(key).serialize();
+                    json!({
+                        "kty": "EC",
+                        "crv": "secp256k1",
+                        "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+                        "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+                        "kid": kid,
+                    })
+                }
+                SigningKey::Ed25519(key) => json!({
+                    "kty": "OKP",
+                    "crv": "Ed25519",
+                    "x": URL_SAFE_NO_PAD.encode(key.verifying_key().as_bytes()),
+                    "kid": kid,
+                }),
+                SigningKey::P256(key) => {
+                    let point = key.verifying_key().to_encoded_point(false);
+                    json!({
+                        "kty": "EC",
+                        "crv": "P-256",
+                        "x": URL_SAFE_NO_PAD.encode(point.x().unwrap()),
+                        "y": URL_SAFE_NO_PAD.encode(point.y().unwrap()),
+                        "kid": kid,
+                    })
+                }
+            }
+        }
+    }
+
+    fn token(key: &SigningKey, header: Value, claims: Value) -> String {
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let signature = key.sign(signing_input.as_bytes());
+
+        format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    fn claims() -> Value {
+        let now = Utc::now().timestamp();
+
+        json!({
+            "iss": ISSUER,
+            "aud": [AUDIENCE, "other"],
+            "sub": "alice",
+            "nbf": now - 60,
+            "exp": now + 600,
+        })
+    }
+
+    fn jwks(keys: &[(&SigningKey, &str)]) -> String {
+        let keys: Vec<Value> = keys.iter().map(|(key, kid)| key.jwk(kid)).collect();
+
+        json!({ "keys": keys }).to_string()
+    }
+
+    #[test]
+    fn verifies_es256k_eddsa_and_es256() {
+        let keys = [
+            (SigningKey::secp256k1(1), ALG_ES256K),
+            (SigningKey::ed25519(2), ALG_EDDSA),
+            (SigningKey::p256(3), ALG_ES256),
+        ];
+
+        for (key, alg) in keys.iter() {
+            let jws = token(key, json!({ "alg": alg, "kid": "k1" }), claims());
+            let result = verify(&jws, &key.jwk("k1").to_string(), ISSUER, AUDIENCE);
+
+            assert!(result.success, "{}: {}", alg, result.err_msg);
+            assert_eq!(result.alg, *alg);
+            assert_eq!(result.kid, "k1");
+            assert!(result.claims.contains("alice"));
+        }
+    }
+
+    #[test]
+    fn verifies_with_public_key_forms() {
+        let secp256k1 = libsecp256k1::SecretKey::parse(&[1; 32]).unwrap();
+        let public_key = libsecp256k1::PublicKey::from_secret_key(&secp256k1);
+        let jws = token(
+            &SigningKey::Secp256k1(secp256k1),
+            json!({ "alg": ALG_ES256K }),
+            claims(),
+        );
+        let hex_key = format!("0x{}", hex::encode(public_key.serialize_compressed()));
+        assert!(verify(&jws, &hex_key, "", "").success);
+
+        let ed25519 = ed25519_dalek::SigningKey::from_bytes(&[2; 32]);
+        let base58_key = base58::ToBase58::to_base58(&ed25519.verifying_key().to_bytes()[..]);
+        let jws = token(
+            &SigningKey::Ed25519(ed25519),
+            json!({ "alg": ALG_EDDSA }),
+            claims(),
+        );
+        assert!(verify(&jws, &base58_key, "", "").success);
+    }
+
+    #[test]
+    fn selects_the_jwks_key_by_kid() {
+        let first = SigningKey::ed25519(4);
+        let second = SigningKey::ed25519(5);
+        let keys = jwks(&[(&first, "first"), (&second, "second")]);
+
+        let jws = token(
+            &second,
+            json!({ "alg": ALG_EDDSA, "kid": "second" }),
+            claims(),
+        );
+        assert!(verify(&jws, &keys, ISSUER, AUDIENCE).success);
+
+        let jws = token(
+            &second,
+            json!({ "alg": ALG_EDDSA, "kid": "first" }),
+            claims(),
+        );
+        assert!(!verify(&jws, &keys, ISSUER, AUDIENCE).success);
+
+        let jws = token(
+            &second,
+            json!({ "alg": ALG_EDDSA, "kid": "third" }),
+            claims(),
+        );
+        let result = verify(&jws, &keys, ISSUER, AUDIENCE);
+        assert!(!result.success);
+        assert!(
+            result.err_msg.contains("no EdDSA key"),
+            "{}",
+            result.err_msg
+        );
+    }
+
+    #[test]
+    fn rejects_alg_and_key_mismatch() {
+        let key = SigningKey::ed25519(6);
+        let jws = token(&key, json!({ "alg": ALG_ES256 }), claims());
+        let result = verify(&jws, &key.jwk("").to_string(), ISSUER, AUDIENCE);
+
+        assert!(!result.success);
+        assert!(
+            result.err_msg.contains("cannot be used"),
+            "{}",
+            result.err_msg
+        );
+
+        let jws = token(&key, json!({ "alg": "none" }), claims());
+        assert!(!verify(&jws, &key.jwk("").to_string(), ISSUER, AUDIENCE).success);
+    }
+
+    #[test]
+    fn rejects_crit() {
+        let key = SigningKey::p256(7);
+        let jws = token(
+            &key,
+            json!({ "alg": ALG_ES256, "crit": ["b64"], "b64": false }),
+            claims(),
+        );
+        let result = verify(&jws, &key.jwk("").to_string(), ISSUER, AUDIENCE);
+
+        assert!(!result.success);
+        assert!(result.err_msg.contains("crit"), "{}", result.err_msg);
+    }
+
+    #[test]
+    fn rejects_failed_claims() {
+        let key = SigningKey::secp256k1(8);
+        let jwk = key.jwk("").to_string();
+        let now = Utc::now().timestamp();
+
+        let mut expired = claims();
+        expired["exp"] = json!(now - 1);
+        let mut early = claims();
+        early["nbf"] = json!(now + 600);
+
+        for (claims, issuer, audience, err_msg) in [
+            (expired, ISSUER, AUDIENCE, "expired"),
+            (early, ISSUER, AUDIENCE, "not yet valid"),
+            (claims(), "https://other.example", AUDIENCE, "issuer"),
+            (claims(), ISSUER, "stranger", "audience"),
+        ] {
+            let jws = token(&key, json!({ "alg": ALG_ES256K }), claims);
+            let result = verify(&jws, &jwk, issuer, audience);
+
+            assert!(!result.success);
+            assert!(result.err_msg.contains(err_msg), "{}", result.err_msg);
+        }
+    }
+
+    #[test]
+    fn rejects_a_tampered_payload() {
+        let key = SigningKey::ed25519(9);
+        let jws = token(&key, json!({ "alg": ALG_EDDSA }), claims());
+        let mut parts: Vec<&str> = jws.split('.').collect();
+        let mut tampered = claims();
+        tampered["sub"] = json!("mallory");
+        let payload = URL_SAFE_NO_PAD.encode(tampered.to_string());
+        parts[1] = &payload;
+
+        assert!(!verify(&parts.join("."), &key.jwk("").to_string(), "", "").success);
+    }
+
+    #[test]
+    fn accepts_high_s_es256k() {
+        let key = SigningKey::secp256k1(10);
+        let SigningKey::Secp256k1(secret) = &key else {
+            unreachable!()
+        };
+        let header = URL_SAFE_NO_PAD.encode(json!({ "alg": ALG_ES256K }).to_string());
+        let payload = URL_SAFE_NO_PAD.encode(claims().to_string());
+        let signing_input = format!("{}.{}", header, payload);
+
+        let message = libsecp256k1::Message::parse(&Sha256::digest(&signing_input).into());
+        let (mut signature, _) = libsecp256k1::sign(&message, secret);
+        signature.s = -signature.s;
+        assert!(signature.s.is_high());
+
+        let jws = format!(
+            "{}.{}",
+            signing_input,
+            URL_SAFE_NO_PAD.encode(signature.serialize())
+        );
+
+        assert!(verify(&jws, &key.jwk("").to_string(), "", "").success);
+    }
+}
@@ -0,0 +1,370 @@
+use base58::{FromBase58, ToBase58};
+use libsecp256k1::PublicKey;
+
+use crate::bitcoin::{decode_address as decode_bitcoin_address, Address as BitcoinAddress};
+use crate::cosmos::decode_address as decode_cosmos_address;
+use crate::error::CryptoError;
+use crate::sr25519::decode_ss58;
+use crate::webauthn::decode_multibase as decode_p256_multibase;
+
+pub const ENC_SECP256K1: &str = "secp256k1";
+pub const ENC_ED25519: &str = "ed25519";
+pub const ENC_P256: &str = "p256";
+pub const ENC_SR25519: &str = "sr25519";
+pub const ENC_COSMOS: &str = "cosmos";
+pub const ENC_BITCOIN: &str = "bitcoin";
+
+pub const FORMAT_ADDRESS: &str = "address";
+pub const FORMAT_HEX: &str = "hex";
+pub const FORMAT_BASE58: &str = "base58";
+pub const FORMAT_MULTIBASE: &str = "multibase";
+pub const FORMAT_SS58: &str = "ss58";
+pub const FORMAT_BECH32: &str = "bech32";
+pub const FORMAT_BASE58CHECK: &str = "base58check";
+pub const FORMAT_DID_KEY: &str = "did:key";
+pub const FORMAT_DID_PKH: &str = "did:pkh";
+
+/// Multicodec varint prefixes for public keys
+const MULTICODEC_ED25519_PUB: [u8; 2] = [0xed, 0x01];
+const MULTICODEC_SECP256K1_PUB: [u8; 2] = [0xe7, 0x01];
+
+/// Multibase prefix for base58btc
+const MULTIBASE_BASE58BTC: char = 'z';
+
+/**
+ * A public key reduced to its encryption type and the form `verify` expects:
+ * a 0x address or hex key for secp256k1, a base58 key for ed25519, a
+ * multibase key for p256, a hex key for sr25519, and the address itself for
+ * bitcoin and cosmos.
+ */
+#[derive(Debug, PartialEq)]
+pub struct ParsedKey {
+    pub enc: &'static str,
+    pub format: &'static str,
+    pub public_key: String,
+}
+
+pub fn parse(public_key: &str) -> Result<ParsedKey, CryptoError> {
+    let unknown = || CryptoError::UnknownKeyType(public_key.to_string());
+
+    if let Some(rest) = public_key.strip_prefix("did:key:") {
+        return parse_multibase(rest)
+            .map(|key| ParsedKey {
+                format: FORMAT_DID_KEY,
+                ..key
+            })
+            .ok_or_else(unknown);
+    }
+
+    if let Some(rest) = public_key.strip_prefix("did:pkh:") {
+        return parse_did_pkh(rest).ok_or_else(unknown);
+    }
+
+    if let Some(key) = parse_hex(public_key) {
+        return Ok(key);
+    }
+
+    if let Some(key) = parse_multibase(public_key) {
+        return Ok(key);
+    }
+
+    if let Some(key) = parse_address(public_key) {
+        return Ok(key);
+    }
+
+    if let Some((_, key)) = decode_ss58(public_key) {
+        return Ok(ParsedKey {
+            enc: ENC_SR25519,
+            format: FORMAT_SS58,
+            public_key: format!("0x{}", hex::encode(key)),
+        });
+    }
+
+    match public_key.from_base58() {
+        Ok(bytes) if bytes.len() == 32 => Ok(ParsedKey {
+            enc: ENC_ED25519,
+            format: FORMAT_BASE58,
+            public_key: public_key.to_string(),
+        }),
+        _ => Err(unknown()),
+    }
+}
+
+/**
+ * 0x prefixed address, or a compressed/uncompressed secp256k1 key with or
+ * without the 0x prefix. A hex key is always read as secp256k1: a P-256 SEC1
+ * key looks the same, so P-256 keys are only accepted as multibase or did:key.
+ */
+fn parse_hex(public_key: &str) -> Option<ParsedKey> {
+    let prefixed = public_key.starts_with("0x");
+    let hex = public_key.trim_start_matches("0x");
+
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    match hex.len() {
+        40 if prefixed => Some(ParsedKey {
+            enc: ENC_SECP256K1,
+            format: FORMAT_ADDRESS,
+            public_key: public_key.to_string(),
+        }),
+        66 | 130 => {
+            let bytes = hex::decode(hex).ok()?;
+            PublicKey::parse_slice(&bytes, None).ok()?;
+
+            Some(ParsedKey {
+                enc: ENC_SECP256K1,
+                format: FORMAT_HEX,
+                public_key: format!("0x{}", hex.to_lowercase()),
+            })
+        }
+        _ => None,
+    }
+}
+
+fn parse_multibase(public_key: &str) -> Option<ParsedKey> {
+    let encoded = public_key.strip_prefix(MULTIBASE_BASE58BTC)?;
+    let bytes = encoded.from_base58().ok()?;
+
+    if bytes.len() < 2 {
+        return None;
+    }
+    let (codec, key) = bytes.split_at(2);
+
+    if codec == MULTICODEC_ED25519_PUB && key.len() == 32 {
+        return Some(ParsedKey {
+            enc: ENC_ED25519,
+            format: FORMAT_MULTIBASE,
+            public_key: key.to_base58(),
+        });
+    }
+
+    if decode_p256_multibase(public_key).is_some() {
+        return Some(ParsedKey {
+            enc: ENC_P256,
+            format: FORMAT_MULTIBASE,
+            public_key: public_key.to_string(),
+        });
+    }
+
+    if codec == MULTICODEC_SECP256K1_PUB && key.len() == 33 {
+        PublicKey::parse_slice(key, None).ok()?;
+
+        return Some(ParsedKey {
+            enc: ENC_SECP256K1,
+            format: FORMAT_MULTIBASE,
+            public_key: format!("0x{}", hex::encode(key)),
+        });
+    }
+
+    None
+}
+
+/**
+ * Bitcoin and Cosmos addresses are verified as they are, the signature
+ * carries or recovers the public key
+ */
+fn parse_address(public_key: &str) -> Option<ParsedKey> {
+    if let Some(address) = decode_bitcoin_address(public_key) {
+        let format = match address {
+            BitcoinAddress::P2wpkh(_) => FORMAT_BECH32,
+            _ => FORMAT_BASE58CHECK,
+        };
+
+        return Some(ParsedKey {
+            enc: ENC_BITCOIN,
+            format,
+            public_key: public_key.to_string(),
+        });
+    }
+
+    decode_cosmos_address(public_key).map(|_| ParsedKey {
+        enc: ENC_COSMOS,
+        format: FORMAT_BECH32,
+        public_key: public_key.to_string(),
+    })
+}
+
+/**
+ * did:pkh:<namespace>:<reference>:<account>, only eip155, bip122, cosmos
+ * and solana (raw ed25519 keys) accounts can be verified
+ */
+fn parse_did_pkh(rest: &str) -> Option<ParsedKey> {
+    let mut parts = rest.splitn(3, ':');
+    let namespace = parts.next()?;
+    let _reference = parts.next()?;
+    let account = parts.next()?;
+
+    let key = match namespace {
+        "eip155" => parse_hex(account).filter(|key| key.format == FORMAT_ADDRESS)?,
+        "bip122" => parse_address(account).filter(|key| key.enc == ENC_BITCOIN)?,
+        "cosmos" => parse_address(account).filter(|key| key.enc == ENC_COSMOS)?,
+        "solana" => match account.from_base58() {
+            Ok(bytes) if bytes.len() == 32 => ParsedKey {
+                enc: ENC_ED25519,
+                format: FORMAT_BASE58,
+                public_key: account.to_string(),
+            },
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    Some(ParsedKey {
+        format: FORMAT_DID_PKH,
+        ..key
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bech32::{ToBase32, Variant};
+
+    const ADDRESS: &str = "0x2c7536E3605D9C16a7a3D7b1898e529396a65c23";
+    const SECP256K1_COMPRESSED: &str =
+        "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd";
+    const ED25519_BASE58: &str = "B12NYF8RrR3h41TDCTJojY59usg3mbtbjnFs7Eud1Y6u";
+    const DID_KEY_ED25519: &str = "did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK";
+    const DID_KEY_SECP256K1: &str = "did:key:zQ3shokFTS3brHcDQrn82RUDfCZESWL1ZdCEJwekUDPQiYBme";
+    const DID_KEY_P256: &str = "did:key:zDnaerDaTF5BXEavCrfRZEk316dpbLsfPDZ3WJ5hRTPFU2169";
+    const SS58_ALICE: &str = "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY";
+    const BITCOIN_P2WPKH: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    const BITCOIN_P2PKH: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+    fn cosmos_address() -> String {
+        bech32::encode("cosmos", [7u8; 20].to_base32(), Variant::Bech32).unwrap()
+    }
+
+    fn uncompressed() -> String {
+        let bytes = hex::decode(SECP256K1_COMPRESSED).unwrap();
+        let key = PublicKey::parse_slice(&bytes, None).unwrap();
+        hex::encode(key.serialize())
+    }
+
+    #[test]
+    fn known_forms_are_detected() {
+        let cosmos = cosmos_address();
+        let uncompressed = uncompressed();
+        let cases = [
+            (ADDRESS, ENC_SECP256K1, FORMAT_ADDRESS),
+            (SECP256K1_COMPRESSED, ENC_SECP256K1, FORMAT_HEX),
+            (uncompressed.as_str(), ENC_SECP256K1, FORMAT_HEX),
+            (ED25519_BASE58, ENC_ED25519, FORMAT_BASE58),
+            (&DID_KEY_ED25519[8..], ENC_ED25519, FORMAT_MULTIBASE),
+            (&DID_KEY_SECP256K1[8..], ENC_SECP256K1, FORMAT_MULTIBASE),
+            (&DID_KEY_P256[8..], ENC_P256, FORMAT_MULTIBASE),
+            (DID_KEY_ED25519, ENC_ED25519, FORMAT_DID_KEY),
+            (DID_KEY_SECP256K1, ENC_SECP256K1, FORMAT_DID_KEY),
+            (DID_KEY_P256, ENC_P256, FORMAT_DID_KEY),
+            (SS58_ALICE, ENC_SR25519, FORMAT_SS58),
+            (BITCOIN_P2WPKH, ENC_BITCOIN, FORMAT_BECH32),
+            (BITCOIN_P2PKH, ENC_BITCOIN, FORMAT_BASE58CHECK),
+            (cosmos.as_str(), ENC_COSMOS, FORMAT_BECH32),
+        ];
+
+        for (public_key, enc, format) in cases.iter() {
+            let parsed = parse(public_key).unwrap_or_else(|e| panic!("{}: {}", public_key, e));
+
+            assert_eq!(
+                (parsed.enc, parsed.format),
+                (*enc, *format),
+                "{}",
+                public_key
+            );
+        }
+    }
+
+    #[test]
+    fn did_pkh_accounts_are_detected() {
+        let cosmos = format!("did:pkh:cosmos:cosmoshub-4:{}", cosmos_address());
+        let cases = [
+            (format!("did:pkh:eip155:1:{}", ADDRESS), ENC_SECP256K1),
+            (
+                format!(
+                    "did:pkh:bip122:000000000019d6689c085ae165831e93:{}",
+                    BITCOIN_P2WPKH
+                ),
+                ENC_BITCOIN,
+            ),
+            (cosmos, ENC_COSMOS),
+            (
+                format!(
+                    "did:pkh:solana:4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZ:{}",
+                    ED25519_BASE58
+                ),
+                ENC_ED25519,
+            ),
+        ];
+
+        for (public_key, enc) in cases.iter() {
+            let parsed = parse(public_key).unwrap();
+
+            assert_eq!(
+                (parsed.enc, parsed.format),
+                (*enc, FORMAT_DID_PKH),
+                "{}",
+                public_key
+            );
+        }
+
+        assert!(parse(&format!("did:pkh:eip155:1:{}", SECP256K1_COMPRESSED)).is_err());
+        assert!(parse(&format!("did:pkh:tezos:NetXdQprcVkpaWU:{}", ADDRESS)).is_err());
+    }
+
+    #[test]
+    fn keys_are_normalised_for_verify() {
+        let prefixed = parse(&format!("0x{}", SECP256K1_COMPRESSED.to_uppercase())).unwrap();
+        assert_eq!(prefixed.public_key, format!("0x{}", SECP256K1_COMPRESSED));
+
+        assert_eq!(
+            parse(DID_KEY_ED25519)
+                .unwrap()
+                .public_key
+                .from_base58()
+                .unwrap()
+                .len(),
+            32
+        );
+        assert_eq!(parse(DID_KEY_P256).unwrap().public_key, &DID_KEY_P256[8..]);
+        assert_eq!(parse(ADDRESS).unwrap().public_key, ADDRESS);
+    }
+
+    #[test]
+    fn hex_keys_are_never_p256() {
+        let p256 = decode_p256_multibase(&DID_KEY_P256[8..]).unwrap();
+
+        match parse(&hex::encode(&p256)) {
+            Ok(key) => assert_eq!(key.enc, ENC_SECP256K1),
+            Err(e) => assert!(matches!(e, CryptoError::UnknownKeyType(_))),
+        }
+    }
+
+    #[test]
+    fn malformed_keys_are_rejected() {
+        let cases = [
+            "",
+            "0",
+            "0x",
+            "0x00",
+            "a",
+            "not a key",
+            "did:key:",
+            "did:key:zzzz",
+            "did:pkh:eip155:1",
+            // address without 0x
+            "2c7536E3605D9C16a7a3D7b1898e529396a65c23",
+            // compressed key that is not on the curve
+            "020000000000000000000000000000000000000000000000000000000000000005",
+        ];
+
+        for public_key in cases.iter() {
+            assert!(
+                matches!(parse(public_key), Err(CryptoError::UnknownKeyType(_))),
+                "{}",
+                public_key
+            );
+        }
+    }
+}
@@ -1,20 +1,28 @@
 #![allow(improper_ctypes)]
 
+mod bitcoin;
+mod bls;
+mod cosmos;
+mod ecies;
 mod ed25519;
+mod error;
+mod hash;
+mod hd;
+mod jws;
+mod key;
 mod secp256k1;
+mod siwe;
+mod sr25519;
+mod webauthn;
 
-use ed25519_compact::KeyPair;
-use ed25519_compact::{Noise, SecretKey};
+use error::CryptoError;
 use marine_rs_sdk::marine;
 use marine_rs_sdk::module_manifest;
 use marine_rs_sdk::WasmLoggerBuilder;
-use std::ops::Deref;
 
 use ed25519::verify as verify_ed25519;
 use secp256k1::verify as verify_secp256k1;
 
-const DEFAULT_ENC: &str = "secp256k1";
-
 use types::*;
 
 module_manifest!();
@@ -26,50 +34,598 @@ pub fn main() {
         .unwrap();
 }
 
+/**
+ * Generates a keypair for `enc` (ed25519 when empty, as before `enc` was
+ * added), see `KeyPair` for the encoding of each type
+ */
 #[marine]
-pub fn generate_keypair() -> Ed25519KeyPair {
-    let kp = KeyPair::generate();
-    let base64_pk = base64::encode(kp.pk.deref());
+pub fn generate_keypair(enc: String) -> KeyPairResult {
+    let result = if enc.is_empty() || enc == ed25519::ENC {
+        Ok(ed25519::generate_keypair())
+    } else if enc == secp256k1::ENC {
+        secp256k1::generate_keypair()
+    } else if enc == bls::ENC {
+        bls::generate_keypair()
+    } else {
+        Err(CryptoError::NotSupportedEncryptionType(enc))
+    };
+
+    to_keypair_result(result)
+}
+
+/**
+ * BIP-39 mnemonic to recover keys from with `derive_keypair`
+ */
+#[marine]
+pub fn generate_mnemonic(words: u32) -> MnemonicResult {
+    match hd::generate_mnemonic(words) {
+        Ok(mnemonic) => MnemonicResult {
+            success: true,
+            err_msg: "".to_string(),
+            mnemonic,
+        },
+        Err(e) => MnemonicResult {
+            success: false,
+            err_msg: e.to_string(),
+            ..Default::default()
+        },
+    }
+}
 
-    let base64_sk = base64::encode(kp.sk.deref());
+/**
+ * Key of `path` derived from a mnemonic, encoded as `generate_keypair`
+ * does, ed25519 when `enc` is empty. An empty path is m/44'/60'/0'/0/0 for
+ * secp256k1 and m/44'/501'/0'/0' for ed25519.
+ */
+#[marine]
+pub fn derive_keypair(
+    mnemonic: String,
+    passphrase: String,
+    path: String,
+    enc: String,
+) -> KeyPairResult {
+    to_keypair_result(hd::derive_keypair(&mnemonic, &passphrase, &path, &enc))
+}
 
-    Ed25519KeyPair {
-        pk: base64_pk,
-        sk: base64_sk,
+fn to_keypair_result(result: Result<KeyPair, CryptoError>) -> KeyPairResult {
+    match result {
+        Ok(keypair) => KeyPairResult {
+            success: true,
+            err_msg: "".to_string(),
+            keypair,
+        },
+        Err(e) => KeyPairResult {
+            success: false,
+            err_msg: e.to_string(),
+            ..Default::default()
+        },
     }
 }
 
+/**
+ * Checks `signature` of `message` with a key of type `enc`, ed25519 when
+ * `enc` is empty as for `generate_keypair` and `sign`. Use `verify_batch` to
+ * have the type resolved from the key instead.
+ */
 #[marine]
 pub fn verify(public_key: String, signature: String, message: String, enc: String) -> bool {
-    let verify: bool;
+    verify_signature(public_key, signature, message, enc).success
+}
+
+/**
+ * Same as `verify` but reports why a signature was rejected
+ */
+#[marine]
+pub fn verify_signature(
+    public_key: String,
+    signature: String,
+    message: String,
+    enc: String,
+) -> VerifyResult {
+    to_verify_result(verify_with(&public_key, &signature, &message, &enc))
+}
+
+/**
+ * Verifies many signatures in one call, results are in request order.
+ * A request with an empty `enc` has its key type resolved from the public
+ * key, as `get_public_key_type` does, and every result carries the key type
+ * it was checked with. ed25519 signatures are checked together with batch
+ * verification and only checked one by one when the batch fails, to find
+ * the bad ones.
+ */
+#[marine]
+pub fn verify_batch(requests: Vec<VerifyRequest>) -> Vec<KeyVerifyResult> {
+    let resolved: Vec<(KeyTypeResult, VerifyRequest)> = requests
+        .into_iter()
+        .map(|r| (resolve_key_type(&r), r))
+        .collect();
+
+    let ed25519_batch_valid = {
+        let items: Vec<(&str, &str, &str)> = resolved
+            .iter()
+            .filter(|(key_type, _)| key_type.success && key_type.enc == ed25519::ENC)
+            .map(|(key_type, r)| {
+                (
+                    key_type.public_key.as_str(),
+                    r.signature.as_str(),
+                    r.message.as_str(),
+                )
+            })
+            .collect();
+
+        !items.is_empty() && ed25519::verify_batch(&items)
+    };
+
+    resolved
+        .into_iter()
+        .map(|(key_type, r)| {
+            let verify = if !key_type.success {
+                VerifyResult {
+                    success: false,
+                    err_msg: key_type.err_msg.clone(),
+                }
+            } else if ed25519_batch_valid && key_type.enc == ed25519::ENC {
+                to_verify_result(Ok(()))
+            } else {
+                to_verify_result(verify_with(
+                    &key_type.public_key,
+                    &r.signature,
+                    &r.message,
+                    &key_type.enc,
+                ))
+            };
+
+            KeyVerifyResult { key_type, verify }
+        })
+        .collect()
+}
+
+/**
+ * The key type `request` is verified with, `enc` as given when it is set
+ */
+fn resolve_key_type(request: &VerifyRequest) -> KeyTypeResult {
+    if request.enc.is_empty() {
+        get_public_key_type(&request.public_key)
+    } else {
+        KeyTypeResult {
+            success: true,
+            err_msg: "".to_string(),
+            enc: request.enc.clone(),
+            format: "".to_string(),
+            public_key: request.public_key.clone(),
+        }
+    }
+}
+
+fn verify_with(
+    public_key: &str,
+    signature: &str,
+    message: &str,
+    enc: &str,
+) -> Result<(), CryptoError> {
+    if enc.is_empty() || enc == ed25519::ENC {
+        verify_ed25519(public_key, signature, message)
+    } else if enc == secp256k1::ENC {
+        verify_secp256k1(public_key, signature, message)
+    } else if enc == webauthn::ENC {
+        webauthn::verify(public_key, signature, message)
+    } else if enc == sr25519::ENC {
+        sr25519::verify(public_key, signature, message)
+    } else if enc == cosmos::ENC {
+        cosmos::verify(public_key, signature, message)
+    } else if enc == bitcoin::ENC {
+        bitcoin::verify(public_key, signature, message)
+    } else if enc == bls::ENC {
+        bls::verify(public_key, signature, message)
+    } else {
+        Err(CryptoError::NotSupportedEncryptionType(enc.to_string()))
+    }
+}
+
+fn to_sign_result(result: Result<String, CryptoError>) -> SignResult {
+    match result {
+        Ok(signature) => SignResult {
+            success: true,
+            err_msg: "".to_string(),
+            signature,
+        },
+        Err(e) => SignResult {
+            success: false,
+            err_msg: e.to_string(),
+            ..Default::default()
+        },
+    }
+}
+
+fn to_verify_result(result: Result<(), CryptoError>) -> VerifyResult {
+    match result {
+        Ok(()) => VerifyResult {
+            success: true,
+            err_msg: "".to_string(),
+        },
+        Err(e) => VerifyResult {
+            success: false,
+            err_msg: e.to_string(),
+        },
+    }
+}
 
-    if enc.is_empty() || enc == DEFAULT_ENC {
-        verify = verify_secp256k1(public_key.clone(), signature.clone(), message);
+/**
+ * Signs `message` in the encoding `verify` expects for `enc` (ed25519 when
+ * empty): personal_sign hex for secp256k1, base58 for ed25519, 0x hex G2 point
+ * for bls12381
+ */
+#[marine]
+pub fn sign(message: String, private_key: String, enc: String) -> SignResult {
+    let result = if enc.is_empty() || enc == ed25519::ENC {
+        ed25519::sign(&message, &private_key)
+    } else if enc == secp256k1::ENC {
+        secp256k1::sign(&message, &private_key)
+    } else if enc == bls::ENC {
+        bls::sign(&message, &private_key)
     } else {
-        verify = verify_ed25519(public_key.clone(), signature.clone(), message);
+        Err(CryptoError::NotSupportedEncryptionType(enc))
+    };
+
+    to_sign_result(result)
+}
+
+/**
+ * Detects the encryption type of a hex address or key, a base58 ed25519 key,
+ * a multibase/multicodec key, or a did:key / did:pkh identifier. `public_key`
+ * in the result is the form `verify` accepts for that type.
+ */
+#[marine]
+pub fn get_public_key_type(public_key: &str) -> KeyTypeResult {
+    match key::parse(public_key) {
+        Ok(key) => KeyTypeResult {
+            success: true,
+            err_msg: "".to_string(),
+            enc: key.enc.to_string(),
+            format: key.format.to_string(),
+            public_key: key.public_key,
+        },
+        Err(e) => KeyTypeResult {
+            success: false,
+            err_msg: e.to_string(),
+            ..Default::default()
+        },
     }
+}
 
-    verify
+/**
+ * Verifies a Sign-In with Ethereum (EIP-4361) message and its personal_sign
+ * signature. The caller should still compare the returned domain and nonce
+ * with the ones it issued.
+ */
+#[marine]
+pub fn verify_siwe(message: String, signature: String) -> SiweResult {
+    siwe::verify(&message, &signature)
 }
 
+/**
+ * Verifies a compact JWS/JWT signed with ES256K, EdDSA or ES256 and returns
+ * its claims. `jwks_or_key` is a JWKS, a JWK or a public key; exp and nbf
+ * are always checked, `issuer` and `audience` when not empty.
+ */
 #[marine]
-pub fn sign(message: String, private_key: String) -> String {
-    let pk_key_decoded = base64::decode(private_key).unwrap();
+pub fn verify_jws(
+    token: String,
+    jwks_or_key: String,
+    issuer: String,
+    audience: String,
+) -> JwsResult {
+    jws::verify(&token, &jwks_or_key, &issuer, &audience)
+}
 
-    let pk = pk_key_decoded.try_into().expect("invalid private key");
+/**
+ * Encrypts `plaintext` to a single ed25519 (via X25519) or secp256k1 public
+ * key, see `encrypt_for_recipients`
+ */
+#[marine]
+pub fn encrypt(recipient_pubkey: String, plaintext: String) -> EncryptResult {
+    encrypt_for_recipients(vec![recipient_pubkey], plaintext)
+}
 
-    let sk = SecretKey::new(pk);
+/**
+ * Encrypts `plaintext` into a JSON `Envelope`, with the content key wrapped
+ * once per recipient
+ */
+#[marine]
+pub fn encrypt_for_recipients(recipients: Vec<String>, plaintext: String) -> EncryptResult {
+    match ecies::encrypt(&recipients, &plaintext) {
+        Ok(envelope) => EncryptResult {
+            success: true,
+            err_msg: "".to_string(),
+            envelope,
+        },
+        Err(e) => EncryptResult {
+            success: false,
+            err_msg: e.to_string(),
+            ..Default::default()
+        },
+    }
+}
 
-    let signature = sk.sign(message, Some(Noise::default()));
+/**
+ * Opens an envelope with a recipient private key, encoded as
+ * `generate_keypair` returns it for `enc`
+ */
+#[marine]
+pub fn decrypt(envelope: String, private_key: String, enc: String) -> DecryptResult {
+    match ecies::decrypt(&envelope, &private_key, &enc) {
+        Ok(plaintext) => DecryptResult {
+            success: true,
+            err_msg: "".to_string(),
+            plaintext,
+        },
+        Err(e) => DecryptResult {
+            success: false,
+            err_msg: e.to_string(),
+            ..Default::default()
+        },
+    }
+}
 
-    base64::encode(signature)
+/**
+ * Wraps the content key of an envelope again for a new recipient list,
+ * `private_key` must belong to one of the current recipients
+ */
+#[marine]
+pub fn rewrap(
+    envelope: String,
+    private_key: String,
+    enc: String,
+    recipients: Vec<String>,
+) -> EncryptResult {
+    match ecies::rewrap(&envelope, &private_key, &enc, &recipients) {
+        Ok(envelope) => EncryptResult {
+            success: true,
+            err_msg: "".to_string(),
+            envelope,
+        },
+        Err(e) => EncryptResult {
+            success: false,
+            err_msg: e.to_string(),
+            ..Default::default()
+        },
+    }
 }
 
+/**
+ * SHA-256 of the UTF-8 bytes of `data`, lowercase hex
+ */
 #[marine]
-pub fn get_public_key_type(public_key: &str) -> String {
-    if &public_key[..2] == "0x" {
-        return "secp256k1".to_string();
-    } else {
-        return "ed25519".to_string();
+pub fn sha256(data: String) -> String {
+    hash::sha256(data.as_bytes())
+}
+
+/**
+ * Keccak-256 of the UTF-8 bytes of `data`, lowercase hex
+ */
+#[marine]
+pub fn keccak256(data: String) -> String {
+    hash::keccak256(data.as_bytes())
+}
+
+/**
+ * BLAKE3 of the UTF-8 bytes of `data`, lowercase hex
+ */
+#[marine]
+pub fn blake3(data: String) -> String {
+    hash::blake3(data.as_bytes())
+}
+
+/**
+ * Digest an Ethereum wallet signs for `message` with `personal_sign`
+ */
+#[marine]
+pub fn eth_message_hash(message: String) -> String {
+    hash::eth_message_hash(&message)
+}
+
+/**
+ * Hash the node gives a transaction, see `Transaction::generate_hash`
+ */
+#[marine]
+#[allow(clippy::too_many_arguments)]
+pub fn transaction_hash(
+    token_key: String,
+    data_key: String,
+    data: String,
+    nonce: i64,
+    public_key: String,
+    alias: String,
+    method: String,
+    token_id: String,
+    version: i64,
+) -> String {
+    types::transaction_hash(
+        &token_key,
+        &data_key,
+        &data,
+        nonce,
+        &public_key,
+        &alias,
+        &method,
+        &token_id,
+        version,
+    )
+}
+
+/**
+ * Hash the node gives a cron, see `Cron::generate_hash`
+ */
+#[marine]
+pub fn cron_hash(address: String, topic: String, chain: String) -> String {
+    types::cron_hash(&address, &topic, &chain)
+}
+
+/**
+ * Hash the node gives a metadata row, see `Metadata::generate_hash`
+ */
+#[marine]
+pub fn metadata_hash(data_key: String, alias: String, public_key: String) -> String {
+    types::metadata_hash(&data_key, &alias, &public_key)
+}
+
+/**
+ * BLS proof of possession of `private_key`, published with its public key
+ */
+#[marine]
+pub fn bls_pop_prove(private_key: String) -> SignResult {
+    to_sign_result(bls::pop_prove(&private_key))
+}
+
+#[marine]
+pub fn bls_pop_verify(public_key: String, proof: String) -> VerifyResult {
+    to_verify_result(bls::pop_verify(&public_key, &proof))
+}
+
+/**
+ * Aggregates BLS signatures into one, over the same or different messages
+ */
+#[marine]
+pub fn bls_aggregate(signatures: Vec<String>) -> SignResult {
+    to_sign_result(bls::aggregate(&signatures))
+}
+
+/**
+ * Verifies an aggregate BLS signature where `public_keys[i]` signed
+ * `messages[i]`
+ */
+#[marine]
+pub fn bls_aggregate_verify(
+    public_keys: Vec<String>,
+    messages: Vec<String>,
+    signature: String,
+) -> VerifyResult {
+    to_verify_result(bls::aggregate_verify(&public_keys, &messages, &signature))
+}
+
+/**
+ * Verifies an aggregate BLS signature of every key over `message`, for keys
+ * whose proof of possession has been checked with `bls_pop_verify`
+ */
+#[marine]
+pub fn bls_fast_aggregate_verify(
+    public_keys: Vec<String>,
+    message: String,
+    signature: String,
+) -> VerifyResult {
+    to_verify_result(bls::fast_aggregate_verify(&public_keys, &message, &signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_enc_is_ed25519() {
+        let result = generate_keypair("".to_string());
+        assert!(result.success, "{}", result.err_msg);
+        assert_eq!(result.keypair.enc, ed25519::ENC);
+
+        let signed = sign("hello".to_string(), result.keypair.sk, "".to_string());
+        assert!(signed.success, "{}", signed.err_msg);
+
+        assert!(verify(
+            result.keypair.pk.clone(),
+            signed.signature.clone(),
+            "hello".to_string(),
+            "".to_string(),
+        ));
+        assert!(!verify(
+            result.keypair.pk,
+            signed.signature,
+            "other".to_string(),
+            "".to_string(),
+        ));
+    }
+
+    #[test]
+    fn empty_enc_does_not_verify_secp256k1() {
+        let keypair = generate_keypair(secp256k1::ENC.to_string()).keypair;
+        let signed = sign("hello".to_string(), keypair.sk, secp256k1::ENC.to_string());
+        assert!(signed.success, "{}", signed.err_msg);
+
+        let check = |enc: &str| {
+            verify_signature(
+                keypair.pk.clone(),
+                signed.signature.clone(),
+                "hello".to_string(),
+                enc.to_string(),
+            )
+        };
+
+        assert!(check(secp256k1::ENC).success);
+        assert!(!check("").success);
+    }
+
+    fn ed25519_request(message: &str) -> VerifyRequest {
+        let keypair = generate_keypair(ed25519::ENC.to_string()).keypair;
+        let signed = sign(message.to_string(), keypair.sk, ed25519::ENC.to_string());
+
+        VerifyRequest {
+            public_key: keypair.pk,
+            signature: signed.signature,
+            message: message.to_string(),
+            enc: "".to_string(),
+        }
+    }
+
+    #[test]
+    fn verify_batch_resolves_key_types() {
+        let mut tampered = ed25519_request("second");
+        tampered.message = "tampered".to_string();
+        let unknown = VerifyRequest {
+            public_key: "not a key".to_string(),
+            ..ed25519_request("third")
+        };
+
+        let results = verify_batch(vec![ed25519_request("first"), tampered, unknown]);
+
+        assert_eq!(results.len(), 3);
+        assert!(results[0].key_type.success);
+        assert_eq!(results[0].key_type.enc, ed25519::ENC);
+        assert!(results[0].verify.success);
+        assert!(results[1].key_type.success);
+        assert!(!results[1].verify.success);
+        assert!(!results[2].key_type.success);
+        assert!(!results[2].verify.success);
+    }
+
+    #[test]
+    fn verify_batch_rejects_small_order_keys_like_verify() {
+        // identity public key, R = identity and S = 0 satisfy the cofactored
+        // equation for any message
+        let mut identity = [0u8; 32];
+        identity[0] = 1;
+        let mut signature = identity.to_vec();
+        signature.extend_from_slice(&[0u8; 32]);
+
+        let request = VerifyRequest {
+            public_key: base58::ToBase58::to_base58(&identity[..]),
+            signature: base58::ToBase58::to_base58(&signature[..]),
+            message: "forged".to_string(),
+            enc: ed25519::ENC.to_string(),
+        };
+
+        assert!(verify_with(
+            &request.public_key,
+            &request.signature,
+            &request.message,
+            ed25519::ENC
+        )
+        .is_err());
+        assert!(!ed25519::verify_batch(&[(
+            request.public_key.as_str(),
+            request.signature.as_str(),
+            request.message.as_str(),
+        )]));
+
+        let results = verify_batch(vec![ed25519_request("valid"), request]);
+        assert!(results[0].verify.success);
+        assert!(!results[1].verify.success);
     }
 }
@@ -1,5 +1,12 @@
-use libsecp256k1::{recover, Message, RecoveryId, Signature};
+use libsecp256k1::{
+    recover, sign as sign_message, Message, PublicKey, RecoveryId, SecretKey, Signature,
+};
 use tiny_keccak::{Hasher, Keccak};
+use types::KeyPair;
+
+use crate::error::CryptoError;
+
+pub const ENC: &str = "secp256k1";
 
 pub fn eth_message(message: String) -> [u8; 32] {
     let msg = format!(
@@ -31,7 +38,7 @@ fn public_key_to_address(public_key: [u8; 65]) -> String {
     format!("0x{}", address_hex)
 }
 
-fn keccak256_hash(bytes: &[u8]) -> Vec<u8> {
+pub fn keccak256_hash(bytes: &[u8]) -> Vec<u8> {
     let mut hasher = Keccak::v256();
     hasher.update(bytes);
     let mut resp: [u8; 32] = Default::default();
@@ -39,28 +46,289 @@ fn keccak256_hash(bytes: &[u8]) -> Vec<u8> {
     resp.iter().cloned().collect()
 }
 
-pub fn verify(public_key: String, signature: String, message: String) -> bool {
-    let sign_decoded = hex::decode(signature[2..].to_string()).unwrap();
+/**
+ * Checks a personal_sign signature against an address or a raw public key,
+ * either compressed (33 bytes) or uncompressed (65 bytes), hex encoded.
+ */
+pub fn verify(public_key: &str, signature: &str, message: &str) -> Result<(), CryptoError> {
+    let signer = recover_signer(message, signature)?;
+    let key = public_key.trim_start_matches("0x");
 
-    let sign: [u8; 64] = sign_decoded[..64]
-        .try_into()
-        .expect("Error: Sign with incorrect length");
+    let matches = match key.len() {
+        40 => public_key_to_address(signer.serialize()).eq_ignore_ascii_case(&format!("0x{}", key)),
+        66 | 130 => {
+            let decoded =
+                hex::decode(key).map_err(|e| CryptoError::InvalidPublicKey(e.to_string()))?;
+            let expected = PublicKey::parse_slice(&decoded, None)
+                .map_err(|_| CryptoError::InvalidPublicKey(public_key.to_string()))?;
+            expected == signer
+        }
+        _ => return Err(CryptoError::InvalidPublicKey(public_key.to_string())),
+    };
+
+    if !matches {
+        return Err(CryptoError::SignerMismatch(public_key_to_address(
+            signer.serialize(),
+        )));
+    }
+
+    Ok(())
+}
+
+/**
+ * Recovers the address that produced a personal_sign signature over `message`.
+ */
+pub fn recover_address(message: &str, signature: &str) -> Result<String, CryptoError> {
+    recover_signer(message, signature).map(|pubkey| public_key_to_address(pubkey.serialize()))
+}
+
+/**
+ * Recovers the public key behind a hex `r || s || v` signature. The recovery id may
+ * be 0/1, 27/28 or EIP-155 encoded, and s must be in the lower half order.
+ */
+fn recover_signer(message: &str, signature: &str) -> Result<PublicKey, CryptoError> {
+    let sign_decoded = hex::decode(signature.trim_start_matches("0x"))
+        .map_err(|e| CryptoError::InvalidSignature(e.to_string()))?;
+
+    // EIP-155 v values grow with the chain id and may take more than one byte
+    if sign_decoded.len() < 65 || sign_decoded.len() > 72 {
+        return Err(CryptoError::InvalidSignature(format!(
+            "expected 65 bytes, got {}",
+            sign_decoded.len()
+        )));
+    }
+
+    let mut sign = [0u8; 64];
+    sign.copy_from_slice(&sign_decoded[..64]);
+
+    let ctx_sig = Signature::parse_standard(&sign)
+        .map_err(|_| CryptoError::InvalidSignature("r or s overflows".to_string()))?;
+    if ctx_sig.s.is_high() {
+        return Err(CryptoError::HighS());
+    }
+
+    let v = sign_decoded[64..]
+        .iter()
+        .fold(0u64, |acc, b| (acc << 8) | *b as u64);
+    let recovery_id = match v {
+        0 | 1 => v,
+        27 | 28 => v - 27,
+        v if v >= 35 => (v - 35) % 2,
+        _ => return Err(CryptoError::InvalidRecoveryId(v)),
+    };
+    let ctx_recovery_id =
+        RecoveryId::parse(recovery_id as u8).map_err(|_| CryptoError::InvalidRecoveryId(v))?;
+
+    let ctx_message = Message::parse(&eth_message(message.to_string()));
+
+    recover(&ctx_message, &ctx_sig, &ctx_recovery_id)
+        .map_err(|_| CryptoError::InvalidSignature("unable to recover signer".to_string()))
+}
+
+/**
+ * EIP-55 mixed-case checksum encoding of an address
+ */
+pub fn to_checksum_address(address: &str) -> String {
+    let lower = address.trim_start_matches("0x").to_lowercase();
+    let hash = hex::encode(keccak256_hash(lower.as_bytes()));
+
+    let checksummed: String = lower
+        .chars()
+        .zip(hash.chars())
+        .map(|(c, h)| {
+            if c.is_ascii_alphabetic() && h.to_digit(16).unwrap_or(0) >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+
+    format!("0x{}", checksummed)
+}
+
+pub fn generate_keypair() -> Result<KeyPair, CryptoError> {
+    let mut seed = [0u8; 32];
+
+    // a random 32 byte value is out of the curve order with negligible odds
+    let sk = loop {
+        getrandom::getrandom(&mut seed).map_err(|e| CryptoError::KeyGeneration(e.to_string()))?;
+        if let Ok(sk) = SecretKey::parse(&seed) {
+            break sk;
+        }
+    };
+
+    Ok(keypair_from_secret(&sk))
+}
+
+pub fn keypair_from_secret(sk: &SecretKey) -> KeyPair {
+    KeyPair {
+        pk: public_key_to_address(PublicKey::from_secret_key(sk).serialize()),
+        sk: format!("0x{}", hex::encode(sk.serialize())),
+        enc: ENC.to_string(),
+        ..Default::default()
+    }
+}
+
+/**
+ * Signs `message` with personal_sign framing using a 0x hex secret key.
+ * Returns a 0x hex `r || s || v` signature with v as 27/28.
+ */
+pub fn sign(message: &str, private_key: &str) -> Result<String, CryptoError> {
+    let decoded = hex::decode(private_key.trim_start_matches("0x"))
+        .map_err(|e| CryptoError::InvalidPrivateKey(e.to_string()))?;
+    let sk = SecretKey::parse_slice(&decoded)
+        .map_err(|_| CryptoError::InvalidPrivateKey("expected 32 bytes".to_string()))?;
+
+    let (signature, recovery_id) =
+        sign_message(&Message::parse(&eth_message(message.to_string())), &sk);
+
+    let mut serialized = signature.serialize().to_vec();
+    serialized.push(recovery_id.serialize() + 27);
+
+    Ok(format!("0x{}", hex::encode(serialized)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+    const ADDRESS: &str = "0x2c7536E3605D9C16a7a3D7b1898e529396a65c23";
+    const MESSAGE: &str = "hello node";
+
+    // order of the secp256k1 group
+    const N: [u8; 32] = [
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xfe, 0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36,
+        0x41, 0x41,
+    ];
+
+    fn signature_bytes() -> Vec<u8> {
+        hex::decode(sign(MESSAGE, SECRET).unwrap().trim_start_matches("0x")).unwrap()
+    }
+
+    fn with_v(v: &[u8]) -> String {
+        let mut bytes = signature_bytes()[..64].to_vec();
+        bytes.extend_from_slice(v);
+        format!("0x{}", hex::encode(bytes))
+    }
+
+    fn recovery_id() -> u8 {
+        signature_bytes()[64] - 27
+    }
+
+    fn public_key() -> PublicKey {
+        let sk = SecretKey::parse_slice(&hex::decode(&SECRET[2..]).unwrap()).unwrap();
+        PublicKey::from_secret_key(&sk)
+    }
+
+    #[test]
+    fn malformed_signatures_are_invalid() {
+        let full = sign(MESSAGE, SECRET).unwrap();
+        let cases = [
+            // 64 bytes
+            full[..full.len() - 2].to_string(),
+            // odd length
+            full[..full.len() - 1].to_string(),
+            // not hex
+            format!("{}zz", &full[..full.len() - 2]),
+            "".to_string(),
+        ];
+
+        for signature in cases.iter() {
+            assert!(
+                matches!(
+                    recover_signer(MESSAGE, signature),
+                    Err(CryptoError::InvalidSignature(_))
+                ),
+                "{}",
+                signature
+            );
+        }
+    }
+
+    #[test]
+    fn short_signature_reports_its_length() {
+        let full = sign(MESSAGE, SECRET).unwrap();
+        let error = verify(ADDRESS, &full[..full.len() - 2], MESSAGE).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Invalid signature: expected 65 bytes, got 64"
+        );
+    }
+
+    #[test]
+    fn every_recovery_id_encoding_is_accepted() {
+        let id = recovery_id();
+        let encodings = [
+            vec![id],
+            vec![27 + id],
+            // EIP-155 on chain 1
+            vec![37 + id],
+            // EIP-155 on chain 137, v takes two bytes
+            (309u16 + id as u16).to_be_bytes().to_vec(),
+        ];
+
+        for v in encodings.iter() {
+            assert_eq!(
+                recover_address(MESSAGE, &with_v(v)).unwrap(),
+                ADDRESS.to_lowercase()
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_recovery_ids_are_rejected() {
+        for v in [2u8, 26, 29, 34] {
+            assert!(matches!(
+                recover_signer(MESSAGE, &with_v(&[v])),
+                Err(CryptoError::InvalidRecoveryId(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn high_s_is_rejected() {
+        let bytes = signature_bytes();
+        let mut high_s = [0u8; 32];
+        let mut borrow = 0i16;
+
+        for i in (0..32).rev() {
+            let diff = N[i] as i16 - bytes[32 + i] as i16 - borrow;
+            borrow = if diff < 0 { 1 } else { 0 };
+            high_s[i] = (diff + (borrow << 8)) as u8;
+        }
 
-    let message_decoded = eth_message(message);
+        let mut flipped = bytes[..32].to_vec();
+        flipped.extend_from_slice(&high_s);
+        flipped.push(27 + (recovery_id() ^ 1));
 
-    let ctx_message = Message::parse(&message_decoded);
-    let ctx_sig = Signature::parse_standard(&sign).expect("signature is valid");
-    let recovery_id = sign_decoded[64] as i32;
+        assert!(matches!(
+            recover_signer(MESSAGE, &hex::encode(flipped)),
+            Err(CryptoError::HighS())
+        ));
+    }
 
-    let pubkey = recover(
-        &ctx_message,
-        &ctx_sig,
-        &RecoveryId::parse_rpc(recovery_id as u8).unwrap(),
-    )
-    .unwrap();
+    #[test]
+    fn every_key_form_is_verified() {
+        let signature = sign(MESSAGE, SECRET).unwrap();
+        let keys = [
+            ADDRESS.to_string(),
+            ADDRESS.to_lowercase(),
+            hex::encode(public_key().serialize_compressed()),
+            format!("0x{}", hex::encode(public_key().serialize())),
+        ];
 
-    // log::info!("pubkey: {:?}", pubkey.serialize());
-    log::info!("address: {:?}", public_key_to_address(pubkey.serialize()));
+        for key in keys.iter() {
+            assert!(verify(key, &signature, MESSAGE).is_ok(), "{}", key);
+            assert!(verify(key, &signature, "other message").is_err(), "{}", key);
+        }
 
-    public_key.to_lowercase() == public_key_to_address(pubkey.serialize()).to_lowercase()
+        assert!(matches!(
+            verify("0x1234", &signature, MESSAGE),
+            Err(CryptoError::InvalidPublicKey(_))
+        ));
+    }
 }
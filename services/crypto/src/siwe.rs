@@ -0,0 +1,321 @@
+use chrono::{DateTime, Utc};
+use types::SiweResult;
+
+use crate::secp256k1::{recover_address, to_checksum_address};
+
+const PREAMBLE: &str = " wants you to sign in with your Ethereum account:";
+const SUPPORTED_VERSION: &str = "1";
+const MIN_NONCE_LENGTH: usize = 8;
+
+/**
+ * Parses an EIP-4361 message into a result with every field filled in.
+ * `success` is left false, the caller decides once the checks pass.
+ */
+pub fn parse(message: &str) -> Result<SiweResult, String> {
+    let mut lines = message.split('\n').map(|l| l.trim_end_matches('\r'));
+    let mut result = SiweResult::default();
+
+    let header = lines.next().unwrap_or_default();
+    result.domain = header
+        .strip_suffix(PREAMBLE)
+        .ok_or("missing sign in preamble")?
+        .to_string();
+    if result.domain.is_empty() || result.domain.contains(char::is_whitespace) {
+        return Err("invalid domain".into());
+    }
+
+    result.address = lines.next().ok_or("missing address")?.to_string();
+    check_address(&result.address)?;
+
+    if lines.next() != Some("") {
+        return Err("expected empty line after address".into());
+    }
+
+    // the statement is optional, without it the spec still leaves its empty
+    // line, older signers drop it
+    let mut line = lines.next().ok_or("missing uri")?;
+    if line.is_empty() {
+        line = lines.next().ok_or("missing uri")?;
+    } else if !line.starts_with("URI: ") {
+        result.statement = line.to_string();
+        if lines.next() != Some("") {
+            return Err("expected empty line after statement".into());
+        }
+        line = lines.next().ok_or("missing uri")?;
+    }
+
+    result.uri = field(line, "URI")?;
+    result.version = field(lines.next().unwrap_or_default(), "Version")?;
+    result.chain_id = field(lines.next().unwrap_or_default(), "Chain ID")?
+        .parse()
+        .map_err(|_| "invalid chain id")?;
+    result.nonce = field(lines.next().unwrap_or_default(), "Nonce")?;
+    result.issued_at = field(lines.next().unwrap_or_default(), "Issued At")?;
+
+    let mut next = lines.next();
+    if let Some(value) = next.and_then(|l| l.strip_prefix("Expiration Time: ")) {
+        result.expiration_time = value.to_string();
+        next = lines.next();
+    }
+    if let Some(value) = next.and_then(|l| l.strip_prefix("Not Before: ")) {
+        result.not_before = value.to_string();
+        next = lines.next();
+    }
+    if let Some(value) = next.and_then(|l| l.strip_prefix("Request ID: ")) {
+        result.request_id = value.to_string();
+        next = lines.next();
+    }
+    if next == Some("Resources:") {
+        for resource in lines.by_ref() {
+            let uri = resource
+                .strip_prefix("- ")
+                .ok_or_else(|| format!("invalid resource: {}", resource))?;
+            result.resources.push(uri.to_string());
+        }
+        next = None;
+    }
+
+    match next {
+        None | Some("") if lines.all(|l| l.is_empty()) => Ok(result),
+        _ => Err("unexpected trailing content".into()),
+    }
+}
+
+/**
+ * Parses and checks a SIWE message, then recovers the signer and compares it
+ * with the address in the message.
+ */
+pub fn verify(message: &str, signature: &str) -> SiweResult {
+    let mut result = match parse(message) {
+        Ok(result) => result,
+        Err(e) => {
+            return SiweResult {
+                err_msg: e,
+                ..Default::default()
+            }
+        }
+    };
+
+    match check(&result, message, signature) {
+        Ok(()) => result.success = true,
+        Err(e) => result.err_msg = e,
+    }
+
+    result
+}
+
+fn check(result: &SiweResult, message: &str, signature: &str) -> Result<(), String> {
+    if result.version != SUPPORTED_VERSION {
+        return Err(format!("unsupported version: {}", result.version));
+    }
+
+    if result.chain_id == 0 {
+        return Err("invalid chain id".into());
+    }
+
+    if result.nonce.len() < MIN_NONCE_LENGTH
+        || !result.nonce.chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        return Err("nonce must be at least 8 alphanumeric characters".into());
+    }
+
+    let now = Utc::now();
+
+    let issued_at = timestamp(&result.issued_at, "issued at")?;
+    if issued_at > now {
+        return Err("message is issued in the future".into());
+    }
+
+    if !result.expiration_time.is_empty() {
+        let expiration_time = timestamp(&result.expiration_time, "expiration time")?;
+        if expiration_time <= now {
+            return Err("message has expired".into());
+        }
+    }
+
+    if !result.not_before.is_empty() {
+        let not_before = timestamp(&result.not_before, "not before")?;
+        if not_before > now {
+            return Err("message is not yet valid".into());
+        }
+    }
+
+    let signer = recover_address(message, signature).map_err(|e| e.to_string())?;
+    if signer.to_lowercase() != result.address.to_lowercase() {
+        return Err(format!(
+            "signer {} does not match {}",
+            signer, result.address
+        ));
+    }
+
+    Ok(())
+}
+
+fn field(line: &str, name: &str) -> Result<String, String> {
+    line.strip_prefix(name)
+        .and_then(|l| l.strip_prefix(": "))
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_string())
+        .ok_or_else(|| format!("missing {}", name))
+}
+
+fn timestamp(value: &str, name: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|_| format!("invalid {}: {}", name, value))
+}
+
+/**
+ * EIP-4361 requires an EIP-55 checksummed address
+ */
+fn check_address(address: &str) -> Result<(), String> {
+    let hex = address
+        .strip_prefix("0x")
+        .ok_or("address must start with 0x")?;
+
+    if hex.len() != 40 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid address: {}", address));
+    }
+
+    if to_checksum_address(address) != address {
+        return Err(format!("address is not EIP-55 checksummed: {}", address));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secp256k1::sign;
+
+    // Example message from EIP-4361
+    const SPEC_EXAMPLE: &str = "service.invalid wants you to sign in with your Ethereum account:
+0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2
+
+I accept the ServiceOrg Terms of Service: https://service.invalid/tos
+
+URI: https://service.invalid/login
+Version: 1
+Chain ID: 1
+Nonce: 32891756
+Issued At: 2021-09-30T16:25:24Z
+Resources:
+- ipfs://bafybeiemxf5abjwjbikoz4mc3a3dla6ual3jsgpdr4cjr3oz3evfyavhwq/
+- https://example.com/my-web2-claim.json";
+
+    const SECRET: &str = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+    const ADDRESS: &str = "0x2c7536E3605D9C16a7a3D7b1898e529396a65c23";
+    const OTHER_SECRET: &str = "0x8da4ef21b864d2cc526dbdb2a120bd2874c36c9d0a1fb7f8c63d7f7a8b41de8f";
+
+    fn message(address: &str, optional: &[&str]) -> String {
+        let mut lines = vec![
+            format!("example.com wants you to sign in with your Ethereum account:\n{}\n\nSign in to the node\n", address),
+            "URI: https://example.com/login".to_string(),
+            "Version: 1".to_string(),
+            "Chain ID: 1".to_string(),
+            "Nonce: 32891756".to_string(),
+            "Issued At: 2021-09-30T16:25:24Z".to_string(),
+        ];
+        lines.extend(optional.iter().map(|l| l.to_string()));
+        lines.join("\n")
+    }
+
+    #[test]
+    fn spec_example_is_parsed() {
+        let result = parse(SPEC_EXAMPLE).unwrap();
+
+        assert_eq!(result.domain, "service.invalid");
+        assert_eq!(result.address, "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+        assert_eq!(
+            result.statement,
+            "I accept the ServiceOrg Terms of Service: https://service.invalid/tos"
+        );
+        assert_eq!(result.uri, "https://service.invalid/login");
+        assert_eq!(result.version, "1");
+        assert_eq!(result.chain_id, 1);
+        assert_eq!(result.nonce, "32891756");
+        assert_eq!(result.issued_at, "2021-09-30T16:25:24Z");
+        assert_eq!(
+            result.resources,
+            vec![
+                "ipfs://bafybeiemxf5abjwjbikoz4mc3a3dla6ual3jsgpdr4cjr3oz3evfyavhwq/",
+                "https://example.com/my-web2-claim.json",
+            ]
+        );
+    }
+
+    #[test]
+    fn spec_example_without_optional_fields_is_parsed() {
+        let statement = "I accept the ServiceOrg Terms of Service: https://service.invalid/tos\n";
+        let bare = SPEC_EXAMPLE
+            .replace(statement, "")
+            .split("\nResources:")
+            .next()
+            .unwrap()
+            .to_string();
+
+        let result = parse(&bare).unwrap();
+        assert_eq!(result.statement, "");
+        assert!(result.resources.is_empty());
+        assert_eq!(result.expiration_time, "");
+        assert_eq!(result.uri, "https://service.invalid/login");
+
+        // older signers leave out the empty statement line
+        let legacy = bare.replace("\n\n\nURI", "\n\nURI");
+        assert_eq!(parse(&legacy).unwrap().uri, "https://service.invalid/login");
+
+        let expiring = format!("{}\nExpiration Time: 2100-01-01T00:00:00Z", bare);
+        let result = parse(&expiring).unwrap();
+        assert_eq!(result.expiration_time, "2100-01-01T00:00:00Z");
+        assert!(result.resources.is_empty());
+    }
+
+    #[test]
+    fn signed_message_is_verified() {
+        let message = message(ADDRESS, &["Expiration Time: 2100-01-01T00:00:00Z"]);
+        let result = verify(&message, &sign(&message, SECRET).unwrap());
+
+        assert!(result.success, "{}", result.err_msg);
+        assert_eq!(result.address, ADDRESS);
+    }
+
+    #[test]
+    fn non_checksummed_address_is_rejected() {
+        let message = message(&ADDRESS.to_lowercase(), &[]);
+        let result = verify(&message, &sign(&message, SECRET).unwrap());
+
+        assert!(!result.success);
+        assert!(result.err_msg.contains("EIP-55"), "{}", result.err_msg);
+    }
+
+    #[test]
+    fn expired_message_is_rejected() {
+        let message = message(ADDRESS, &["Expiration Time: 2021-10-01T00:00:00Z"]);
+        let result = verify(&message, &sign(&message, SECRET).unwrap());
+
+        assert!(!result.success);
+        assert_eq!(result.err_msg, "message has expired");
+    }
+
+    #[test]
+    fn future_not_before_is_rejected() {
+        let message = message(ADDRESS, &["Not Before: 2100-01-01T00:00:00Z"]);
+        let result = verify(&message, &sign(&message, SECRET).unwrap());
+
+        assert!(!result.success);
+        assert_eq!(result.err_msg, "message is not yet valid");
+    }
+
+    #[test]
+    fn other_signer_is_rejected() {
+        let message = message(ADDRESS, &[]);
+        let result = verify(&message, &sign(&message, OTHER_SECRET).unwrap());
+
+        assert!(!result.success);
+        assert!(result.err_msg.starts_with("signer "), "{}", result.err_msg);
+        assert!(result
+            .err_msg
+            .ends_with(&format!("does not match {}", ADDRESS)));
+    }
+}
@@ -0,0 +1,132 @@
+use base58::FromBase58;
+use blake2::{Blake2b512, Digest};
+use schnorrkel::{PublicKey, Signature};
+
+use crate::error::CryptoError;
+
+pub const ENC: &str = "sr25519";
+
+/// Signing context used by Substrate and polkadot.js
+const SIGNING_CONTEXT: &[u8] = b"substrate";
+const SS58_CHECKSUM_PREFIX: &[u8] = b"SS58PRE";
+const SS58_CHECKSUM_LENGTH: usize = 2;
+
+/// polkadot.js `signRaw` wraps the payload in these tags before signing
+const WRAP_PREFIX: &str = "<Bytes>";
+const WRAP_POSTFIX: &str = "</Bytes>";
+
+/**
+ * Decodes an SS58 address into its network prefix and 32 byte public key,
+ * validating the blake2b checksum
+ */
+pub fn decode_ss58(address: &str) -> Option<(u16, [u8; 32])> {
+    let bytes = address.from_base58().ok()?;
+
+    let prefix_length = match bytes.first()? {
+        0..=63 => 1,
+        64..=127 => 2,
+        _ => return None,
+    };
+    if bytes.len() != prefix_length + 32 + SS58_CHECKSUM_LENGTH {
+        return None;
+    }
+
+    let (payload, checksum) = bytes.split_at(bytes.len() - SS58_CHECKSUM_LENGTH);
+    let hash = Blake2b512::new()
+        .chain_update(SS58_CHECKSUM_PREFIX)
+        .chain_update(payload)
+        .finalize();
+    if &hash[..SS58_CHECKSUM_LENGTH] != checksum {
+        return None;
+    }
+
+    let prefix = if prefix_length == 1 {
+        payload[0] as u16
+    } else {
+        // two byte prefixes pack 14 bits, see the SS58 specification
+        let lower = ((payload[0] as u16 & 0b0011_1111) << 2) | (payload[1] as u16 >> 6);
+        let upper = payload[1] as u16 & 0b0011_1111;
+        lower | (upper << 8)
+    };
+
+    let key = payload[prefix_length..].try_into().ok()?;
+
+    Some((prefix, key))
+}
+
+/**
+ * Verifies a hex encoded sr25519 signature against an SS58 address or a hex
+ * public key. Signatures over the `<Bytes>` wrapped message are accepted too.
+ */
+pub fn verify(public_key: &str, signature: &str, message: &str) -> Result<(), CryptoError> {
+    let invalid_key = || CryptoError::InvalidPublicKey(public_key.to_string());
+
+    let key_bytes = match decode_ss58(public_key) {
+        Some((_, key)) => key.to_vec(),
+        None => hex::decode(public_key.trim_start_matches("0x")).map_err(|_| invalid_key())?,
+    };
+    let key = PublicKey::from_bytes(&key_bytes).map_err(|_| invalid_key())?;
+
+    let sign_decoded = hex::decode(signature.trim_start_matches("0x"))
+        .map_err(|e| CryptoError::InvalidSignature(e.to_string()))?;
+    let sign = Signature::from_bytes(&sign_decoded)
+        .map_err(|e| CryptoError::InvalidSignature(e.to_string()))?;
+
+    let wrapped = format!("{}{}{}", WRAP_PREFIX, message, WRAP_POSTFIX);
+
+    key.verify_simple(SIGNING_CONTEXT, message.as_bytes(), &sign)
+        .or_else(|_| key.verify_simple(SIGNING_CONTEXT, wrapped.as_bytes(), &sign))
+        .map_err(|_| CryptoError::InvalidSignature("signature doesn't verify".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base58::ToBase58;
+
+    // substrate dev account //Alice
+    const ALICE: &str = "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY";
+    const ALICE_HEX: &str = "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
+
+    const MESSAGE: &str = "This is a test message";
+    // sr25519 signatures are randomised, these were produced once with the
+    // //Alice key over MESSAGE and over `<Bytes>MESSAGE</Bytes>` as signRaw does
+    const RAW_SIGNATURE: &str = "0x6a4a516c1ab23f5bbb2b702c9756434b974e4cba822a1f77232d74dc006a536097500b903995aabb0d054aa77287abe67f211ba1b4338457054c5035d4ba7184";
+    const WRAPPED_SIGNATURE: &str = "0xd4ab78efee77acf566b61df3fd3ce6661ed97b477be88c7919ea60b04c15e812f6d5fda9bc544c10fd0f84e1c08fb7f2897cdb133085adc85504f950a813138e";
+
+    #[test]
+    fn decodes_ss58_address() {
+        let (prefix, key) = decode_ss58(ALICE).unwrap();
+
+        assert_eq!(prefix, 42);
+        assert_eq!(hex::encode(key), ALICE_HEX);
+    }
+
+    #[test]
+    fn rejects_ss58_with_bad_checksum() {
+        let mut bytes = ALICE.from_base58().unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x01;
+
+        assert!(decode_ss58(&bytes.to_base58()).is_none());
+        assert!(verify(&bytes.to_base58(), WRAPPED_SIGNATURE, MESSAGE).is_err());
+    }
+
+    #[test]
+    fn verifies_raw_signature() {
+        assert!(verify(ALICE, RAW_SIGNATURE, MESSAGE).is_ok());
+        assert!(verify(ALICE_HEX, RAW_SIGNATURE, MESSAGE).is_ok());
+    }
+
+    #[test]
+    fn verifies_bytes_wrapped_signature() {
+        assert!(verify(ALICE, WRAPPED_SIGNATURE, MESSAGE).is_ok());
+        assert!(verify(&format!("0x{}", ALICE_HEX), WRAPPED_SIGNATURE, MESSAGE).is_ok());
+    }
+
+    #[test]
+    fn rejects_another_message() {
+        assert!(verify(ALICE, RAW_SIGNATURE, "This is another message").is_err());
+        assert!(verify(ALICE, WRAPPED_SIGNATURE, "This is another message").is_err());
+    }
+}
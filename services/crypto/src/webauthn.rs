@@ -0,0 +1,265 @@
+use base58::FromBase58;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::CryptoError;
+
+pub const ENC: &str = "p256";
+
+/// Multicodec varint prefix for p256-pub
+pub const MULTICODEC_P256_PUB: [u8; 2] = [0x80, 0x24];
+
+const CLIENT_DATA_TYPE_GET: &str = "webauthn.get";
+const AUTHENTICATOR_DATA_MIN_LENGTH: usize = 37;
+const FLAG_USER_PRESENT: u8 = 0x01;
+
+/// Module env holding the relying party id passkeys are scoped to
+pub const RP_ID_ENV: &str = "WEBAUTHN_RP_ID";
+/// Module env holding the origin the client data must come from
+pub const ORIGIN_ENV: &str = "WEBAUTHN_ORIGIN";
+
+/**
+ * The relying party assertions are accepted for, read from the module envs
+ */
+#[derive(Debug, Clone)]
+pub struct RelyingParty {
+    pub id: String,
+    pub origin: String,
+}
+
+impl RelyingParty {
+    pub fn from_env() -> Result<Self, CryptoError> {
+        let read = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .filter(|value| !value.is_empty())
+                .ok_or_else(|| CryptoError::InvalidSignature(format!("{} is not configured", name)))
+        };
+
+        Ok(RelyingParty {
+            id: read(RP_ID_ENV)?,
+            origin: read(ORIGIN_ENV)?,
+        })
+    }
+}
+
+/**
+ * The fields of an AuthenticatorAssertionResponse, base64url encoded, sent
+ * as the transaction signature
+ */
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Assertion {
+    pub authenticator_data: String,
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+/**
+ * Verifies a WebAuthn assertion made with a passkey for the configured
+ * relying party, see `verify_for`
+ */
+pub fn verify(public_key: &str, signature: &str, message: &str) -> Result<(), CryptoError> {
+    verify_for(&RelyingParty::from_env()?, public_key, signature, message)
+}
+
+/**
+ * Verifies a WebAuthn assertion made with a passkey. The challenge must be the
+ * sha256 of `message`, the rpIdHash must be the sha256 of the relying party id
+ * and the client data must come from its origin. The authenticator signs
+ * `authenticatorData || sha256(clientDataJSON)` with P-256.
+ * The public key is a multibase p256-pub key, bare or as a did:key. Hex SEC1
+ * keys are refused, they cannot be told apart from secp256k1 keys.
+ */
+pub fn verify_for(
+    relying_party: &RelyingParty,
+    public_key: &str,
+    signature: &str,
+    message: &str,
+) -> Result<(), CryptoError> {
+    let key = parse_public_key(public_key)?;
+
+    let assertion: Assertion = serde_json::from_str(signature)
+        .map_err(|e| CryptoError::InvalidSignature(format!("invalid assertion: {}", e)))?;
+
+    let authenticator_data = decode(&assertion.authenticator_data, "authenticatorData")?;
+    let client_data_json = decode(&assertion.client_data_json, "clientDataJSON")?;
+    let der = decode(&assertion.signature, "signature")?;
+
+    if authenticator_data.len() < AUTHENTICATOR_DATA_MIN_LENGTH {
+        return Err(CryptoError::InvalidSignature(
+            "authenticatorData is too short".to_string(),
+        ));
+    }
+    if authenticator_data[..32] != Sha256::digest(relying_party.id.as_bytes())[..] {
+        return Err(CryptoError::InvalidSignature(
+            "rpIdHash does not match the relying party".to_string(),
+        ));
+    }
+    if authenticator_data[32] & FLAG_USER_PRESENT == 0 {
+        return Err(CryptoError::InvalidSignature(
+            "user presence flag is not set".to_string(),
+        ));
+    }
+
+    let client_data: ClientData = serde_json::from_slice(&client_data_json)
+        .map_err(|e| CryptoError::InvalidSignature(format!("invalid clientDataJSON: {}", e)))?;
+
+    if client_data.kind != CLIENT_DATA_TYPE_GET {
+        return Err(CryptoError::InvalidSignature(format!(
+            "unexpected client data type {}",
+            client_data.kind
+        )));
+    }
+
+    if client_data.origin != relying_party.origin {
+        return Err(CryptoError::InvalidSignature(format!(
+            "unexpected origin {}",
+            client_data.origin
+        )));
+    }
+
+    let challenge = decode(&client_data.challenge, "challenge")?;
+    if challenge != Sha256::digest(message.as_bytes()).as_slice() {
+        return Err(CryptoError::InvalidSignature(
+            "challenge does not match the message hash".to_string(),
+        ));
+    }
+
+    let signature = Signature::from_der(&der)
+        .map_err(|_| CryptoError::InvalidSignature("invalid DER signature".to_string()))?;
+
+    let mut signed = authenticator_data;
+    signed.extend_from_slice(&Sha256::digest(&client_data_json));
+
+    key.verify(&signed, &signature)
+        .map_err(|_| CryptoError::InvalidSignature("signature doesn't verify".to_string()))
+}
+
+/**
+ * Compressed SEC1 bytes behind a multibase p256-pub key, if it is one
+ */
+pub fn decode_multibase(public_key: &str) -> Option<Vec<u8>> {
+    let bytes = public_key.strip_prefix('z')?.from_base58().ok()?;
+
+    if bytes.len() != 35 || bytes[..2] != MULTICODEC_P256_PUB {
+        return None;
+    }
+    VerifyingKey::from_sec1_bytes(&bytes[2..]).ok()?;
+
+    Some(bytes[2..].to_vec())
+}
+
+fn parse_public_key(public_key: &str) -> Result<VerifyingKey, CryptoError> {
+    let multibase = public_key.strip_prefix("did:key:").unwrap_or(public_key);
+    let bytes = decode_multibase(multibase)
+        .ok_or_else(|| CryptoError::InvalidPublicKey(public_key.to_string()))?;
+
+    VerifyingKey::from_sec1_bytes(&bytes)
+        .map_err(|_| CryptoError::InvalidPublicKey(public_key.to_string()))
+}
+
+fn decode(value: &str, name: &str) -> Result<Vec<u8>, CryptoError> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| CryptoError::InvalidSignature(format!("{} is not base64url", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base58::ToBase58;
+    use p256::ecdsa::signature::Signer;
+    use p256::ecdsa::SigningKey;
+
+    const MESSAGE: &str = "hello";
+
+    fn relying_party() -> RelyingParty {
+        RelyingParty {
+            id: "example.com".to_string(),
+            origin: "https://example.com".to_string(),
+        }
+    }
+
+    fn assertion(key: &SigningKey, rp_id: &str, origin: &str) -> String {
+        let mut authenticator_data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        authenticator_data.extend_from_slice(&[FLAG_USER_PRESENT, 0, 0, 0, 1]);
+
+        let client_data_json = format!(
+            r#"{{"type":"{}","challenge":"{}","origin":"{}"}}"#,
+            CLIENT_DATA_TYPE_GET,
+            URL_SAFE_NO_PAD.encode(Sha256::digest(MESSAGE.as_bytes())),
+            origin
+        );
+
+        let mut signed = authenticator_data.clone();
+        signed.extend_from_slice(&Sha256::digest(client_data_json.as_bytes()));
+        let signature: Signature = key.sign(&signed);
+
+        serde_json::json!({
+            "authenticatorData": URL_SAFE_NO_PAD.encode(&authenticator_data),
+            "clientDataJSON": URL_SAFE_NO_PAD.encode(client_data_json.as_bytes()),
+            "signature": URL_SAFE_NO_PAD.encode(signature.to_der().as_bytes()),
+        })
+        .to_string()
+    }
+
+    // multibase p256-pub, the form `key::parse` reports
+    fn public_key(key: &SigningKey) -> String {
+        let mut bytes = MULTICODEC_P256_PUB.to_vec();
+        bytes.extend_from_slice(key.verifying_key().to_encoded_point(true).as_bytes());
+
+        format!("z{}", bytes.to_base58())
+    }
+
+    #[test]
+    fn accepts_the_relying_party() {
+        let key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let signature = assertion(&key, "example.com", "https://example.com");
+
+        assert!(verify_for(&relying_party(), &public_key(&key), &signature, MESSAGE).is_ok());
+    }
+
+    #[test]
+    fn rejects_another_rp_id() {
+        let key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let signature = assertion(&key, "evil.com", "https://example.com");
+
+        assert!(verify_for(&relying_party(), &public_key(&key), &signature, MESSAGE).is_err());
+    }
+
+    #[test]
+    fn accepts_did_key_and_refuses_hex_keys() {
+        let key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let signature = assertion(&key, "example.com", "https://example.com");
+        let did_key = format!("did:key:{}", public_key(&key));
+        let hex_key = hex::encode(key.verifying_key().to_encoded_point(true).as_bytes());
+
+        assert!(verify_for(&relying_party(), &did_key, &signature, MESSAGE).is_ok());
+        assert!(matches!(
+            verify_for(&relying_party(), &hex_key, &signature, MESSAGE),
+            Err(CryptoError::InvalidPublicKey(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_another_origin() {
+        let key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let signature = assertion(&key, "example.com", "https://evil.com");
+
+        assert!(verify_for(&relying_party(), &public_key(&key), &signature, MESSAGE).is_err());
+    }
+}
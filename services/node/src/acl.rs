@@ -0,0 +1,493 @@
+use marine_rs_sdk::marine;
+use marine_sqlite_connector::{State, Statement, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::clock::physical_now;
+use crate::defaults::{
+    ACL_PERMISSION_APPEND, ACL_PERMISSION_READ_PRIVATE, ACL_PERMISSION_WRITE, ACL_TABLE_NAME,
+    METHOD_GRANT, METHOD_REVOKE, STATUS_FAILED, STATUS_SUCCESS,
+};
+use crate::error::ServiceError;
+use crate::error::ServiceError::{
+    InvalidDataFormatForMethodType, InvalidMethod, InvalidOwner, RecordNotFound, ReplayedPayload,
+};
+use crate::privacy::{reseal_private_heads, rewrap_private_envelopes};
+use crate::storage_impl::{get_storage, Storage};
+use crate::transaction::{SignedScope, Transaction};
+
+/**
+ * Right given by `owner` to `grantee` on the owner's rows of a data key.
+ * An empty alias covers every alias of the data key, `expires_at` = 0 never
+ * expires.
+ */
+#[marine]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Acl {
+    pub data_key: String,
+    pub alias: String,
+    pub owner: String,
+    pub grantee: String,
+    pub permission: String,
+    pub expires_at: u64,
+    pub transaction_hash: String,
+    pub timestamp: u64,
+}
+
+/**
+ * `data` of a "grant" or "revoke" transaction
+ */
+#[derive(Debug, Default, Deserialize)]
+pub struct SerdeAcl {
+    pub grantee: String,
+    pub permission: String,
+    #[serde(default)]
+    pub expires_at: u64,
+    #[serde(flatten)]
+    pub scope: SignedScope,
+}
+
+impl Storage {
+    pub fn create_acl_table(&self) {
+        let table_schema = format!(
+            "
+            CREATE TABLE IF NOT EXISTS {} (
+                data_key TEXT NOT NULL,
+                alias varchar(255) NOT NULL,
+                owner TEXT NOT NULL,
+                grantee TEXT NOT NULL,
+                permission varchar(32) NOT NULL,
+                expires_at INTEGER NOT NULL,
+                transaction_hash TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                UNIQUE(data_key, alias, owner, grantee, permission)
+            );",
+            ACL_TABLE_NAME
+        );
+
+        let result = self.connection.execute(table_schema);
+
+        if let Err(error) = result {
+            println!("create_acl_table error: {}", error);
+        }
+    }
+
+    pub fn write_acl(&self, acl: Acl) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "INSERT OR REPLACE INTO {ACL_TABLE_NAME} (data_key, alias, owner, grantee, permission, expires_at, transaction_hash, timestamp) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+        ))?;
+
+        statement.bind(1, &Value::String(acl.data_key))?;
+        statement.bind(2, &Value::String(acl.alias))?;
+        statement.bind(3, &Value::String(acl.owner))?;
+        statement.bind(4, &Value::String(acl.grantee))?;
+        statement.bind(5, &Value::String(acl.permission))?;
+        statement.bind(6, &Value::Integer(acl.expires_at as i64))?;
+        statement.bind(7, &Value::String(acl.transaction_hash))?;
+        statement.bind(8, &Value::Integer(acl.timestamp as i64))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    pub fn get_acl(
+        &self,
+        data_key: String,
+        alias: String,
+        owner: String,
+        grantee: String,
+        permission: String,
+    ) -> Result<Acl, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {ACL_TABLE_NAME} WHERE data_key = ? AND alias = ? AND owner = ? AND grantee = ? AND permission = ?"
+        ))?;
+
+        statement.bind(1, &Value::String(data_key.clone()))?;
+        statement.bind(2, &Value::String(alias.clone()))?;
+        statement.bind(3, &Value::String(owner))?;
+        statement.bind(4, &Value::String(grantee.clone()))?;
+        statement.bind(5, &Value::String(permission.clone()))?;
+
+        if let State::Row = statement.next()? {
+            read(&statement)
+        } else {
+            Err(RecordNotFound(f!(
+                "{permission} on {data_key} {alias} for {grantee}"
+            )))
+        }
+    }
+
+    pub fn delete_acl(
+        &self,
+        data_key: String,
+        alias: String,
+        owner: String,
+        grantee: String,
+        permission: String,
+    ) -> Result<(), ServiceError> {
+        self.get_acl(
+            data_key.clone(),
+            alias.clone(),
+            owner.clone(),
+            grantee.clone(),
+            permission.clone(),
+        )?;
+
+        let mut statement = self.connection.prepare(f!(
+            "DELETE FROM {ACL_TABLE_NAME} WHERE data_key = ? AND alias = ? AND owner = ? AND grantee = ? AND permission = ?"
+        ))?;
+
+        statement.bind(1, &Value::String(data_key))?;
+        statement.bind(2, &Value::String(alias))?;
+        statement.bind(3, &Value::String(owner))?;
+        statement.bind(4, &Value::String(grantee))?;
+        statement.bind(5, &Value::String(permission))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    pub fn get_all_acls(&self) -> Result<Vec<Acl>, ServiceError> {
+        let mut statement = self
+            .connection
+            .prepare(f!("SELECT * FROM {ACL_TABLE_NAME} ORDER BY timestamp ASC"))?;
+
+        let mut acls = Vec::new();
+
+        while let State::Row = statement.next()? {
+            acls.push(read(&statement)?);
+        }
+
+        Ok(acls)
+    }
+
+    /**
+     * Grants on a data key, optionally limited to one owner
+     */
+    pub fn get_acls(&self, data_key: String, owner: String) -> Result<Vec<Acl>, ServiceError> {
+        let mut statement = if owner.is_empty() {
+            let mut statement = self.connection.prepare(f!(
+                "SELECT * FROM {ACL_TABLE_NAME} WHERE data_key = ? ORDER BY timestamp ASC"
+            ))?;
+            statement.bind(1, &Value::String(data_key))?;
+            statement
+        } else {
+            let mut statement = self.connection.prepare(f!(
+                "SELECT * FROM {ACL_TABLE_NAME} WHERE data_key = ? AND owner = ? ORDER BY timestamp ASC"
+            ))?;
+            statement.bind(1, &Value::String(data_key))?;
+            statement.bind(2, &Value::String(owner))?;
+            statement
+        };
+
+        let mut acls = Vec::new();
+
+        while let State::Row = statement.next()? {
+            acls.push(read(&statement)?);
+        }
+
+        Ok(acls)
+    }
+
+    /**
+     * Whether `grantee` holds one of `permissions` on the owner's alias,
+     * through a grant on the alias itself or on the whole data key
+     */
+    pub fn has_permission(
+        &self,
+        data_key: String,
+        alias: String,
+        owner: String,
+        grantee: String,
+        permissions: &[&str],
+    ) -> Result<bool, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {ACL_TABLE_NAME} WHERE data_key = ? AND owner = ? AND grantee = ? AND (alias = ? OR alias = '') AND (expires_at = 0 OR expires_at > ?)"
+        ))?;
+
+        statement.bind(1, &Value::String(data_key))?;
+        statement.bind(2, &Value::String(owner))?;
+        statement.bind(3, &Value::String(grantee))?;
+        statement.bind(4, &Value::String(alias))?;
+        statement.bind(5, &Value::Integer(physical_now() as i64))?;
+
+        while let State::Row = statement.next()? {
+            let acl = read(&statement)?;
+
+            if permissions.contains(&acl.permission.as_str()) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+pub fn is_valid_permission(permission: &str) -> bool {
+    permission == ACL_PERMISSION_WRITE
+        || permission == ACL_PERMISSION_APPEND
+        || permission == ACL_PERMISSION_READ_PRIVATE
+}
+
+/**
+ * Parses the `data` of a "grant" or "revoke" transaction and checks it
+ * against the transaction fields it was signed for
+ */
+pub fn parse_acl_request(
+    scope: &SignedScope,
+    public_key: &str,
+    data: &str,
+) -> Result<SerdeAcl, ServiceError> {
+    let method = &scope.method;
+    let request: SerdeAcl = serde_json::from_str(data)
+        .map_err(|e| InvalidDataFormatForMethodType(f!("{method}: {e}")))?;
+
+    request.scope.check(scope)?;
+
+    if scope.data_key.is_empty() || request.grantee.is_empty() || request.grantee == public_key {
+        return Err(InvalidDataFormatForMethodType(method.to_string()));
+    }
+
+    if !is_valid_permission(&request.permission) {
+        return Err(InvalidDataFormatForMethodType(f!(
+            "unknown permission {request.permission}"
+        )));
+    }
+
+    Ok(request)
+}
+
+/**
+ * Checks a "grant" or "revoke" transaction before it is recorded.
+ * The signer is always the owner, so a grant only ever covers the signer's rows.
+ * A payload is applied at most once, a new nonce is needed to grant again.
+ */
+pub fn check_acl_request(
+    storage: &Storage,
+    scope: &SignedScope,
+    public_key: &str,
+    data: &str,
+) -> Result<(), ServiceError> {
+    let request = parse_acl_request(scope, public_key, data)?;
+    let SignedScope {
+        method,
+        data_key,
+        alias,
+        nonce,
+        ..
+    } = scope;
+
+    if storage.is_applied_payload(public_key.to_string(), method.clone(), data.to_string())? {
+        return Err(ReplayedPayload(f!(
+            "{method} {data_key} {alias} nonce {nonce}"
+        )));
+    }
+
+    if method == METHOD_GRANT {
+        if request.expires_at != 0 && request.expires_at <= physical_now() {
+            return Err(InvalidDataFormatForMethodType(f!(
+                "expires_at {request.expires_at} is in the past"
+            )));
+        }
+    } else if method == METHOD_REVOKE {
+        storage.get_acl(
+            data_key.to_string(),
+            alias.to_string(),
+            public_key.to_string(),
+            request.grantee,
+            request.permission,
+        )?;
+    }
+
+    Ok(())
+}
+
+/**
+ * Validated "grant" and "revoke" method types
+ */
+pub fn validate_acl(transaction_hash: String) {
+    let storage = get_storage().expect("Internal error to database connector");
+    let mut transaction = storage.get_transaction(transaction_hash).unwrap().clone();
+
+    match apply_acl(&storage, &transaction) {
+        Ok(()) => {
+            transaction.status = STATUS_SUCCESS;
+            transaction.error_text = "".to_string();
+        }
+        Err(e) => {
+            transaction.status = STATUS_FAILED;
+            transaction.error_text = e.to_string();
+        }
+    }
+
+    let _ = storage.update_transaction_status(
+        transaction.hash.clone(),
+        transaction.status,
+        transaction.error_text.clone(),
+    );
+}
+
+fn apply_acl(storage: &Storage, transaction: &Transaction) -> Result<(), ServiceError> {
+    check_acl_request(
+        storage,
+        &SignedScope::from(transaction),
+        &transaction.public_key,
+        &transaction.data,
+    )?;
+
+    let request: SerdeAcl = serde_json::from_str(&transaction.data)
+        .map_err(|e| InvalidDataFormatForMethodType(e.to_string()))?;
+
+    let rewrap = request.permission == ACL_PERMISSION_READ_PRIVATE;
+
+    if transaction.method == METHOD_GRANT {
+        storage.write_acl(Acl {
+            data_key: transaction.data_key.clone(),
+            alias: transaction.alias.clone(),
+            owner: transaction.public_key.clone(),
+            grantee: request.grantee,
+            permission: request.permission,
+            expires_at: request.expires_at,
+            transaction_hash: transaction.hash.clone(),
+            timestamp: transaction.timestamp,
+        })?;
+    } else if transaction.method == METHOD_REVOKE {
+        storage.delete_acl(
+            transaction.data_key.clone(),
+            transaction.alias.clone(),
+            transaction.public_key.clone(),
+            request.grantee,
+            request.permission,
+        )?;
+    } else {
+        return Err(InvalidMethod(f!(
+            "{transaction.method} is not an acl method"
+        )));
+    }
+
+    // readers of private aliases changed, older blocks in IPFS are left as
+    // written and a revoked reader loses the current content with a fresh key
+    if rewrap {
+        rewrap_private_envelopes(
+            storage,
+            &transaction.data_key,
+            &transaction.alias,
+            &transaction.public_key,
+        )?;
+
+        if transaction.method == METHOD_REVOKE {
+            reseal_private_heads(storage, transaction)?;
+        }
+    }
+
+    Ok(())
+}
+
+/**
+ * Checks that `writer` may write the owner's alias. Keys of the owner's
+ * identity always may, delegates need "write", or "append" when the alias
+ * does not exist yet.
+ */
+pub fn check_write_access(
+    storage: &Storage,
+    data_key: &str,
+    alias: &str,
+    owner: &str,
+    writer: &str,
+    exists: bool,
+) -> Result<(), ServiceError> {
+    if storage.is_same_owner(owner.to_string(), writer.to_string())? {
+        return Ok(());
+    }
+
+    let permissions: &[&str] = if exists {
+        &[ACL_PERMISSION_WRITE]
+    } else {
+        &[ACL_PERMISSION_WRITE, ACL_PERMISSION_APPEND]
+    };
+
+    let allowed = storage.has_permission(
+        data_key.to_string(),
+        alias.to_string(),
+        owner.to_string(),
+        writer.to_string(),
+        permissions,
+    )?;
+
+    if allowed {
+        Ok(())
+    } else {
+        Err(InvalidOwner(f!(
+            "{writer} has no write access to {data_key} {alias} of {owner}"
+        )))
+    }
+}
+
+pub fn read(statement: &Statement) -> Result<Acl, ServiceError> {
+    Ok(Acl {
+        data_key: statement.read::<String>(0)?,
+        alias: statement.read::<String>(1)?,
+        owner: statement.read::<String>(2)?,
+        grantee: statement.read::<String>(3)?,
+        permission: statement.read::<String>(4)?,
+        expires_at: statement.read::<i64>(5)? as u64,
+        transaction_hash: statement.read::<String>(6)?,
+        timestamp: statement.read::<i64>(7)? as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grant(data_key: &str, alias: &str, nonce: i64) -> String {
+        serde_json::json!({
+            "grantee": "0xreader",
+            "permission": ACL_PERMISSION_WRITE,
+            "method": METHOD_GRANT,
+            "data_key": data_key,
+            "alias": alias,
+            "nonce": nonce,
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn grant_signed_for_the_transaction_is_accepted() {
+        let data = grant("dk", "profile", 1);
+        let scope = SignedScope::new(METHOD_GRANT, "", "dk", "profile", 1);
+        let request = parse_acl_request(&scope, "0xowner", &data).unwrap();
+
+        assert_eq!(request.grantee, "0xreader");
+    }
+
+    #[test]
+    fn grant_replayed_on_other_fields_is_rejected() {
+        let data = grant("dk", "profile", 1);
+        let sent = [
+            SignedScope::new(METHOD_REVOKE, "", "dk", "profile", 1),
+            SignedScope::new(METHOD_GRANT, "", "other", "profile", 1),
+            SignedScope::new(METHOD_GRANT, "", "dk", "", 1),
+            SignedScope::new(METHOD_GRANT, "", "dk", "profile", 2),
+        ];
+
+        for scope in sent.iter() {
+            assert!(parse_acl_request(scope, "0xowner", &data).is_err());
+        }
+    }
+
+    #[test]
+    fn unscoped_grant_is_rejected() {
+        let data = r#"{"grantee":"0xreader","permission":"write"}"#;
+        let scope = SignedScope::new(METHOD_GRANT, "", "dk", "", 0);
+
+        assert!(parse_acl_request(&scope, "0xowner", data).is_err());
+    }
+
+    #[test]
+    fn self_grant_is_rejected() {
+        let data = grant("dk", "profile", 1);
+        let scope = SignedScope::new(METHOD_GRANT, "", "dk", "profile", 1);
+
+        assert!(parse_acl_request(&scope, "0xreader", &data).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use marine_rs_sdk::marine;
+use marine_sqlite_connector::{State, Statement, Value};
+
+use crate::clock::physical_now;
+use crate::defaults::OPERATORS_TABLE_NAME;
+use crate::error::ServiceError;
+use crate::error::ServiceError::{RecordNotFound, Unauthorized};
+use crate::storage_impl::{get_storage, Storage};
+
+#[marine]
+#[derive(Debug, Default, Clone)]
+pub struct Operator {
+    pub peer_id: String,
+    pub added_by: String,
+    pub timestamp: u64,
+}
+
+impl Storage {
+    pub fn create_operators_table(&self) {
+        let table_schema = format!(
+            "
+            CREATE TABLE IF NOT EXISTS {} (
+                peer_id TEXT PRIMARY KEY UNIQUE,
+                added_by TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            );",
+            OPERATORS_TABLE_NAME
+        );
+
+        let result = self.connection.execute(table_schema);
+
+        if let Err(error) = result {
+            println!("create_operators_table error: {}", error);
+        }
+    }
+
+    pub fn write_operator(&self, operator: Operator) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "INSERT OR REPLACE INTO {OPERATORS_TABLE_NAME} (peer_id, added_by, timestamp) VALUES (?, ?, ?)"
+        ))?;
+
+        statement.bind(1, &Value::String(operator.peer_id))?;
+        statement.bind(2, &Value::String(operator.added_by))?;
+        statement.bind(3, &Value::Integer(operator.timestamp as i64))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    pub fn delete_operator(&self, peer_id: String) -> Result<(), ServiceError> {
+        if !self.is_operator(peer_id.clone())? {
+            return Err(RecordNotFound(f!("operator {peer_id}")));
+        }
+
+        let mut statement = self
+            .connection
+            .prepare(f!("DELETE FROM {OPERATORS_TABLE_NAME} WHERE peer_id = ?"))?;
+
+        statement.bind(1, &Value::String(peer_id))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    pub fn is_operator(&self, peer_id: String) -> Result<bool, ServiceError> {
+        let mut statement = self
+            .connection
+            .prepare(f!("SELECT * FROM {OPERATORS_TABLE_NAME} WHERE peer_id = ?"))?;
+
+        statement.bind(1, &Value::String(peer_id))?;
+
+        Ok(matches!(statement.next()?, State::Row))
+    }
+
+    pub fn get_operators(&self) -> Result<Vec<Operator>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {OPERATORS_TABLE_NAME} ORDER BY timestamp ASC"
+        ))?;
+
+        let mut operators = Vec::new();
+
+        while let State::Row = statement.next()? {
+            operators.push(read(&statement)?);
+        }
+
+        Ok(operators)
+    }
+}
+
+/**
+ * Allows the call only when it was initiated by the host peer or by one of
+ * the configured operators. Denied calls are logged with the caller.
+ */
+pub fn authorize(action: &str) -> Result<(), ServiceError> {
+    let cp = marine_rs_sdk::get_call_parameters();
+
+    check_caller(action, &cp.init_peer_id, &cp.host_id, |peer_id| {
+        get_storage()?.is_operator(peer_id.to_string())
+    })
+}
+
+/**
+ * Allows the call only when it was initiated by the host peer
+ */
+pub fn authorize_host(action: &str) -> Result<(), ServiceError> {
+    let cp = marine_rs_sdk::get_call_parameters();
+
+    check_host(action, &cp.init_peer_id, &cp.host_id)
+}
+
+/**
+ * The host is always allowed, any other caller only when `is_operator`
+ * confirms it. The operator lookup is skipped for the host, so the host keeps
+ * control even with an empty or unreadable operators table.
+ */
+fn check_caller<F>(
+    action: &str,
+    peer_id: &str,
+    host_id: &str,
+    is_operator: F,
+) -> Result<(), ServiceError>
+where
+    F: FnOnce(&str) -> Result<bool, ServiceError>,
+{
+    if peer_id == host_id || is_operator(peer_id)? {
+        Ok(())
+    } else {
+        deny(action, peer_id)
+    }
+}
+
+fn check_host(action: &str, peer_id: &str, host_id: &str) -> Result<(), ServiceError> {
+    if peer_id == host_id {
+        Ok(())
+    } else {
+        deny(action, peer_id)
+    }
+}
+
+fn deny(action: &str, peer_id: &str) -> Result<(), ServiceError> {
+    log::warn!("unauthorized call to {} from {}", action, peer_id);
+    Err(Unauthorized(f!(
+        "{peer_id} is not allowed to call {action}"
+    )))
+}
+
+pub fn add_operator(peer_id: String) -> Result<(), ServiceError> {
+    authorize_host("add_operator")?;
+
+    get_storage()?.write_operator(Operator {
+        peer_id,
+        added_by: marine_rs_sdk::get_call_parameters().init_peer_id,
+        timestamp: physical_now(),
+    })
+}
+
+pub fn remove_operator(peer_id: String) -> Result<(), ServiceError> {
+    authorize_host("remove_operator")?;
+
+    get_storage()?.delete_operator(peer_id)
+}
+
+pub fn read(statement: &Statement) -> Result<Operator, ServiceError> {
+    Ok(Operator {
+        peer_id: statement.read::<String>(0)?,
+        added_by: statement.read::<String>(1)?,
+        timestamp: statement.read::<i64>(2)? as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ServiceError::InternalError;
+
+    const HOST: &str = "12D3KooWHost";
+    const OPERATOR: &str = "12D3KooWOperator";
+    const STRANGER: &str = "12D3KooWStranger";
+
+    fn operators<'a>(list: &'a [&'a str]) -> impl Fn(&str) -> Result<bool, ServiceError> + 'a {
+        move |peer_id| Ok(list.contains(&peer_id))
+    }
+
+    #[test]
+    fn host_is_always_allowed() {
+        assert!(check_caller("action", HOST, HOST, operators(&[])).is_ok());
+        assert!(
+            check_caller("action", HOST, HOST, |_| -> Result<bool, ServiceError> {
+                panic!("the host must not need an operator lookup")
+            })
+            .is_ok()
+        );
+        assert!(check_host("action", HOST, HOST).is_ok());
+    }
+
+    #[test]
+    fn operator_is_allowed_but_not_for_host_only_calls() {
+        assert!(check_caller("action", OPERATOR, HOST, operators(&[OPERATOR])).is_ok());
+        assert!(matches!(
+            check_host("add_operator", OPERATOR, HOST),
+            Err(Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn stranger_is_denied() {
+        assert!(matches!(
+            check_caller("action", STRANGER, HOST, operators(&[OPERATOR])),
+            Err(Unauthorized(_))
+        ));
+        assert!(matches!(
+            check_host("action", STRANGER, HOST),
+            Err(Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn lookup_errors_deny_the_call() {
+        let result = check_caller("action", OPERATOR, HOST, |_| {
+            Err(InternalError("database is locked".to_string()))
+        });
+        assert!(matches!(result, Err(InternalError(_))));
+    }
+
+    #[test]
+    fn removing_the_last_operator_leaves_the_host_in_control() {
+        let mut list = vec![OPERATOR];
+        list.retain(|peer_id| *peer_id != OPERATOR);
+
+        assert!(matches!(
+            check_caller("action", OPERATOR, HOST, operators(&list)),
+            Err(Unauthorized(_))
+        ));
+        assert!(check_caller("action", HOST, HOST, operators(&list)).is_ok());
+        assert!(check_host("add_operator", HOST, HOST).is_ok());
+    }
+}
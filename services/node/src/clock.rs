@@ -0,0 +1,178 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::defaults::CLOCK_MAX_DRIFT;
+use crate::error::ServiceError;
+use crate::error::ServiceError::InvalidClock;
+
+/// Bits of a packed timestamp reserved for the logical counter
+pub const LOGICAL_BITS: u32 = 16;
+const LOGICAL_MASK: u64 = (1 << LOGICAL_BITS) - 1;
+/// Largest wall time that still fits a packed timestamp
+pub const MAX_WALL: u64 = u64::MAX >> LOGICAL_BITS;
+
+/**
+ * Hybrid logical clock timestamp.
+ * `wall` follows the physical clock in milliseconds, `logical` orders events
+ * that share the same wall time or arrive from a peer that is ahead of us.
+ */
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HybridTimestamp {
+    pub wall: u64,
+    pub logical: u64,
+}
+
+impl HybridTimestamp {
+    pub fn new(wall: u64, logical: u64) -> Self {
+        Self { wall, logical }.normalize()
+    }
+
+    /**
+     * Packs the timestamp into a single integer which sorts the same way as
+     * the timestamp itself, so it can be used directly as an ordering column.
+     * `tick` and `merge` never go past `MAX_WALL`, so this cannot wrap.
+     */
+    pub fn pack(&self) -> u64 {
+        (self.wall << LOGICAL_BITS) | (self.logical & LOGICAL_MASK)
+    }
+
+    pub fn unpack(packed: u64) -> Self {
+        Self {
+            wall: packed >> LOGICAL_BITS,
+            logical: packed & LOGICAL_MASK,
+        }
+    }
+
+    /**
+     * Advances the clock for a local event (send or write)
+     */
+    pub fn tick(&self, now: u64) -> Result<Self, ServiceError> {
+        let clock = if now > self.wall {
+            Self::new(now, 0)
+        } else {
+            Self::new(self.wall, self.logical + 1)
+        };
+
+        clock.check_bounds()
+    }
+
+    /**
+     * Advances the clock on receipt of a timestamp from a peer or an import.
+     * A remote wall time more than `CLOCK_MAX_DRIFT` ahead of `now` is
+     * rejected, it would drag the clock into the future for good.
+     */
+    pub fn merge(&self, remote: HybridTimestamp, now: u64) -> Result<Self, ServiceError> {
+        if remote.wall > now.saturating_add(CLOCK_MAX_DRIFT) {
+            return Err(InvalidClock(format!(
+                "remote wall time {} is {} ms ahead",
+                remote.wall,
+                remote.wall - now
+            )));
+        }
+
+        let wall = now.max(self.wall).max(remote.wall);
+
+        let logical = if wall == self.wall && wall == remote.wall {
+            self.logical.max(remote.logical) + 1
+        } else if wall == self.wall {
+            self.logical + 1
+        } else if wall == remote.wall {
+            remote.logical + 1
+        } else {
+            0
+        };
+
+        Self::new(wall, logical).check_bounds()
+    }
+
+    fn check_bounds(self) -> Result<Self, ServiceError> {
+        if self.wall > MAX_WALL {
+            Err(InvalidClock(format!("wall time {} overflows", self.wall)))
+        } else {
+            Ok(self)
+        }
+    }
+
+    /**
+     * Carries an overflowing logical counter into the wall time so that the
+     * packed form never wraps
+     */
+    fn normalize(self) -> Self {
+        if self.logical > LOGICAL_MASK {
+            Self {
+                wall: self.wall.saturating_add(1),
+                logical: 0,
+            }
+        } else {
+            self
+        }
+    }
+}
+
+pub fn physical_now() -> u64 {
+    let now = SystemTime::now();
+    let timestamp = now.duration_since(UNIX_EPOCH).expect("Time went backwards");
+
+    timestamp.as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick_is_monotonic() {
+        let clock = HybridTimestamp::new(100, 0);
+
+        assert_eq!(clock.tick(200).unwrap(), HybridTimestamp::new(200, 0));
+        assert_eq!(clock.tick(50).unwrap(), HybridTimestamp::new(100, 1));
+        assert!(clock.tick(100).unwrap() > clock);
+    }
+
+    #[test]
+    fn merge_takes_the_latest_clock() {
+        let local = HybridTimestamp::new(100, 3);
+        let remote = HybridTimestamp::new(150, 7);
+
+        assert_eq!(
+            local.merge(remote, 120).unwrap(),
+            HybridTimestamp::new(150, 8)
+        );
+        assert_eq!(
+            local.merge(remote, 200).unwrap(),
+            HybridTimestamp::new(200, 0)
+        );
+        assert_eq!(
+            local.merge(HybridTimestamp::new(100, 9), 50).unwrap(),
+            HybridTimestamp::new(100, 10)
+        );
+    }
+
+    #[test]
+    fn merge_rejects_drift() {
+        let local = HybridTimestamp::new(100, 0);
+        let remote = HybridTimestamp::new(100 + CLOCK_MAX_DRIFT + 1, 0);
+
+        assert!(local.merge(remote, 100).is_err());
+        assert!(local.merge(remote, 99).is_err());
+        assert!(local.merge(remote, 101).is_ok());
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        let clock = HybridTimestamp::new(MAX_WALL, LOGICAL_MASK);
+
+        assert!(clock.tick(0).is_err());
+        assert!(HybridTimestamp::new(0, 0).tick(MAX_WALL + 1).is_err());
+        assert!(HybridTimestamp::new(MAX_WALL, 0).tick(0).is_ok());
+    }
+
+    #[test]
+    fn packed_form_keeps_order() {
+        let earlier = HybridTimestamp::new(100, LOGICAL_MASK);
+        let later = earlier.tick(0).unwrap();
+
+        assert_eq!(later, HybridTimestamp::new(101, 0));
+        assert!(later.pack() > earlier.pack());
+        assert_eq!(HybridTimestamp::unpack(later.pack()), later);
+    }
+}
@@ -0,0 +1,76 @@
+use crate::clock::{physical_now, HybridTimestamp};
+use crate::defaults::CLOCK_TABLE_NAME;
+use crate::error::ServiceError;
+use crate::storage_impl::Storage;
+use marine_sqlite_connector::{State, Value};
+
+impl Storage {
+    /**
+     * The clock table holds a single row with the last issued hybrid timestamp
+     * so that the clock never goes backwards across service restarts.
+     */
+    pub fn create_clock_table(&self) {
+        let table_schema = format!(
+            "
+            CREATE TABLE IF NOT EXISTS {} (
+                id INTEGER PRIMARY KEY CHECK (id = 0),
+                wall INTEGER NOT NULL,
+                logical INTEGER NOT NULL
+            );",
+            CLOCK_TABLE_NAME
+        );
+
+        let result = self.connection.execute(table_schema);
+
+        if let Err(error) = result {
+            println!("create_clock_table error: {}", error);
+        }
+    }
+
+    pub fn get_clock(&self) -> Result<HybridTimestamp, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT wall, logical FROM {CLOCK_TABLE_NAME} WHERE id = 0"
+        ))?;
+
+        if let State::Row = statement.next()? {
+            Ok(HybridTimestamp::new(
+                statement.read::<i64>(0)? as u64,
+                statement.read::<i64>(1)? as u64,
+            ))
+        } else {
+            Ok(HybridTimestamp::default())
+        }
+    }
+
+    fn write_clock(&self, clock: HybridTimestamp) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "INSERT OR REPLACE INTO {CLOCK_TABLE_NAME} (id, wall, logical) VALUES (0, ?, ?)"
+        ))?;
+
+        statement.bind(1, &Value::Integer(clock.wall as i64))?;
+        statement.bind(2, &Value::Integer(clock.logical as i64))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    /**
+     * Issues a new timestamp for a local event
+     */
+    pub fn tick_clock(&self) -> Result<HybridTimestamp, ServiceError> {
+        let clock = self.get_clock()?.tick(physical_now())?;
+        self.write_clock(clock)?;
+
+        Ok(clock)
+    }
+
+    /**
+     * Merges a timestamp received from a peer or an imported record
+     */
+    pub fn merge_clock(&self, remote: HybridTimestamp) -> Result<HybridTimestamp, ServiceError> {
+        let clock = self.get_clock()?.merge(remote, physical_now())?;
+        self.write_clock(clock)?;
+
+        Ok(clock)
+    }
+}
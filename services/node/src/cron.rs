@@ -1,7 +1,7 @@
 use marine_rs_sdk::marine;
 use marine_sqlite_connector::{State, Statement, Value};
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
+use types::cron_hash;
 
 use crate::defaults::CRON_STATUS_ACTIVE;
 use crate::{defaults::CRON_TABLE_NAME, storage_impl::Storage};
@@ -44,22 +44,29 @@ impl Cron {
         topic: String,
         chain: String,
     ) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(
-            format!(
-                "{}{}{}",
-                address,
-                topic,
-                chain,
-            )
-            .as_bytes(),
-        );
-        bs58::encode(hasher.finalize()).into_string()
+        cron_hash(&address, &topic, &chain)
+    }
+}
+
+impl From<CronResult> for Cron {
+    fn from(cron: CronResult) -> Self {
+        Self {
+            hash: cron.hash,
+            token_key: cron.token_key,
+            address: cron.address,
+            topic: cron.topic,
+            token_type: cron.token_type,
+            chain: cron.chain,
+            status: cron.status,
+            meta_contract_id: cron.meta_contract_id,
+            node_url: cron.node_url,
+            public_key: cron.public_key,
+        }
     }
 }
 
 #[marine]
-#[derive(Debug, Default, Clone, Serialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct CronResult {
     pub hash: String,
     pub token_key: String,
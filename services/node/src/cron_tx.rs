@@ -7,7 +7,7 @@ use sha2::{Digest, Sha256};
 use crate::{error::ServiceError, error::ServiceError::InternalError};
 
 #[marine]
-#[derive(Debug, Default, Clone, Serialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct CronTx {
     pub hash: String,
     pub address: String,
@@ -4,10 +4,23 @@ pub static METADATAS_TABLE_NAME: &str = "metadatas";
 pub static META_CONTRACT_TABLE_NAME: &str = "metacontracts";
 pub static CRON_TABLE_NAME: &str = "cron";
 pub static CRON_TX_TABLE_NAME: &str = "cron_tx";
+pub static CLOCK_TABLE_NAME: &str = "clock";
+pub static RECEIPTS_TABLE_NAME: &str = "receipts";
+pub static OPERATORS_TABLE_NAME: &str = "operators";
+pub static ACL_TABLE_NAME: &str = "acl";
+pub static TRANSFERS_TABLE_NAME: &str = "transfers";
+pub static IDENTITIES_TABLE_NAME: &str = "identities";
+pub static MULTISIG_POLICIES_TABLE_NAME: &str = "multisig_policies";
+pub static MULTISIG_REQUESTS_TABLE_NAME: &str = "multisig_requests";
+pub static MULTISIG_SIGNATURES_TABLE_NAME: &str = "multisig_signatures";
+pub static SESSIONS_TABLE_NAME: &str = "sessions";
+pub static PRIVATE_ALIASES_TABLE_NAME: &str = "private_aliases";
+pub static PRIVATE_ENVELOPES_TABLE_NAME: &str = "private_envelopes";
 // Transaction
 pub static STATUS_PENDING: i64 = 0;
 pub static STATUS_SUCCESS: i64 = 1;
 pub static STATUS_FAILED: i64 = 2;
+pub static STATUS_AWAITING_SIGNATURES: i64 = 3;
 // Cron
 pub static CRON_STATUS_ACTIVE: i64 = 1;
 pub static CRON_STATUS_DISABLE: i64 = 0;
@@ -20,6 +33,68 @@ pub static METHOD_CONTRACT: &str = "contract";
 pub static METHOD_METADATA: &str = "metadata";
 pub static METHOD_CLONE: &str = "clone";
 pub static METHOD_CRON: &str = "cron";
+pub static METHOD_GRANT: &str = "grant";
+pub static METHOD_REVOKE: &str = "revoke";
+pub static METHOD_TRANSFER: &str = "transfer";
+pub static METHOD_ACCEPT_TRANSFER: &str = "accept_transfer";
+pub static METHOD_ROTATE_KEY: &str = "rotate_key";
+pub static METHOD_LINK_KEY: &str = "link_key";
+pub static METHOD_UNLINK_KEY: &str = "unlink_key";
+pub static METHOD_MULTISIG_POLICY: &str = "multisig_policy";
+pub static METHOD_SESSION_GRANT: &str = "session_grant";
+pub static METHOD_SESSION_REVOKE: &str = "session_revoke";
+pub static METHOD_PRIVACY: &str = "privacy";
+// TRANSFER
+pub const TRANSFER_KIND_METADATA: &str = "metadata";
+pub const TRANSFER_KIND_META_CONTRACT: &str = "meta_contract";
+pub const TRANSFER_STATUS_PENDING: i64 = 0;
+pub const TRANSFER_STATUS_COMPLETED: i64 = 1;
+// IDENTITY
+pub const IDENTITY_STATUS_ACTIVE: i64 = 1;
+pub const IDENTITY_STATUS_REVOKED: i64 = 0;
+// MULTISIG
+pub const MULTISIG_SCOPE_TOKEN_KEY: &str = "token_key";
+pub const MULTISIG_SCOPE_DATA_KEY: &str = "data_key";
+pub const MULTISIG_DEFAULT_TTL: u64 = 24 * 60 * 60 * 1000;
+// SESSION
+pub const SESSION_STATUS_ACTIVE: i64 = 1;
+pub const SESSION_STATUS_REVOKED: i64 = 0;
+pub const SESSION_RATE_WINDOW: u64 = 60 * 60 * 1000;
+// ACL PERMISSIONS
+pub const ACL_PERMISSION_WRITE: &str = "write";
+pub const ACL_PERMISSION_APPEND: &str = "append";
+pub const ACL_PERMISSION_READ_PRIVATE: &str = "read_private";
+// PRIVACY
+pub const CUSTODIAN_KEY_ENV: &str = "PRIVACY_CUSTODIAN_KEY";
+// REPLAY
+pub const REPLAY_KIND_META_CONTRACT: &str = "meta_contract";
+pub const REPLAY_KIND_METADATA: &str = "metadata";
+pub const REPLAY_KIND_CRON: &str = "cron";
+pub const REPLAY_ISSUE_MISSING: &str = "missing";
+pub const REPLAY_ISSUE_MISMATCH: &str = "mismatch";
+pub const REPLAY_ISSUE_UNTRACKED: &str = "untracked";
+pub const REPLAY_ISSUE_NO_RECEIPT: &str = "no_receipt";
+// INTEGRITY
+pub const INTEGRITY_DEFAULT_LIMIT: u32 = 100;
+pub const INTEGRITY_MAX_DEPTH: u32 = 256;
+pub const INTEGRITY_EMPTY_CID: &str = "empty_cid";
+pub const INTEGRITY_UNRESOLVABLE: &str = "unresolvable";
+pub const INTEGRITY_INVALID_BLOCK: &str = "invalid_block";
+pub const INTEGRITY_BROKEN_CHAIN: &str = "broken_chain";
+pub const INTEGRITY_MISSING_TRANSACTION: &str = "missing_transaction";
+// CLOCK
+pub const CLOCK_MAX_DRIFT: u64 = 60 * 1000;
+// SNAPSHOT
+pub const SNAPSHOT_FORMAT: &str = "transaction-node-snapshot";
+pub const SNAPSHOT_VERSION: u32 = 3;
+pub const SNAPSHOT_CHUNK_BYTES: usize = 64 * 1024;
 // ENCRYPTION
 pub static ENCRYPTION_TYPE_SECP256K1: &str = "secp256k1";
 pub static ENCRYPTION_TYPE_ED25519: &str = "ed25519";
+pub static ENCRYPTION_TYPE_P256: &str = "p256";
+pub static ENCRYPTION_TYPE_SR25519: &str = "sr25519";
+pub static ENCRYPTION_TYPE_COSMOS: &str = "cosmos";
+pub static ENCRYPTION_TYPE_BITCOIN: &str = "bitcoin";
+// KEY FORMATS
+pub const KEY_FORMAT_ADDRESS: &str = "address";
+pub const KEY_FORMAT_DID_PKH: &str = "did:pkh";
@@ -29,4 +29,18 @@ pub enum ServiceError {
     NoEncryptionType(),
     #[error["Invalid data format: {0}"]]
     InvalidDataFormatForMethodType(String),
+    #[error["Invalid snapshot: {0}"]]
+    InvalidSnapshot(String),
+    #[error["Unauthorized: {0}"]]
+    Unauthorized(String),
+    #[error["Revoked key: {0}"]]
+    RevokedKey(String),
+    #[error["Session denied: {0}"]]
+    SessionDenied(String),
+    #[error["Invalid clock: {0}"]]
+    InvalidClock(String),
+    #[error["Replayed payload: {0}"]]
+    ReplayedPayload(String),
+    #[error["Invalid replay range: {0}"]]
+    InvalidReplayRange(String),
 }
@@ -0,0 +1,580 @@
+use marine_rs_sdk::marine;
+use marine_sqlite_connector::{State, Statement, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::defaults::{
+    IDENTITIES_TABLE_NAME, IDENTITY_STATUS_ACTIVE, IDENTITY_STATUS_REVOKED, METHOD_LINK_KEY,
+    METHOD_ROTATE_KEY, METHOD_UNLINK_KEY, STATUS_FAILED, STATUS_SUCCESS,
+};
+use crate::error::ServiceError;
+use crate::error::ServiceError::{
+    InvalidDataFormatForMethodType, InvalidEncryption, InvalidMethod, InvalidOwner,
+    InvalidSignature, RecordFound, RecordNotFound, RevokedKey,
+};
+use crate::session::find_session;
+use crate::storage_impl::{get_storage, Storage};
+use crate::transaction::{SignedScope, Transaction};
+use crate::{get_public_key_type, verify};
+
+/**
+ * Key that belongs to an identity (account). The identity id is the first key
+ * of the identity and never changes, keys without a row are their own identity.
+ * Keys of any encryption type can be linked into the same identity.
+ */
+#[marine]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct IdentityKey {
+    pub public_key: String,
+    pub identity_id: String,
+    pub enc: String,
+    pub status: i64,
+    pub transaction_hash: String,
+    pub timestamp: u64,
+}
+
+/**
+ * `data` of a "rotate_key" transaction signed by the current key.
+ * `new_signature` is the new key's signature of `key_signature_message`,
+ * proving the new key agreed to take over.
+ */
+#[derive(Debug, Default, Deserialize)]
+pub struct SerdeRotation {
+    pub new_key: String,
+    pub new_signature: String,
+}
+
+/**
+ * `data` of a "link_key" transaction: `key` joins the signer's account and
+ * `signature` is its signature of `key_signature_message`, so both keys have
+ * signed. For "unlink_key" only `key` is read. Both carry the signed scope.
+ */
+#[derive(Debug, Default, Deserialize)]
+pub struct SerdeLink {
+    pub key: String,
+    #[serde(default)]
+    pub signature: String,
+    #[serde(flatten)]
+    pub scope: SignedScope,
+}
+
+impl Storage {
+    pub fn create_identities_table(&self) {
+        let table_schema = format!(
+            "
+            CREATE TABLE IF NOT EXISTS {} (
+                public_key TEXT PRIMARY KEY UNIQUE,
+                identity_id TEXT NOT NULL,
+                enc varchar(32) NOT NULL,
+                status INTEGER NOT NULL,
+                transaction_hash TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            );",
+            IDENTITIES_TABLE_NAME
+        );
+
+        let result = self.connection.execute(table_schema);
+
+        if let Err(error) = result {
+            println!("create_identities_table error: {}", error);
+        }
+    }
+
+    pub fn write_identity_key(&self, key: IdentityKey) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "INSERT OR REPLACE INTO {IDENTITIES_TABLE_NAME} (public_key, identity_id, enc, status, transaction_hash, timestamp) VALUES (?, ?, ?, ?, ?, ?)"
+        ))?;
+
+        statement.bind(1, &Value::String(key.public_key))?;
+        statement.bind(2, &Value::String(key.identity_id))?;
+        statement.bind(3, &Value::String(key.enc))?;
+        statement.bind(4, &Value::Integer(key.status))?;
+        statement.bind(5, &Value::String(key.transaction_hash))?;
+        statement.bind(6, &Value::Integer(key.timestamp as i64))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    pub fn get_identity_key(&self, public_key: String) -> Result<IdentityKey, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {IDENTITIES_TABLE_NAME} WHERE public_key = ?"
+        ))?;
+
+        statement.bind(1, &Value::String(public_key.clone()))?;
+
+        if let State::Row = statement.next()? {
+            read(&statement)
+        } else {
+            Err(RecordNotFound(f!("identity key {public_key}")))
+        }
+    }
+
+    pub fn get_identity_keys(&self, identity_id: String) -> Result<Vec<IdentityKey>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {IDENTITIES_TABLE_NAME} WHERE identity_id = ? ORDER BY timestamp ASC"
+        ))?;
+
+        statement.bind(1, &Value::String(identity_id))?;
+
+        let mut keys = Vec::new();
+
+        while let State::Row = statement.next()? {
+            keys.push(read(&statement)?);
+        }
+
+        Ok(keys)
+    }
+
+    pub fn get_all_identity_keys(&self) -> Result<Vec<IdentityKey>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {IDENTITIES_TABLE_NAME} ORDER BY timestamp ASC"
+        ))?;
+
+        let mut keys = Vec::new();
+
+        while let State::Row = statement.next()? {
+            keys.push(read(&statement)?);
+        }
+
+        Ok(keys)
+    }
+
+    pub fn update_identity_key_status(
+        &self,
+        public_key: String,
+        status: i64,
+    ) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "UPDATE {IDENTITIES_TABLE_NAME} SET status = ? WHERE public_key = ?"
+        ))?;
+
+        statement.bind(1, &Value::Integer(status))?;
+        statement.bind(2, &Value::String(public_key))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    /**
+     * Takes `public_key` out of its identity, it becomes its own identity
+     * again and stays usable. When it was the identity id, the remaining keys
+     * move to the identity of `keeper`.
+     */
+    pub fn detach_identity_key(
+        &self,
+        public_key: String,
+        keeper: String,
+    ) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "UPDATE {IDENTITIES_TABLE_NAME} SET identity_id = ? WHERE identity_id = ? AND public_key != ?"
+        ))?;
+
+        statement.bind(1, &Value::String(keeper))?;
+        statement.bind(2, &Value::String(public_key.clone()))?;
+        statement.bind(3, &Value::String(public_key.clone()))?;
+        statement.next()?;
+
+        let mut statement = self.connection.prepare(f!(
+            "DELETE FROM {IDENTITIES_TABLE_NAME} WHERE public_key = ?"
+        ))?;
+
+        statement.bind(1, &Value::String(public_key))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    /**
+     * Every key of the identity `public_key` belongs to, revoked keys included
+     */
+    pub fn get_account_keys(&self, public_key: String) -> Result<Vec<String>, ServiceError> {
+        let identity_id = self.resolve_identity(public_key.clone())?;
+        let keys = self.get_identity_keys(identity_id)?;
+
+        if keys.is_empty() {
+            Ok(vec![public_key])
+        } else {
+            Ok(keys.into_iter().map(|key| key.public_key).collect())
+        }
+    }
+
+    pub fn resolve_identity(&self, public_key: String) -> Result<String, ServiceError> {
+        match self.get_identity_key(public_key.clone()) {
+            Ok(key) => Ok(key.identity_id),
+            Err(RecordNotFound(_)) => Ok(public_key),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn is_revoked_key(&self, public_key: String) -> Result<bool, ServiceError> {
+        match self.get_identity_key(public_key) {
+            Ok(key) => Ok(key.status == IDENTITY_STATUS_REVOKED),
+            Err(RecordNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /**
+     * Whether `signer` may act for an entry owned by `owner`: both keys resolve
+     * to the same identity and the signer has not been revoked
+     */
+    pub fn is_same_owner(&self, owner: String, signer: String) -> Result<bool, ServiceError> {
+        if self.is_revoked_key(signer.clone())? {
+            return Ok(false);
+        }
+
+        if owner == signer {
+            return Ok(true);
+        }
+
+        Ok(self.resolve_identity(owner)? == self.resolve_identity(signer)?)
+    }
+}
+
+/**
+ * Fails when the key has been rotated away
+ */
+pub fn check_active_key(storage: &Storage, public_key: &str) -> Result<(), ServiceError> {
+    if storage.is_revoked_key(public_key.to_string())? {
+        Err(RevokedKey(public_key.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+/**
+ * Message a joining key signs to show it agrees to take over from, or join,
+ * `public_key`. Method and nonce keep the signature from being reused for
+ * another identity transaction.
+ */
+pub fn key_signature_message(method: &str, public_key: &str, new_key: &str, nonce: i64) -> String {
+    f!("{method}:{public_key}:{new_key}:{nonce}")
+}
+
+pub fn parse_rotation(data: &str) -> Result<SerdeRotation, ServiceError> {
+    let request: SerdeRotation = serde_json::from_str(data)
+        .map_err(|e| InvalidDataFormatForMethodType(f!("{METHOD_ROTATE_KEY}: {e}")))?;
+
+    if request.new_signature.is_empty() {
+        return Err(InvalidDataFormatForMethodType(f!(
+            "{METHOD_ROTATE_KEY} needs the signature of {request.new_key}"
+        )));
+    }
+
+    Ok(request)
+}
+
+/**
+ * A "link_key" needs the joining key's signature, an "unlink_key" only the key.
+ * Either is only valid for the transaction it was signed for, and only once.
+ */
+pub fn parse_link<F>(
+    scope: &SignedScope,
+    data: &str,
+    is_applied: F,
+) -> Result<SerdeLink, ServiceError>
+where
+    F: FnOnce() -> Result<bool, ServiceError>,
+{
+    let method = scope.method.as_str();
+    let request: SerdeLink = serde_json::from_str(data)
+        .map_err(|e| InvalidDataFormatForMethodType(f!("{method}: {e}")))?;
+
+    request.scope.check(scope)?;
+
+    if request.key.is_empty() || (method == METHOD_LINK_KEY && request.signature.is_empty()) {
+        return Err(InvalidDataFormatForMethodType(f!(
+            "{method} needs a key and its signature"
+        )));
+    }
+
+    scope.check_not_applied(is_applied)?;
+
+    Ok(request)
+}
+
+/**
+ * Checks a "rotate_key", "link_key" or "unlink_key" transaction before it is
+ * recorded
+ */
+pub fn check_identity_request(
+    storage: &Storage,
+    scope: &SignedScope,
+    public_key: &str,
+    data: &str,
+) -> Result<(), ServiceError> {
+    let method = scope.method.as_str();
+    let is_applied =
+        || storage.is_applied_payload(public_key.to_string(), method.to_string(), data.to_string());
+
+    if method == METHOD_ROTATE_KEY {
+        let request = parse_rotation(data)?;
+
+        check_new_key(storage, public_key, &request.new_key)?;
+
+        let message = key_signature_message(method, public_key, &request.new_key, scope.nonce);
+        check_key_signature(message, &request.new_key, request.new_signature)
+    } else if method == METHOD_LINK_KEY {
+        let request = parse_link(scope, data, is_applied)?;
+
+        check_new_key(storage, public_key, &request.key)?;
+
+        let message = key_signature_message(method, public_key, &request.key, scope.nonce);
+        check_key_signature(message, &request.key, request.signature)
+    } else if method == METHOD_UNLINK_KEY {
+        let request = parse_link(scope, data, is_applied)?;
+
+        if request.key == public_key {
+            return Err(InvalidDataFormatForMethodType(f!(
+                "cannot unlink the signing key {public_key}"
+            )));
+        }
+
+        let key = storage.get_identity_key(request.key.clone())?;
+
+        if key.identity_id != storage.resolve_identity(public_key.to_string())? {
+            return Err(InvalidOwner(f!(
+                "{request.key} is not linked to {public_key}"
+            )));
+        }
+
+        if key.status == IDENTITY_STATUS_REVOKED {
+            return Err(RevokedKey(request.key));
+        }
+
+        Ok(())
+    } else {
+        Err(InvalidMethod(f!("{method} is not an identity method")))
+    }
+}
+
+/**
+ * A key can only join an identity once, and never while it is a session key
+ */
+fn check_new_key(storage: &Storage, public_key: &str, new_key: &str) -> Result<(), ServiceError> {
+    if new_key.is_empty() || new_key == public_key {
+        return Err(InvalidDataFormatForMethodType(f!(
+            "invalid new key {new_key}"
+        )));
+    }
+
+    if !get_public_key_type(new_key).success {
+        return Err(InvalidEncryption(new_key.to_string()));
+    }
+
+    if find_session(storage, new_key)?.is_some() {
+        return Err(RecordFound(f!("session {new_key}")));
+    }
+
+    match storage.get_identity_key(new_key.to_string()) {
+        Ok(_) => Err(RecordFound(f!("identity key {new_key}"))),
+        Err(RecordNotFound(_)) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/**
+ * The joining key proves control by signing `message`
+ */
+fn check_key_signature(
+    message: String,
+    new_key: &str,
+    signature: String,
+) -> Result<(), ServiceError> {
+    let key_type = get_public_key_type(new_key);
+
+    if verify(
+        key_type.public_key,
+        signature,
+        message.clone(),
+        key_type.enc,
+    ) {
+        Ok(())
+    } else {
+        Err(InvalidSignature(f!("{new_key} did not sign {message}")))
+    }
+}
+
+/**
+ * Validated "rotate_key", "link_key" and "unlink_key" method types
+ */
+pub fn validate_identity(transaction_hash: String) {
+    let storage = get_storage().expect("Internal error to database connector");
+    let mut transaction = storage.get_transaction(transaction_hash).unwrap().clone();
+
+    match apply_identity(&storage, &transaction) {
+        Ok(()) => {
+            transaction.status = STATUS_SUCCESS;
+            transaction.error_text = "".to_string();
+        }
+        Err(e) => {
+            transaction.status = STATUS_FAILED;
+            transaction.error_text = e.to_string();
+        }
+    }
+
+    let _ = storage.update_transaction_status(
+        transaction.hash.clone(),
+        transaction.status,
+        transaction.error_text.clone(),
+    );
+}
+
+fn apply_identity(storage: &Storage, transaction: &Transaction) -> Result<(), ServiceError> {
+    check_active_key(storage, &transaction.public_key)?;
+    check_identity_request(
+        storage,
+        &SignedScope::from(transaction),
+        &transaction.public_key,
+        &transaction.data,
+    )?;
+
+    if transaction.method == METHOD_UNLINK_KEY {
+        let request: SerdeLink = serde_json::from_str(&transaction.data)
+            .map_err(|e| InvalidDataFormatForMethodType(e.to_string()))?;
+
+        return storage.detach_identity_key(request.key, transaction.public_key.clone());
+    }
+
+    let (new_key, signer_status) = if transaction.method == METHOD_ROTATE_KEY {
+        let request: SerdeRotation = serde_json::from_str(&transaction.data)
+            .map_err(|e| InvalidDataFormatForMethodType(e.to_string()))?;
+
+        (request.new_key, IDENTITY_STATUS_REVOKED)
+    } else {
+        let request: SerdeLink = serde_json::from_str(&transaction.data)
+            .map_err(|e| InvalidDataFormatForMethodType(e.to_string()))?;
+
+        (request.key, IDENTITY_STATUS_ACTIVE)
+    };
+
+    let identity_id = match storage.get_identity_key(transaction.public_key.clone()) {
+        Ok(key) => {
+            storage.update_identity_key_status(key.public_key, signer_status)?;
+            key.identity_id
+        }
+        Err(RecordNotFound(_)) => {
+            storage.write_identity_key(IdentityKey {
+                public_key: transaction.public_key.clone(),
+                identity_id: transaction.public_key.clone(),
+                enc: get_public_key_type(&transaction.public_key).enc,
+                status: signer_status,
+                transaction_hash: transaction.hash.clone(),
+                timestamp: transaction.timestamp,
+            })?;
+            transaction.public_key.clone()
+        }
+        Err(e) => return Err(e),
+    };
+
+    storage.write_identity_key(IdentityKey {
+        public_key: new_key.clone(),
+        identity_id,
+        enc: get_public_key_type(&new_key).enc,
+        status: IDENTITY_STATUS_ACTIVE,
+        transaction_hash: transaction.hash.clone(),
+        timestamp: transaction.timestamp,
+    })
+}
+
+pub fn read(statement: &Statement) -> Result<IdentityKey, ServiceError> {
+    Ok(IdentityKey {
+        public_key: statement.read::<String>(0)?,
+        identity_id: statement.read::<String>(1)?,
+        enc: statement.read::<String>(2)?,
+        status: statement.read::<i64>(3)?,
+        transaction_hash: statement.read::<String>(4)?,
+        timestamp: statement.read::<i64>(5)? as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn co_signature_message_is_bound_to_the_transaction() {
+        let message = key_signature_message(METHOD_ROTATE_KEY, "0xold", "0xnew", 4);
+
+        assert_eq!(message, "rotate_key:0xold:0xnew:4");
+        assert_ne!(
+            message,
+            key_signature_message(METHOD_LINK_KEY, "0xold", "0xnew", 4)
+        );
+        assert_ne!(
+            message,
+            key_signature_message(METHOD_ROTATE_KEY, "0xold", "0xnew", 5)
+        );
+    }
+
+    fn link(method: &str, key: &str, signature: &str, nonce: i64) -> String {
+        serde_json::json!({
+            "key": key,
+            "signature": signature,
+            "method": method,
+            "nonce": nonce,
+        })
+        .to_string()
+    }
+
+    fn not_applied() -> Result<bool, ServiceError> {
+        Ok(false)
+    }
+
+    #[test]
+    fn link_needs_the_joining_key_signature() {
+        let link_scope = SignedScope::new(METHOD_LINK_KEY, "", "", "", 1);
+        let unlink_scope = SignedScope::new(METHOD_UNLINK_KEY, "", "", "", 1);
+
+        let data = link(METHOD_LINK_KEY, "0xnew", "", 1);
+        assert!(parse_link(&link_scope, &data, not_applied).is_err());
+        let data = link(METHOD_LINK_KEY, "0xnew", "0xsig", 1);
+        assert!(parse_link(&link_scope, &data, not_applied).is_ok());
+        let data = link(METHOD_UNLINK_KEY, "0xnew", "", 1);
+        assert!(parse_link(&unlink_scope, &data, not_applied).is_ok());
+        let data = link(METHOD_UNLINK_KEY, "", "", 1);
+        assert!(parse_link(&unlink_scope, &data, not_applied).is_err());
+    }
+
+    #[test]
+    fn link_is_bound_to_its_method_and_nonce() {
+        let data = link(METHOD_LINK_KEY, "0xnew", "0xsig", 1);
+        let sent = [
+            SignedScope::new(METHOD_UNLINK_KEY, "", "", "", 1),
+            SignedScope::new(METHOD_LINK_KEY, "", "", "", 2),
+            SignedScope::new(METHOD_LINK_KEY, "", "dk", "", 1),
+        ];
+
+        for scope in sent.iter() {
+            assert!(parse_link(scope, &data, not_applied).is_err());
+        }
+
+        assert!(parse_link(
+            &SignedScope::new(METHOD_LINK_KEY, "", "", "", 1),
+            r#"{"key":"0xnew","signature":"0xsig"}"#,
+            not_applied
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn replayed_link_and_unlink_are_rejected() {
+        for method in [METHOD_LINK_KEY, METHOD_UNLINK_KEY] {
+            let scope = SignedScope::new(method, "", "", "", 3);
+            let data = link(method, "0xnew", "0xsig", 3);
+
+            assert!(parse_link(&scope, &data, not_applied).is_ok());
+            assert!(matches!(
+                parse_link(&scope, &data, || Ok(true)),
+                Err(ServiceError::ReplayedPayload(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn rotation_needs_the_new_key_signature() {
+        assert!(parse_rotation(r#"{"new_key":"0xnew"}"#).is_err());
+        assert!(parse_rotation(r#"{"new_key":"0xnew","new_signature":""}"#).is_err());
+        assert!(parse_rotation(r#"{"new_key":"0xnew","new_signature":"0xsig"}"#).is_ok());
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+
+use marine_rs_sdk::marine;
+
+use crate::block::Block;
+use crate::defaults::{
+    INTEGRITY_BROKEN_CHAIN, INTEGRITY_DEFAULT_LIMIT, INTEGRITY_EMPTY_CID, INTEGRITY_INVALID_BLOCK,
+    INTEGRITY_MAX_DEPTH, INTEGRITY_MISSING_TRANSACTION, INTEGRITY_UNRESOLVABLE,
+};
+use crate::error::ServiceError;
+use crate::get;
+use crate::metadatas::Metadata;
+use crate::storage_impl::{get_storage, Storage};
+
+#[marine]
+#[derive(Debug, Default, Clone)]
+pub struct IntegrityIssue {
+    pub metadata_hash: String,
+    pub data_key: String,
+    pub alias: String,
+    pub public_key: String,
+    pub cid: String,
+    pub issue: String,
+    pub detail: String,
+    pub repaired: bool,
+}
+
+#[marine]
+#[derive(Debug, Default, Clone)]
+pub struct IntegrityReport {
+    pub checked: u32,
+    pub next_cursor: String,
+    pub issues: Vec<IntegrityIssue>,
+}
+
+/**
+ * Checks up to `limit` metadata rows after `cursor`: the head cid must resolve
+ * to a block, every `previous` link must resolve, and every block must point
+ * back to a known transaction.
+ *
+ * With `repair`, a row whose head cid is empty or unresolvable is re-linked to
+ * the newest block recorded in its receipts that still resolves.
+ * `next_cursor` is empty once the last row has been checked.
+ */
+pub fn check_integrity(
+    limit: u32,
+    cursor: String,
+    repair: bool,
+) -> Result<IntegrityReport, ServiceError> {
+    let storage = get_storage()?;
+    let limit = if limit == 0 {
+        INTEGRITY_DEFAULT_LIMIT
+    } else {
+        limit
+    };
+
+    let metadatas = storage.get_metadatas_after(cursor, limit)?;
+    let mut report = IntegrityReport::default();
+
+    for metadata in metadatas.iter() {
+        report.checked += 1;
+
+        for (issue, detail) in check_chain(&storage, &metadata.cid)? {
+            let mut repaired = false;
+            let mut detail = detail;
+
+            if repair && is_head_issue(&issue) {
+                if let Some(cid) = find_last_good_cid(&storage, metadata)? {
+                    storage.update_cid(
+                        metadata.data_key.clone(),
+                        metadata.alias.clone(),
+                        metadata.public_key.clone(),
+                        cid.clone(),
+                    )?;
+
+                    repaired = true;
+                    detail = f!("{detail}, relinked to {cid}");
+                }
+            }
+
+            report.issues.push(IntegrityIssue {
+                metadata_hash: metadata.hash.clone(),
+                data_key: metadata.data_key.clone(),
+                alias: metadata.alias.clone(),
+                public_key: metadata.public_key.clone(),
+                cid: metadata.cid.clone(),
+                issue,
+                detail,
+                repaired,
+            });
+        }
+    }
+
+    if metadatas.len() as u32 == limit {
+        if let Some(last) = metadatas.last() {
+            report.next_cursor = last.hash.clone();
+        }
+    }
+
+    Ok(report)
+}
+
+/**
+ * Walks the history chain starting at `cid` and returns every issue found
+ */
+fn check_chain(storage: &Storage, cid: &str) -> Result<Vec<(String, String)>, ServiceError> {
+    let mut issues = vec![];
+
+    if cid.is_empty() {
+        issues.push((
+            INTEGRITY_EMPTY_CID.to_string(),
+            "metadata has no cid".to_string(),
+        ));
+        return Ok(issues);
+    }
+
+    let mut visited = HashSet::new();
+    let mut next_cid = cid.to_string();
+    let mut depth = 0;
+
+    while !next_cid.is_empty() && depth < INTEGRITY_MAX_DEPTH {
+        if !visited.insert(next_cid.clone()) {
+            issues.push((
+                INTEGRITY_BROKEN_CHAIN.to_string(),
+                f!("cycle at {next_cid}, depth {depth}"),
+            ));
+            break;
+        }
+
+        let block = match resolve_block(&next_cid) {
+            Ok(block) => block,
+            Err((issue, reason)) => {
+                if depth == 0 {
+                    issues.push((issue, reason));
+                } else {
+                    issues.push((
+                        INTEGRITY_BROKEN_CHAIN.to_string(),
+                        f!("{next_cid} at depth {depth}: {reason}"),
+                    ));
+                }
+                break;
+            }
+        };
+
+        let transaction_hash = block
+            .transaction
+            .get("hash")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default();
+
+        if !transaction_hash.is_empty() {
+            match storage.get_transaction(transaction_hash.to_string()) {
+                Ok(_) => {}
+                Err(ServiceError::SqliteError(e)) => return Err(ServiceError::SqliteError(e)),
+                Err(_) => issues.push((
+                    INTEGRITY_MISSING_TRANSACTION.to_string(),
+                    f!("{next_cid} at depth {depth} references {transaction_hash}"),
+                )),
+            }
+        }
+
+        next_cid = block
+            .previous
+            .get("/")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        depth += 1;
+    }
+
+    Ok(issues)
+}
+
+fn resolve_block(cid: &str) -> Result<Block, (String, String)> {
+    let result = get(cid.to_string(), "".to_string(), 0);
+
+    if !result.success || result.block.is_empty() {
+        return Err((
+            INTEGRITY_UNRESOLVABLE.to_string(),
+            f!("{cid} does not resolve: {result.error}"),
+        ));
+    }
+
+    serde_json::from_str(&result.block).map_err(|e| {
+        (
+            INTEGRITY_INVALID_BLOCK.to_string(),
+            f!("{cid} is not a metadata block: {e}"),
+        )
+    })
+}
+
+fn is_head_issue(issue: &str) -> bool {
+    issue == INTEGRITY_EMPTY_CID
+        || issue == INTEGRITY_UNRESOLVABLE
+        || issue == INTEGRITY_INVALID_BLOCK
+}
+
+/**
+ * Newest block recorded for the row that still resolves
+ */
+fn find_last_good_cid(
+    storage: &Storage,
+    metadata: &Metadata,
+) -> Result<Option<String>, ServiceError> {
+    let receipts = storage.get_receipts_by_metadata(
+        metadata.data_key.clone(),
+        metadata.alias.clone(),
+        metadata.public_key.clone(),
+    )?;
+
+    for receipt in receipts {
+        if receipt.cid.is_empty() || receipt.cid == metadata.cid {
+            continue;
+        }
+
+        if resolve_block(&receipt.cid).is_ok() {
+            return Ok(Some(receipt.cid));
+        }
+    }
+
+    Ok(None)
+}
@@ -1,49 +1,87 @@
 #![allow(improper_ctypes)]
 
+mod acl;
+mod auth;
 mod block;
+mod clock;
+mod clock_impl;
 pub mod cron;
 pub mod cron_tx;
 mod data_types;
 mod defaults;
 mod error;
+mod identity;
+mod integrity;
 mod meta_contract;
 mod meta_contract_impl;
 mod metadatas;
 mod metadatas_impl;
+mod multisig;
+mod privacy;
+mod receipt;
+mod replay;
 mod result;
+mod session;
+mod snapshot;
 mod storage_impl;
 mod transaction;
 pub mod transactions_impl;
+mod transfer;
 mod validators;
 
+use acl::{check_acl_request, check_write_access, validate_acl};
+use auth::authorize;
+use clock::{physical_now, HybridTimestamp};
 use cron::SerdeCron;
+use identity::{check_active_key, check_identity_request, validate_identity};
 use cron_tx::CronTx;
 use data_types::{DataTypeClone, DataTypeFork, SerdeDataTypeFork};
 use defaults::{
-    CRON_ACTION_CREATE, CRON_STATUS_ACTIVE, CRON_STATUS_DISABLE, ENCRYPTION_TYPE_ED25519,
-    ENCRYPTION_TYPE_SECP256K1, METHOD_CRON, STATUS_PENDING, STATUS_SUCCESS,
+    CRON_ACTION_CREATE, CRON_STATUS_ACTIVE, CRON_STATUS_DISABLE, ENCRYPTION_TYPE_BITCOIN,
+    ENCRYPTION_TYPE_COSMOS, ENCRYPTION_TYPE_ED25519, ENCRYPTION_TYPE_P256,
+    ENCRYPTION_TYPE_SECP256K1, ENCRYPTION_TYPE_SR25519, METHOD_CRON, STATUS_PENDING,
+    STATUS_SUCCESS,
+};
+use defaults::{
+    METHOD_ACCEPT_TRANSFER, METHOD_CLONE, METHOD_CONTRACT, METHOD_GRANT, METHOD_METADATA,
+    METHOD_LINK_KEY, METHOD_MULTISIG_POLICY, METHOD_PRIVACY, METHOD_REVOKE, METHOD_ROTATE_KEY,
+    METHOD_SESSION_GRANT, METHOD_SESSION_REVOKE, METHOD_TRANSFER, METHOD_UNLINK_KEY,
+    STATUS_FAILED,
 };
-use defaults::{METHOD_CLONE, METHOD_CONTRACT, METHOD_METADATA, STATUS_FAILED};
 use marine_rs_sdk::marine;
 use marine_rs_sdk::module_manifest;
 use marine_rs_sdk::WasmLoggerBuilder;
 
 use error::ServiceError::{
-    self, InvalidMethod, InvalidOwner, InvalidSignature, NoEncryptionType,
+    self, InvalidMethod, InvalidSignature, NoEncryptionType,
     NotSupportedEncryptionType, RecordFound,
 };
 
 use metadatas::{FinalMetadata, MetadataOrdering, MetadataQuery};
+use multisig::{check_policy_request, find_policy, open_request, validate_policy};
+use privacy::{check_privacy_request, validate_privacy};
 use result::{
-    FdbClock, FdbCronTxResult, FdbCronTxsResult, FdbCronsResult, FdbMetaContractResult,
-    FdbMetadataHistoryResult, FdbMetadatasResult, FdbTransactionResult, FdbTransactionsResult,
+    FdbAclsResult, FdbClock, FdbEnvelopeResult, FdbIdentityResult, FdbCronTxResult, FdbCronTxsResult, FdbCronsResult, FdbIntegrityResult,
+    FdbMetaContractResult,
+    FdbMetadataHistoryResult, FdbMetadatasResult, FdbMultisigPolicyResult, FdbMultisigRequestResult, FdbOperatorsResult, FdbReplayResult,
+    FdbSessionsResult, FdbSnapshotResult, FdbStatusResult, FdbTransactionResult, FdbTransactionsResult,
+    FdbTransferResult, FdbTransfersResult,
 };
 use result::{FdbMetadataResult, FdbResult};
 use serde_json::Value;
+use session::{
+    check_session, check_session_request, find_session, use_session, validate_session,
+};
 use std::time::{SystemTime, UNIX_EPOCH};
 use storage_impl::get_storage;
-use transaction::{Transaction, TransactionQuery, TransactionOrdering, TransactionRequest};
-use types::{IpfsDagGetResult, IpfsDagPutResult};
+use transaction::{
+    SignedScope, Transaction, TransactionOrdering, TransactionQuery, TransactionRequest,
+};
+use transfer::{check_transfer_request, validate_transfer};
+use types::{
+    DecryptResult, EncryptResult, IpfsDagGetResult, IpfsDagPutResult, KeyPairResult,
+    KeyTypeResult, KeyVerifyResult, VerifyRequest, VerifyResult,
+};
 use validators::{
     validate_clone, validate_cron, validate_meta_contract, validate_metadata,
     validate_metadata_cron,
@@ -68,11 +106,20 @@ pub fn main() {
         .unwrap();
 
     let storage = get_storage().unwrap();
+    storage.create_clock_table();
     storage.create_meta_contract_table();
     storage.create_transactions_table();
     storage.create_metadatas_table();
     storage.create_cron_table();
     storage.create_cron_tx_table();
+    storage.create_receipts_table();
+    storage.create_operators_table();
+    storage.create_acl_table();
+    storage.create_transfers_table();
+    storage.create_identities_table();
+    storage.create_multisig_tables();
+    storage.create_sessions_table();
+    storage.create_privacy_tables();
 }
 
 #[marine]
@@ -87,25 +134,95 @@ pub fn send_transaction(
     method: String,
     nonce: i64,
     version: i64,
+    hlc: u64,
 ) -> FdbResult {
+    submit_transaction(
+        TransactionRequest {
+            data_key,
+            token_key,
+            token_id,
+            alias,
+            public_key,
+            signature,
+            data,
+            method,
+            nonce,
+            version,
+            hlc,
+        },
+        None,
+    )
+}
+
+/**
+ * `verified` carries the signer key type and signature check when the caller
+ * already ran them, as the batch path does in a single crypto call
+ */
+fn submit_transaction(
+    request: TransactionRequest,
+    verified: Option<(KeyTypeResult, VerifyResult)>,
+) -> FdbResult {
+    let TransactionRequest {
+        data_key,
+        token_key,
+        token_id,
+        alias,
+        public_key,
+        signature,
+        data,
+        method,
+        nonce,
+        version,
+        hlc,
+    } = request;
+
     let mut meta_contract_id = "".to_string();
     let mut error: Option<ServiceError> = None;
     let storage = get_storage().expect("Database non existance");
 
+    // a session key signs on behalf of the main key that granted it
+    let signer_key = public_key;
+    let session = find_session(&storage, &signer_key).unwrap_or_else(|e| {
+        error = Some(e);
+        None
+    });
+    let public_key = match &session {
+        Some(session) => session.public_key.clone(),
+        None => signer_key.clone(),
+    };
+
     if error.is_none() {
         if method != METHOD_CONTRACT
             && method != METHOD_METADATA
             && method != METHOD_CLONE
             && method != METHOD_CRON
+            && method != METHOD_GRANT
+            && method != METHOD_REVOKE
+            && method != METHOD_TRANSFER
+            && method != METHOD_ACCEPT_TRANSFER
+            && method != METHOD_ROTATE_KEY
+            && method != METHOD_LINK_KEY
+            && method != METHOD_UNLINK_KEY
+            && method != METHOD_MULTISIG_POLICY
+            && method != METHOD_SESSION_GRANT
+            && method != METHOD_SESSION_REVOKE
+            && method != METHOD_PRIVACY
         {
             error = Some(InvalidMethod(f!("invalid method: {method}")));
         }
     }
 
-    let enc_verify = get_public_key_type(public_key.clone().as_str());
-    if enc_verify.len() <= 0 {
-        error = Some(ServiceError::InvalidEncryption(public_key.clone()));
+    // fields the signed `data` of the newer methods has to repeat
+    let scope = SignedScope::new(&method, &token_key, &data_key, &alias, nonce);
+
+    let (key_type, verified) = match verified {
+        Some((key_type, verify_result)) => (key_type, Some(verify_result)),
+        None => (get_public_key_type(signer_key.clone().as_str()), None),
+    };
+    if !key_type.success {
+        error = Some(ServiceError::InvalidEncryption(key_type.err_msg.clone()));
     }
+    let enc_verify = key_type.enc.clone();
 
     if error.is_none() {
         if method.clone() == METHOD_METADATA {
@@ -115,14 +232,21 @@ pub fn send_transaction(
                 alias.clone(),
             );
 
-            match result {
-                Ok(metadata) => {
-                    if metadata.public_key != public_key {
-                        error = Some(InvalidOwner(f!("not owner of data_key: {public_key}")));
-                    }
-                }
-                Err(ServiceError::RecordNotFound(_)) => {}
-                Err(e) => error = Some(e),
+            let result = match result {
+                Ok(metadata) => check_write_access(
+                    &storage,
+                    &data_key,
+                    &alias,
+                    &metadata.public_key,
+                    &public_key,
+                    true,
+                ),
+                Err(ServiceError::RecordNotFound(_)) => Ok(()),
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = result {
+                error = Some(e);
             }
         } else if method.clone() == METHOD_CONTRACT {
             meta_contract_id = data.clone();
@@ -209,6 +333,33 @@ pub fn send_transaction(
                 }
                 Err(e) => error = Some(ServiceError::InvalidDataFormatForMethodType(e.to_string())),
             }
+        } else if method == METHOD_GRANT || method == METHOD_REVOKE {
+            if let Err(e) = check_acl_request(&storage, &scope, &public_key, &data) {
+                error = Some(e);
+            }
+        } else if method == METHOD_TRANSFER || method == METHOD_ACCEPT_TRANSFER {
+            if let Err(e) = check_transfer_request(&storage, &scope, &public_key, &data) {
+                error = Some(e);
+            }
+        } else if method == METHOD_ROTATE_KEY
+            || method == METHOD_LINK_KEY
+            || method == METHOD_UNLINK_KEY
+        {
+            if let Err(e) = check_identity_request(&storage, &scope, &public_key, &data) {
+                error = Some(e);
+            }
+        } else if method == METHOD_SESSION_GRANT || method == METHOD_SESSION_REVOKE {
+            if let Err(e) = check_session_request(&storage, &scope, &public_key, &data) {
+                error = Some(e);
+            }
+        } else if method == METHOD_PRIVACY {
+            if let Err(e) = check_privacy_request(&storage, &scope, &public_key, &data) {
+                error = Some(e);
+            }
+        } else if method == METHOD_MULTISIG_POLICY {
+            if let Err(e) = check_policy_request(&storage, &scope, &public_key, &data) {
+                error = Some(e);
+            }
         }
     }
 
@@ -218,6 +369,10 @@ pub fn send_transaction(
         } else {
             if enc_verify.clone().ne(ENCRYPTION_TYPE_SECP256K1)
                 && enc_verify.clone().ne(ENCRYPTION_TYPE_ED25519)
+                && enc_verify.clone().ne(ENCRYPTION_TYPE_P256)
+                && enc_verify.clone().ne(ENCRYPTION_TYPE_SR25519)
+                && enc_verify.clone().ne(ENCRYPTION_TYPE_COSMOS)
+                && enc_verify.clone().ne(ENCRYPTION_TYPE_BITCOIN)
             {
                 error = Some(NotSupportedEncryptionType(enc_verify.clone()));
             }
@@ -225,22 +380,70 @@ pub fn send_transaction(
     }
 
     if error.is_none() {
-        let v = verify(
-            public_key.clone(),
-            signature.clone(),
-            data.clone(),
-            enc_verify.clone(),
-        );
-
-        if !v {
-            error = Some(InvalidSignature(f!("not owner of data_key: {public_key}")));
+        let v = verified.unwrap_or_else(|| {
+            verify_signature(
+                key_type.public_key.clone(),
+                signature.clone(),
+                data.clone(),
+                enc_verify.clone(),
+            )
+        });
+
+        if !v.success {
+            error = Some(InvalidSignature(f!(
+                "not owner of data_key: {public_key}: {v.err_msg}"
+            )));
+        }
+    }
+
+    if error.is_none() {
+        if let Err(e) = check_active_key(&storage, &public_key) {
+            error = Some(e);
+        }
+    }
+
+    let mut session_window = None;
+
+    if error.is_none() {
+        if let Some(session) = &session {
+            match check_session(session, &method, &token_key, &alias, physical_now()) {
+                Ok(window) => session_window = Some(window),
+                Err(e) => error = Some(e),
+            }
         }
     }
 
     let cp = marine_rs_sdk::get_call_parameters();
 
-    let now = SystemTime::now();
-    let node_timestamp = now.duration_since(UNIX_EPOCH).expect("Time went backwards");
+    // a transaction forwarded by a peer carries the timestamp it was issued at,
+    // only a host or operator relay may push it into the node clock
+    if hlc != 0 && error.is_none() {
+        if let Err(e) = authorize("send_transaction with hlc") {
+            error = Some(e);
+        }
+    }
+
+    let clock = if hlc != 0 && error.is_none() {
+        storage
+            .merge_clock(HybridTimestamp::unpack(hlc))
+            .or_else(|e| {
+                error = Some(e);
+                storage.tick_clock()
+            })
+    } else {
+        storage.tick_clock()
+    };
+
+    let clock = match clock {
+        Ok(clock) => clock,
+        Err(e) => {
+            return FdbResult {
+                success: false,
+                err_msg: e.to_string(),
+                transaction_hash: "".to_string(),
+            }
+        }
+    };
 
     let mut transaction = Transaction::new(
         token_key,
@@ -251,21 +454,51 @@ pub fn send_transaction(
         data,
         public_key,
         alias,
-        node_timestamp.as_millis() as u64,
+        clock,
         meta_contract_id,
         method,
         token_id,
         version,
     );
+    transaction.signer = signer_key;
+
+    if error.is_none() {
+        let result = find_policy(&storage, &scope, &transaction.public_key, &transaction.data)
+        .and_then(|policy| match policy {
+            Some(policy) => open_request(&storage, &mut transaction, &policy, signature),
+            None => Ok(()),
+        });
+
+        if let Err(e) = result {
+            error = Some(e);
+        }
+    }
+
+    // only an accepted transaction counts towards the session rate cap
+    if error.is_none() {
+        if let (Some(session), Some(window)) = (&session, session_window) {
+            if let Err(e) = use_session(&storage, session, window) {
+                error = Some(e);
+            }
+        }
+    }
 
     if !error.is_none() {
         transaction.error_text = error.unwrap().to_string();
         transaction.status = STATUS_FAILED;
     }
 
-    let _ = storage.write_transaction(transaction.clone());
+    if let Err(e) = storage.write_transaction(transaction.clone()) {
+        return FdbResult {
+            success: false,
+            err_msg: e.to_string(),
+            transaction_hash: "".to_string(),
+        };
+    }
 
     FdbResult {
+        success: true,
+        err_msg: "".to_string(),
         transaction_hash: transaction.hash,
     }
 }
@@ -274,21 +507,27 @@ pub fn send_transaction(
 pub fn send_batch_transaction(
   txs: Vec<TransactionRequest>
 ) -> Vec<FdbResult> {
+  // one crypto call resolves every key type and checks every signature,
+  // instead of two calls per transaction
+  let requests = txs
+    .iter()
+    .map(|tx| VerifyRequest {
+      public_key: tx.public_key.clone(),
+      signature: tx.signature.clone(),
+      message: tx.data.clone(),
+      enc: "".to_string(),
+    })
+    .collect();
+  let verify_results = verify_batch(requests);
+
+  if verify_results.len() != txs.len() {
+    return txs.into_iter().map(|tx| submit_transaction(tx, None)).collect();
+  }
+
   let mut results: Vec<FdbResult> = vec![];
-  
-  for tx in txs {
-    let result = send_transaction(
-      tx.data_key, 
-      tx.token_key, 
-      tx.token_id, 
-      tx.alias, 
-      tx.public_key, 
-      tx.signature, 
-      tx.data, 
-      tx.method, 
-      tx.nonce,
-      tx.version,
-    );
+
+  for (tx, verified) in txs.into_iter().zip(verify_results) {
+    let result = submit_transaction(tx, Some((verified.key_type, verified.verify)));
 
       results.push(result);
   }
@@ -304,6 +543,10 @@ pub fn send_cron_tx(
     tx_hash: String,
     token_id: String,
 ) -> FdbCronTxResult {
+    if let Err(e) = authorize("send_cron_tx") {
+        return Err(e).into();
+    }
+
     let mut error: Option<ServiceError> = None;
     let mut success = true;
     let mut proceed = false;
@@ -396,15 +639,25 @@ pub fn get_success_transactions(from: i64, to: i64) -> FdbTransactionsResult {
     wrapped_try(|| get_storage()?.get_success_transactions(from, ts)).into()
 }
 
+/**
+ * Returns the node hybrid logical clock. Reading the clock counts as a local
+ * event, so the returned value is safe to hand out to peers.
+ */
 #[marine]
 pub fn get_node_clock() -> FdbClock {
-    let now = SystemTime::now();
-    let timestamp = now.duration_since(UNIX_EPOCH).expect("Time went backwards");
-    let milliseconds = timestamp.as_millis();
+    wrapped_try(|| get_storage()?.tick_clock()).into()
+}
 
-    FdbClock {
-        timestamp: milliseconds as i64,
-    }
+/**
+ * Merges a hybrid timestamp received from a peer into the node clock
+ */
+#[marine]
+pub fn sync_node_clock(hlc: u64) -> FdbClock {
+    wrapped_try(|| {
+        authorize("sync_node_clock")?;
+        get_storage()?.merge_clock(HybridTimestamp::unpack(hlc))
+    })
+    .into()
 }
 
 #[marine]
@@ -430,6 +683,77 @@ pub fn search_metadatas(
     wrapped_try(|| get_storage()?.search_metadatas(query, ordering, from, to)).into()
 }
 
+/**
+ * Grants given on a data key, `owner` may be empty to list every owner
+ */
+#[marine]
+pub fn get_acls(data_key: String, owner: String) -> FdbAclsResult {
+    wrapped_try(|| get_storage()?.get_acls(data_key, owner)).into()
+}
+
+/**
+ * Keys of the identity `public_key` belongs to, revoked keys included.
+ * Empty for a key that has never been rotated or linked.
+ */
+#[marine]
+pub fn get_identity(public_key: String) -> FdbIdentityResult {
+    wrapped_try(|| {
+        let storage = get_storage()?;
+        let identity_id = storage.resolve_identity(public_key)?;
+        storage.get_identity_keys(identity_id)
+    })
+    .into()
+}
+
+/**
+ * Metadata owned by any key of the account `public_key` belongs to
+ */
+#[marine]
+pub fn get_account_metadatas(public_key: String) -> FdbMetadatasResult {
+    wrapped_try(|| {
+        let storage = get_storage()?;
+        let mut metadatas = vec![];
+
+        for key in storage.get_account_keys(public_key)? {
+            metadatas.extend(storage.get_metadatas_by_public_key(key)?);
+        }
+
+        Ok(metadatas)
+    })
+    .into()
+}
+
+/**
+ * Current envelope of a private content block, with the content key wrapped
+ * for the owner and the readers at the time of the call
+ */
+#[marine]
+pub fn get_private_envelope(cid: String) -> FdbEnvelopeResult {
+    wrapped_try(|| get_storage()?.get_private_envelope(cid)).into()
+}
+
+/**
+ * Session keys granted by `public_key`, revoked and expired ones included
+ */
+#[marine]
+pub fn get_sessions(public_key: String) -> FdbSessionsResult {
+    wrapped_try(|| get_storage()?.get_sessions(public_key)).into()
+}
+
+#[marine]
+pub fn get_transfer(transaction_hash: String) -> FdbTransferResult {
+    wrapped_try(|| get_storage()?.get_transfer(transaction_hash)).into()
+}
+
+/**
+ * Completed transfers of a metadata alias (`key` = data key) or of a meta
+ * contract binding (`key` = token key, empty alias), oldest first
+ */
+#[marine]
+pub fn get_ownership_history(kind: String, key: String, alias: String) -> FdbTransfersResult {
+    wrapped_try(|| get_storage()?.get_ownership_history(kind, key, alias)).into()
+}
+
 #[marine]
 pub fn get_meta_contract(token_key: String) -> FdbMetaContractResult {
     wrapped_try(|| get_storage()?.get_meta_contract(token_key)).into()
@@ -550,6 +874,10 @@ pub fn get_metadata_with_history(
 // *********** VALIDATOR *****************
 #[marine]
 pub fn bind_meta_contract(transaction_hash: String) {
+    if authorize("bind_meta_contract").is_err() {
+        return;
+    }
+
     validate_meta_contract(transaction_hash);
 }
 
@@ -561,6 +889,10 @@ pub fn set_metadata(
     metadatas: Vec<FinalMetadata>,
     final_error_msg: String,
 ) {
+    if authorize("set_metadata").is_err() {
+        return;
+    }
+
     validate_metadata(
         transaction_hash,
         meta_contract_id,
@@ -573,10 +905,15 @@ pub fn set_metadata(
 #[marine]
 pub fn set_metadata_cron(
     data_key: String,
+    tx_hash: String,
     on_metacontract_result: bool,
     metadatas: Vec<FinalMetadata>,
 ) {
-    validate_metadata_cron(data_key, on_metacontract_result, metadatas);
+    if authorize("set_metadata_cron").is_err() {
+        return;
+    }
+
+    validate_metadata_cron(data_key, tx_hash, on_metacontract_result, metadatas);
 }
 
 #[marine]
@@ -587,6 +924,10 @@ pub fn set_clone(
     data: String,
     final_error_msg: String,
 ) {
+    if authorize("set_clone").is_err() {
+        return;
+    }
+
     validate_clone(
         transaction_hash,
         meta_contract_id,
@@ -598,9 +939,177 @@ pub fn set_clone(
 
 #[marine]
 pub fn set_cron(transaction_hash: String, data: String) {
+    if authorize("set_cron").is_err() {
+        return;
+    }
+
     validate_cron(transaction_hash, data);
 }
 
+#[marine]
+pub fn set_acl(transaction_hash: String) {
+    if authorize("set_acl").is_err() {
+        return;
+    }
+
+    validate_acl(transaction_hash);
+}
+
+#[marine]
+pub fn set_transfer(transaction_hash: String) {
+    if authorize("set_transfer").is_err() {
+        return;
+    }
+
+    validate_transfer(transaction_hash);
+}
+
+#[marine]
+pub fn set_identity(transaction_hash: String) {
+    if authorize("set_identity").is_err() {
+        return;
+    }
+
+    validate_identity(transaction_hash);
+}
+
+#[marine]
+pub fn set_multisig_policy(transaction_hash: String) {
+    if authorize("set_multisig_policy").is_err() {
+        return;
+    }
+
+    validate_policy(transaction_hash);
+}
+
+#[marine]
+pub fn set_session(transaction_hash: String) {
+    if authorize("set_session").is_err() {
+        return;
+    }
+
+    validate_session(transaction_hash);
+}
+
+#[marine]
+pub fn set_privacy(transaction_hash: String) {
+    if authorize("set_privacy").is_err() {
+        return;
+    }
+
+    validate_privacy(transaction_hash);
+}
+
+// *********** MULTISIG *****************
+/**
+ * Co-signs a transaction waiting for signatures. `signature` is the signature
+ * of the transaction hash by `public_key`, which must be a policy signer.
+ */
+#[marine]
+pub fn add_signature(
+    transaction_hash: String,
+    public_key: String,
+    signature: String,
+) -> FdbTransactionResult {
+    wrapped_try(|| multisig::add_signature(transaction_hash, public_key, signature)).into()
+}
+
+#[marine]
+pub fn get_multisig_policy(
+    scope: String,
+    key: String,
+    alias: String,
+    owner: String,
+) -> FdbMultisigPolicyResult {
+    wrapped_try(|| get_storage()?.get_multisig_policy(scope, key, alias, owner)).into()
+}
+
+#[marine]
+pub fn get_multisig_request(transaction_hash: String) -> FdbMultisigRequestResult {
+    wrapped_try(|| get_storage()?.get_multisig_request(transaction_hash)).into()
+}
+
+/**
+ * Fails the transactions whose signature deadline has passed
+ */
+#[marine]
+pub fn expire_multisig_requests() -> FdbStatusResult {
+    wrapped_try(|| {
+        authorize("expire_multisig_requests")?;
+        multisig::expire_requests()
+    })
+    .into()
+}
+
+// *********** SNAPSHOT *****************
+#[marine]
+pub fn export_snapshot() -> FdbSnapshotResult {
+    wrapped_try(|| {
+        authorize("export_snapshot")?;
+        snapshot::export_snapshot()
+    })
+    .into()
+}
+
+#[marine]
+pub fn restore_snapshot(cid: String) -> FdbSnapshotResult {
+    wrapped_try(|| {
+        authorize("restore_snapshot")?;
+        snapshot::restore_snapshot(cid)
+    })
+    .into()
+}
+
+// *********** REPLAY *****************
+/**
+ * Rebuilds meta contract bindings, metadata rows and crons from the successful
+ * transactions between `from` and `to` (milliseconds, `to` = 0 means now).
+ * With `dry_run` the divergences are only reported. Only the whole log
+ * (`from` = 0, `to` = 0) can be applied.
+ */
+#[marine]
+pub fn replay_transactions(from: i64, to: i64, dry_run: bool) -> FdbReplayResult {
+    wrapped_try(|| {
+        authorize("replay_transactions")?;
+        replay::replay_transactions(from, to, dry_run)
+    })
+    .into()
+}
+
+// *********** INTEGRITY *****************
+/**
+ * Checks the history chain of up to `limit` metadata rows after `cursor`.
+ * Pass the returned `next_cursor` to continue, it is empty after the last row.
+ */
+#[marine]
+pub fn check_integrity(limit: u32, cursor: String, repair: bool) -> FdbIntegrityResult {
+    wrapped_try(|| {
+        authorize("check_integrity")?;
+        integrity::check_integrity(limit, cursor, repair)
+    })
+    .into()
+}
+
+// *********** OPERATORS *****************
+/**
+ * Operators may call the validator and admin functions in addition to the
+ * host. Only the host can change the operator list.
+ */
+#[marine]
+pub fn add_operator(peer_id: String) -> FdbStatusResult {
+    wrapped_try(|| auth::add_operator(peer_id)).into()
+}
+
+#[marine]
+pub fn remove_operator(peer_id: String) -> FdbStatusResult {
+    wrapped_try(|| auth::remove_operator(peer_id)).into()
+}
+
+#[marine]
+pub fn get_operators() -> FdbOperatorsResult {
+    wrapped_try(|| get_storage()?.get_operators()).into()
+}
+
 // *********** Deserializer *****************
 #[marine]
 pub fn deserialize_fork(data: String) -> DataTypeFork {
@@ -632,6 +1141,34 @@ extern "C" {
     #[link_name = "verify"]
     pub fn verify(public_key: String, signature: String, message: String, enc: String) -> bool;
 
+    #[link_name = "verify_signature"]
+    pub fn verify_signature(
+        public_key: String,
+        signature: String,
+        message: String,
+        enc: String,
+    ) -> VerifyResult;
+
+    #[link_name = "verify_batch"]
+    pub fn verify_batch(requests: Vec<VerifyRequest>) -> Vec<KeyVerifyResult>;
+
     #[link_name = "get_public_key_type"]
-    pub fn get_public_key_type(public_key: &str) -> String;
+    pub fn get_public_key_type(public_key: &str) -> KeyTypeResult;
+
+    #[link_name = "generate_keypair"]
+    pub fn generate_keypair(enc: String) -> KeyPairResult;
+
+    #[link_name = "encrypt_for_recipients"]
+    pub fn encrypt_for_recipients(recipients: Vec<String>, plaintext: String) -> EncryptResult;
+
+    #[link_name = "decrypt"]
+    pub fn decrypt(envelope: String, private_key: String, enc: String) -> DecryptResult;
+
+    #[link_name = "rewrap"]
+    pub fn rewrap(
+        envelope: String,
+        private_key: String,
+        enc: String,
+        recipients: Vec<String>,
+    ) -> EncryptResult;
 }
@@ -1,7 +1,8 @@
 use marine_rs_sdk::marine;
+use serde::{Deserialize, Serialize};
 
 #[marine]
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct MetaContract {
     pub token_key: String,
     pub meta_contract_id: String,
@@ -58,6 +58,19 @@ impl Storage {
         Ok(())
     }
 
+    pub fn update_meta_contract(&self, contract: MetaContract) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "UPDATE {META_CONTRACT_TABLE_NAME} SET meta_contract_id = ?, public_key = ? WHERE token_key = ?"
+        ))?;
+
+        statement.bind(1, &Value::String(contract.meta_contract_id))?;
+        statement.bind(2, &Value::String(contract.public_key))?;
+        statement.bind(3, &Value::String(contract.token_key))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
     pub fn get_meta_contract(&self, token_key: String) -> Result<MetaContract, ServiceError> {
         let mut statement = self.connection.prepare(f!(
             "SELECT * FROM {META_CONTRACT_TABLE_NAME} WHERE token_key = ?"
@@ -85,6 +98,20 @@ impl Storage {
           Err(RecordNotFound(f!("{meta_contract_id}")))
       }
   }
+
+    pub fn get_all_meta_contracts(&self) -> Result<Vec<MetaContract>, ServiceError> {
+        let mut statement = self
+            .connection
+            .prepare(f!("SELECT * FROM {META_CONTRACT_TABLE_NAME}"))?;
+
+        let mut contracts = Vec::new();
+
+        while let State::Row = statement.next()? {
+            contracts.push(read(&statement)?);
+        }
+
+        Ok(contracts)
+    }
 }
 
 pub fn read(statement: &Statement) -> Result<MetaContract, ServiceError> {
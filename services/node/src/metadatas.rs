@@ -1,7 +1,8 @@
 use marine_rs_sdk::marine;
-use sha2::{Digest, Sha256};
+use serde::{Deserialize, Serialize};
+use types::metadata_hash;
 #[marine]
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Metadata {
     pub hash: String,
     pub data_key: String,
@@ -27,17 +28,7 @@ impl Metadata {
       alias: String,
       public_key: String,
   ) -> String {
-      let mut hasher = Sha256::new();
-      hasher.update(
-          format!(
-              "{}{}{}",
-              data_key,
-              alias,
-              public_key,
-          )
-          .as_bytes(),
-      );
-      bs58::encode(hasher.finalize()).into_string()
+      metadata_hash(&data_key, &alias, &public_key)
   }
 }
 
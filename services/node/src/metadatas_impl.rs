@@ -31,26 +31,18 @@ impl Storage {
      * Its focusing on creating schema
      */
     pub fn write_metadata(&self, metadata: Metadata) -> Result<(), ServiceError> {
-        let s = format!(
-            "insert into {} (hash, data_key, alias, cid, public_key) values ('{}', '{}', '{}', '{}', '{}');",
-            METADATAS_TABLE_NAME,
-            metadata.hash,
-            metadata.data_key,
-            metadata.alias,
-            metadata.cid,
-            metadata.public_key
-        );
+        let mut statement = self.connection.prepare(f!(
+            "INSERT INTO {METADATAS_TABLE_NAME} (hash, data_key, alias, cid, public_key) VALUES (?, ?, ?, ?, ?)"
+        ))?;
 
-        log::info!("{}", s);
+        statement.bind(1, &Value::String(metadata.hash))?;
+        statement.bind(2, &Value::String(metadata.data_key))?;
+        statement.bind(3, &Value::String(metadata.alias))?;
+        statement.bind(4, &Value::String(metadata.cid))?;
+        statement.bind(5, &Value::String(metadata.public_key))?;
+        statement.next()?;
 
-        let result = self.connection.execute(s);
-        match result {
-            Ok(_) => return Ok(()),
-            Err(error) => {
-                log::info!("{:?}", error);
-                return Ok(());
-            }
-        }
+        Ok(())
     }
 
     pub fn update_cid(
@@ -72,6 +64,33 @@ impl Storage {
         Ok(())
     }
 
+    /**
+     * Moves a row to a new owner. The hash is derived from the owner, so it
+     * changes with it.
+     */
+    pub fn update_metadata_owner(
+        &self,
+        data_key: String,
+        alias: String,
+        from_public_key: String,
+        to_public_key: String,
+    ) -> Result<(), ServiceError> {
+        let hash = Metadata::generate_hash(data_key.clone(), alias.clone(), to_public_key.clone());
+
+        let mut statement = self.connection.prepare(f!(
+            "UPDATE {METADATAS_TABLE_NAME} SET hash = ?, public_key = ? WHERE data_key = ? AND alias = ? AND public_key = ?"
+        ))?;
+
+        statement.bind(1, &Value::String(hash))?;
+        statement.bind(2, &Value::String(to_public_key))?;
+        statement.bind(3, &Value::String(data_key))?;
+        statement.bind(4, &Value::String(alias))?;
+        statement.bind(5, &Value::String(from_public_key))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
     pub fn get_owner_metadata_by_datakey_and_alias(
         &self,
         data_key: String,
@@ -109,6 +128,45 @@ impl Storage {
         Ok(metadatas)
     }
 
+    /**
+     * Pages through all metadata rows ordered by hash, starting after `cursor`
+     */
+    pub fn get_metadatas_after(&self, cursor: String, limit: u32) -> Result<Vec<Metadata>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {METADATAS_TABLE_NAME} WHERE hash > ? ORDER BY hash ASC LIMIT ?"
+        ))?;
+
+        statement.bind(1, &Value::String(cursor))?;
+        statement.bind(2, &Value::Integer(limit as i64))?;
+
+        let mut metadatas = vec![];
+
+        while let State::Row = statement.next()? {
+            metadatas.push(read(&statement)?);
+        }
+
+        Ok(metadatas)
+    }
+
+    /**
+     * Every metadata row owned by exactly `public_key`
+     */
+    pub fn get_metadatas_by_public_key(&self, public_key: String) -> Result<Vec<Metadata>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {METADATAS_TABLE_NAME} WHERE public_key = ? ORDER BY data_key, alias"
+        ))?;
+
+        statement.bind(1, &Value::String(public_key))?;
+
+        let mut metadatas = Vec::new();
+
+        while let State::Row = statement.next()? {
+            metadatas.push(read(&statement)?);
+        }
+
+        Ok(metadatas)
+    }
+
     pub fn search_metadatas(&self, query: Vec<MetadataQuery>, ordering: Vec<MetadataOrdering>, from: u32, to: u32) -> Result<Vec<Metadata>, ServiceError> {
       
       let mut query_str = "".to_string();
@@ -0,0 +1,1001 @@
+use marine_rs_sdk::marine;
+use marine_sqlite_connector::{State, Statement, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::clock::physical_now;
+use crate::defaults::{
+    ACL_PERMISSION_APPEND, ACL_PERMISSION_WRITE, METHOD_ACCEPT_TRANSFER, METHOD_CLONE,
+    METHOD_CONTRACT, METHOD_CRON, METHOD_GRANT, METHOD_METADATA, METHOD_MULTISIG_POLICY,
+    METHOD_PRIVACY, METHOD_REVOKE, METHOD_SESSION_GRANT, METHOD_SESSION_REVOKE, METHOD_TRANSFER,
+    MULTISIG_DEFAULT_TTL, MULTISIG_POLICIES_TABLE_NAME, MULTISIG_REQUESTS_TABLE_NAME,
+    MULTISIG_SCOPE_DATA_KEY, MULTISIG_SCOPE_TOKEN_KEY, MULTISIG_SIGNATURES_TABLE_NAME,
+    STATUS_AWAITING_SIGNATURES, STATUS_FAILED, STATUS_PENDING, STATUS_SUCCESS,
+    TRANSACTIONS_TABLE_NAME,
+};
+use crate::error::ServiceError;
+use crate::error::ServiceError::{
+    InvalidDataFormatForMethodType, InvalidOwner, InvalidSignature, RecordFound, RecordNotFound,
+};
+use crate::storage_impl::{get_storage, Storage};
+use crate::transaction::{SignedScope, Transaction};
+use crate::{get_public_key_type, verify};
+
+/**
+ * M of N policy on a token key, or on the rows of `owner` under a data key.
+ * A data key policy with an empty alias covers every alias of the owner.
+ * Guarded transactions stay in `STATUS_AWAITING_SIGNATURES` until `threshold`
+ * of `signers` have signed them, or fail once `ttl` milliseconds have passed.
+ */
+#[marine]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct MultisigPolicy {
+    pub scope: String,
+    pub key: String,
+    pub alias: String,
+    pub owner: String,
+    pub threshold: u32,
+    pub signers: Vec<String>,
+    pub ttl: u64,
+    pub transaction_hash: String,
+    pub timestamp: u64,
+}
+
+/**
+ * Policy a guarded transaction was sent under and the signers so far
+ */
+#[marine]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct MultisigRequest {
+    pub transaction_hash: String,
+    pub scope: String,
+    pub key: String,
+    pub threshold: u32,
+    pub signers: Vec<String>,
+    pub expires_at: u64,
+    pub signed_by: Vec<String>,
+}
+
+/**
+ * Signature of one policy signer on a guarded transaction
+ */
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct MultisigSignature {
+    pub transaction_hash: String,
+    pub slot: String,
+    pub public_key: String,
+    pub signature: String,
+    pub timestamp: u64,
+}
+
+/**
+ * `data` of a "multisig_policy" transaction, a threshold of 0 removes the policy.
+ * `scope` is the policy scope, `signed` the transaction fields it was signed for.
+ */
+#[derive(Debug, Default, Deserialize)]
+pub struct SerdeMultisigPolicy {
+    pub scope: String,
+    pub threshold: u32,
+    #[serde(default)]
+    pub signers: Vec<String>,
+    #[serde(default)]
+    pub ttl: u64,
+    #[serde(flatten)]
+    pub signed: SignedScope,
+}
+
+impl Storage {
+    pub fn create_multisig_tables(&self) {
+        let schemas = vec![
+            format!(
+                "
+                CREATE TABLE IF NOT EXISTS {} (
+                    scope varchar(32) NOT NULL,
+                    key TEXT NOT NULL,
+                    alias varchar(255) NOT NULL,
+                    owner TEXT NOT NULL,
+                    threshold INTEGER NOT NULL,
+                    signers TEXT NOT NULL,
+                    ttl INTEGER NOT NULL,
+                    transaction_hash TEXT NOT NULL,
+                    timestamp INTEGER NOT NULL,
+                    PRIMARY KEY(scope, key, alias, owner)
+                );",
+                MULTISIG_POLICIES_TABLE_NAME
+            ),
+            format!(
+                "
+                CREATE TABLE IF NOT EXISTS {} (
+                    transaction_hash TEXT PRIMARY KEY UNIQUE,
+                    scope varchar(32) NOT NULL,
+                    key TEXT NOT NULL,
+                    threshold INTEGER NOT NULL,
+                    signers TEXT NOT NULL,
+                    expires_at INTEGER NOT NULL
+                );",
+                MULTISIG_REQUESTS_TABLE_NAME
+            ),
+            format!(
+                "
+                CREATE TABLE IF NOT EXISTS {} (
+                    transaction_hash TEXT NOT NULL,
+                    slot TEXT NOT NULL,
+                    public_key TEXT NOT NULL,
+                    signature TEXT NOT NULL,
+                    timestamp INTEGER NOT NULL,
+                    UNIQUE(transaction_hash, slot)
+                );",
+                MULTISIG_SIGNATURES_TABLE_NAME
+            ),
+        ];
+
+        for schema in schemas {
+            if let Err(error) = self.connection.execute(schema) {
+                println!("create_multisig_tables error: {}", error);
+            }
+        }
+    }
+
+    pub fn write_multisig_policy(&self, policy: MultisigPolicy) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "INSERT OR REPLACE INTO {MULTISIG_POLICIES_TABLE_NAME} (scope, key, alias, owner, threshold, signers, ttl, transaction_hash, timestamp) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        ))?;
+
+        statement.bind(1, &Value::String(policy.scope))?;
+        statement.bind(2, &Value::String(policy.key))?;
+        statement.bind(3, &Value::String(policy.alias))?;
+        statement.bind(4, &Value::String(policy.owner))?;
+        statement.bind(5, &Value::Integer(policy.threshold as i64))?;
+        statement.bind(6, &Value::String(to_json(&policy.signers)))?;
+        statement.bind(7, &Value::Integer(policy.ttl as i64))?;
+        statement.bind(8, &Value::String(policy.transaction_hash))?;
+        statement.bind(9, &Value::Integer(policy.timestamp as i64))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    pub fn delete_multisig_policy(&self, policy: &MultisigPolicy) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "DELETE FROM {MULTISIG_POLICIES_TABLE_NAME} WHERE scope = ? AND key = ? AND alias = ? AND owner = ?"
+        ))?;
+
+        statement.bind(1, &Value::String(policy.scope.clone()))?;
+        statement.bind(2, &Value::String(policy.key.clone()))?;
+        statement.bind(3, &Value::String(policy.alias.clone()))?;
+        statement.bind(4, &Value::String(policy.owner.clone()))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    pub fn get_multisig_policy(
+        &self,
+        scope: String,
+        key: String,
+        alias: String,
+        owner: String,
+    ) -> Result<MultisigPolicy, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {MULTISIG_POLICIES_TABLE_NAME} WHERE scope = ? AND key = ? AND alias = ? AND owner = ?"
+        ))?;
+
+        statement.bind(1, &Value::String(scope.clone()))?;
+        statement.bind(2, &Value::String(key.clone()))?;
+        statement.bind(3, &Value::String(alias.clone()))?;
+        statement.bind(4, &Value::String(owner.clone()))?;
+
+        if let State::Row = statement.next()? {
+            read_policy(&statement)
+        } else {
+            Err(RecordNotFound(f!(
+                "multisig policy {scope} {key} {alias} {owner}"
+            )))
+        }
+    }
+
+    /**
+     * Policies on `key` covering `alias`, the ones on the alias itself first
+     */
+    pub fn get_multisig_policies(
+        &self,
+        scope: String,
+        key: String,
+        alias: String,
+    ) -> Result<Vec<MultisigPolicy>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {MULTISIG_POLICIES_TABLE_NAME} WHERE scope = ? AND key = ? AND (alias = ? OR alias = '') ORDER BY alias DESC, timestamp ASC"
+        ))?;
+
+        statement.bind(1, &Value::String(scope))?;
+        statement.bind(2, &Value::String(key))?;
+        statement.bind(3, &Value::String(alias))?;
+
+        let mut policies = Vec::new();
+
+        while let State::Row = statement.next()? {
+            policies.push(read_policy(&statement)?);
+        }
+
+        Ok(policies)
+    }
+
+    pub fn write_multisig_request(&self, request: &MultisigRequest) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "INSERT INTO {MULTISIG_REQUESTS_TABLE_NAME} (transaction_hash, scope, key, threshold, signers, expires_at) VALUES (?, ?, ?, ?, ?, ?)"
+        ))?;
+
+        statement.bind(1, &Value::String(request.transaction_hash.clone()))?;
+        statement.bind(2, &Value::String(request.scope.clone()))?;
+        statement.bind(3, &Value::String(request.key.clone()))?;
+        statement.bind(4, &Value::Integer(request.threshold as i64))?;
+        statement.bind(5, &Value::String(to_json(&request.signers)))?;
+        statement.bind(6, &Value::Integer(request.expires_at as i64))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    pub fn get_multisig_request(
+        &self,
+        transaction_hash: String,
+    ) -> Result<MultisigRequest, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {MULTISIG_REQUESTS_TABLE_NAME} WHERE transaction_hash = ?"
+        ))?;
+
+        statement.bind(1, &Value::String(transaction_hash.clone()))?;
+
+        let mut request = if let State::Row = statement.next()? {
+            read_request(&statement)?
+        } else {
+            return Err(RecordNotFound(f!("multisig request {transaction_hash}")));
+        };
+
+        request.signed_by = self.get_multisig_slots(transaction_hash)?;
+
+        Ok(request)
+    }
+
+    pub fn write_multisig_signature(
+        &self,
+        signature: MultisigSignature,
+    ) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "INSERT INTO {MULTISIG_SIGNATURES_TABLE_NAME} (transaction_hash, slot, public_key, signature, timestamp) VALUES (?, ?, ?, ?, ?)"
+        ))?;
+
+        statement.bind(1, &Value::String(signature.transaction_hash))?;
+        statement.bind(2, &Value::String(signature.slot))?;
+        statement.bind(3, &Value::String(signature.public_key))?;
+        statement.bind(4, &Value::String(signature.signature))?;
+        statement.bind(5, &Value::Integer(signature.timestamp as i64))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    pub fn get_all_multisig_policies(&self) -> Result<Vec<MultisigPolicy>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {MULTISIG_POLICIES_TABLE_NAME} ORDER BY timestamp ASC"
+        ))?;
+
+        let mut policies = Vec::new();
+
+        while let State::Row = statement.next()? {
+            policies.push(read_policy(&statement)?);
+        }
+
+        Ok(policies)
+    }
+
+    /**
+     * Every request without its signers, the signatures are listed apart
+     */
+    pub fn get_all_multisig_requests(&self) -> Result<Vec<MultisigRequest>, ServiceError> {
+        let mut statement = self
+            .connection
+            .prepare(f!("SELECT * FROM {MULTISIG_REQUESTS_TABLE_NAME}"))?;
+
+        let mut requests = Vec::new();
+
+        while let State::Row = statement.next()? {
+            requests.push(read_request(&statement)?);
+        }
+
+        Ok(requests)
+    }
+
+    pub fn get_all_multisig_signatures(&self) -> Result<Vec<MultisigSignature>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {MULTISIG_SIGNATURES_TABLE_NAME} ORDER BY timestamp ASC"
+        ))?;
+
+        let mut signatures = Vec::new();
+
+        while let State::Row = statement.next()? {
+            signatures.push(MultisigSignature {
+                transaction_hash: statement.read::<String>(0)?,
+                slot: statement.read::<String>(1)?,
+                public_key: statement.read::<String>(2)?,
+                signature: statement.read::<String>(3)?,
+                timestamp: statement.read::<i64>(4)? as u64,
+            });
+        }
+
+        Ok(signatures)
+    }
+
+    /**
+     * Policy signers that already signed the transaction
+     */
+    fn get_multisig_slots(&self, transaction_hash: String) -> Result<Vec<String>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT slot FROM {MULTISIG_SIGNATURES_TABLE_NAME} WHERE transaction_hash = ? ORDER BY timestamp ASC"
+        ))?;
+
+        statement.bind(1, &Value::String(transaction_hash))?;
+
+        let mut slots = Vec::new();
+
+        while let State::Row = statement.next()? {
+            slots.push(statement.read::<String>(0)?);
+        }
+
+        Ok(slots)
+    }
+
+    /**
+     * Transactions still waiting for signatures after their deadline
+     */
+    fn get_expired_multisig_requests(&self, now: u64) -> Result<Vec<String>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT r.transaction_hash FROM {MULTISIG_REQUESTS_TABLE_NAME} r JOIN {TRANSACTIONS_TABLE_NAME} t ON t.hash = r.transaction_hash WHERE t.status = ? AND r.expires_at <= ?"
+        ))?;
+
+        statement.bind(1, &Value::Integer(STATUS_AWAITING_SIGNATURES))?;
+        statement.bind(2, &Value::Integer(now as i64))?;
+
+        let mut hashes = Vec::new();
+
+        while let State::Row = statement.next()? {
+            hashes.push(statement.read::<String>(0)?);
+        }
+
+        Ok(hashes)
+    }
+}
+
+/**
+ * Scopes of the policies that may guard `method`. Meta contract bindings and
+ * crons are guarded by a token key policy, metadata writes, grants and privacy
+ * changes by a data key policy, and transfers and sessions by either.
+ */
+pub fn guarded_scopes(method: &str) -> Vec<&'static str> {
+    let mut scopes = vec![];
+
+    if method == METHOD_CONTRACT
+        || method == METHOD_CRON
+        || method == METHOD_TRANSFER
+        || method == METHOD_ACCEPT_TRANSFER
+        || method == METHOD_SESSION_GRANT
+        || method == METHOD_SESSION_REVOKE
+    {
+        scopes.push(MULTISIG_SCOPE_TOKEN_KEY);
+    }
+
+    if method == METHOD_METADATA
+        || method == METHOD_CLONE
+        || method == METHOD_GRANT
+        || method == METHOD_REVOKE
+        || method == METHOD_TRANSFER
+        || method == METHOD_ACCEPT_TRANSFER
+        || method == METHOD_SESSION_GRANT
+        || method == METHOD_SESSION_REVOKE
+        || method == METHOD_PRIVACY
+    {
+        scopes.push(MULTISIG_SCOPE_DATA_KEY);
+    }
+
+    scopes
+}
+
+/**
+ * Policy guarding a transaction, if any. A policy change is guarded by the
+ * policy it changes.
+ */
+pub fn find_policy(
+    storage: &Storage,
+    scope: &SignedScope,
+    public_key: &str,
+    data: &str,
+) -> Result<Option<MultisigPolicy>, ServiceError> {
+    let scopes = if scope.method == METHOD_MULTISIG_POLICY {
+        let request = parse_policy_request(scope, data)?;
+        vec![request.scope]
+    } else {
+        guarded_scopes(&scope.method)
+            .into_iter()
+            .map(|s| s.to_string())
+            .collect()
+    };
+
+    for policy_scope in scopes {
+        if let Some(policy) = find_scoped_policy(storage, &policy_scope, scope, public_key)? {
+            return Ok(Some(policy));
+        }
+    }
+
+    Ok(None)
+}
+
+/**
+ * A token key policy guards every transaction on the token key. A data key
+ * policy only guards the rows of its owner, written by the owner or by a
+ * delegate of the owner.
+ */
+fn find_scoped_policy(
+    storage: &Storage,
+    policy_scope: &str,
+    scope: &SignedScope,
+    public_key: &str,
+) -> Result<Option<MultisigPolicy>, ServiceError> {
+    if policy_scope == MULTISIG_SCOPE_TOKEN_KEY {
+        if scope.token_key.is_empty() {
+            return Ok(None);
+        }
+
+        return match storage.get_multisig_policy(
+            policy_scope.to_string(),
+            scope.token_key.clone(),
+            "".to_string(),
+            "".to_string(),
+        ) {
+            Ok(policy) => Ok(Some(policy)),
+            Err(RecordNotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        };
+    }
+
+    if scope.data_key.is_empty() {
+        return Ok(None);
+    }
+
+    let policies = storage.get_multisig_policies(
+        policy_scope.to_string(),
+        scope.data_key.clone(),
+        scope.alias.clone(),
+    )?;
+
+    for policy in policies {
+        if storage.is_same_owner(policy.owner.clone(), public_key.to_string())?
+            || storage.has_permission(
+                scope.data_key.clone(),
+                scope.alias.clone(),
+                policy.owner.clone(),
+                public_key.to_string(),
+                &[ACL_PERMISSION_WRITE, ACL_PERMISSION_APPEND],
+            )?
+        {
+            return Ok(Some(policy));
+        }
+    }
+
+    Ok(None)
+}
+
+/**
+ * Attaches a new transaction to the policy guarding it. The sender's signature
+ * counts as the first one, the transaction waits for the rest unless the
+ * threshold is already met.
+ */
+pub fn open_request(
+    storage: &Storage,
+    transaction: &mut Transaction,
+    policy: &MultisigPolicy,
+    signature: String,
+) -> Result<(), ServiceError> {
+    let slot = find_slot(storage, &policy.signers, &transaction.public_key)?;
+
+    let ttl = if policy.ttl == 0 {
+        MULTISIG_DEFAULT_TTL
+    } else {
+        policy.ttl
+    };
+
+    storage.write_multisig_request(&MultisigRequest {
+        transaction_hash: transaction.hash.clone(),
+        scope: policy.scope.clone(),
+        key: policy.key.clone(),
+        threshold: policy.threshold,
+        signers: policy.signers.clone(),
+        expires_at: transaction.timestamp + ttl,
+        signed_by: vec![],
+    })?;
+
+    storage.write_multisig_signature(MultisigSignature {
+        transaction_hash: transaction.hash.clone(),
+        slot,
+        public_key: transaction.public_key.clone(),
+        signature,
+        timestamp: physical_now(),
+    })?;
+
+    if policy.threshold > 1 {
+        transaction.status = STATUS_AWAITING_SIGNATURES;
+    }
+
+    Ok(())
+}
+
+/**
+ * Adds a co-signature to a transaction waiting for signatures. The signature
+ * covers the transaction hash, so it only approves this very transaction.
+ * Once the threshold is met the transaction becomes pending and is validated
+ * as usual.
+ */
+pub fn add_signature(
+    transaction_hash: String,
+    public_key: String,
+    signature: String,
+) -> Result<Transaction, ServiceError> {
+    let storage = get_storage()?;
+    let mut transaction = storage.get_transaction(transaction_hash.clone())?;
+
+    if transaction.status != STATUS_AWAITING_SIGNATURES {
+        return Err(InvalidDataFormatForMethodType(f!(
+            "transaction {transaction_hash} is not waiting for signatures"
+        )));
+    }
+
+    let request = storage.get_multisig_request(transaction_hash.clone())?;
+
+    if request.expires_at <= physical_now() {
+        expire(&storage, &mut transaction)?;
+        return Err(InvalidDataFormatForMethodType(f!(
+            "transaction {transaction_hash} expired"
+        )));
+    }
+
+    let slot = find_slot(&storage, &request.signers, &public_key)?;
+
+    if request.signed_by.contains(&slot) {
+        return Err(RecordFound(f!("signature of {slot} on {transaction_hash}")));
+    }
+
+    let key_type = get_public_key_type(&public_key);
+
+    if !key_type.success
+        || !verify(
+            key_type.public_key,
+            signature.clone(),
+            transaction.hash.clone(),
+            key_type.enc,
+        )
+    {
+        return Err(InvalidSignature(f!("not signed by {public_key}")));
+    }
+
+    storage.write_multisig_signature(MultisigSignature {
+        transaction_hash: transaction_hash.clone(),
+        slot,
+        public_key,
+        signature,
+        timestamp: physical_now(),
+    })?;
+
+    if request.signed_by.len() as u32 + 1 >= request.threshold {
+        transaction.status = STATUS_PENDING;
+        storage.update_transaction_status(
+            transaction.hash.clone(),
+            transaction.status,
+            transaction.error_text.clone(),
+        )?;
+    }
+
+    Ok(transaction)
+}
+
+/**
+ * Fails every transaction whose signature deadline has passed
+ */
+pub fn expire_requests() -> Result<(), ServiceError> {
+    let storage = get_storage()?;
+
+    for hash in storage.get_expired_multisig_requests(physical_now())? {
+        let mut transaction = storage.get_transaction(hash)?;
+        expire(&storage, &mut transaction)?;
+    }
+
+    Ok(())
+}
+
+fn expire(storage: &Storage, transaction: &mut Transaction) -> Result<(), ServiceError> {
+    transaction.status = STATUS_FAILED;
+    transaction.error_text = "multisig signatures expired".to_string();
+
+    storage.update_transaction_status(
+        transaction.hash.clone(),
+        transaction.status,
+        transaction.error_text.clone(),
+    )
+}
+
+/**
+ * Policy signer `public_key` signs for, directly or through its identity
+ */
+fn find_slot(
+    storage: &Storage,
+    signers: &[String],
+    public_key: &str,
+) -> Result<String, ServiceError> {
+    for signer in signers {
+        if storage.is_same_owner(signer.clone(), public_key.to_string())? {
+            return Ok(signer.clone());
+        }
+    }
+
+    Err(InvalidOwner(f!("{public_key} is not a multisig signer")))
+}
+
+/**
+ * Parses the `data` of a "multisig_policy" transaction and checks its shape
+ * and the transaction fields it was signed for
+ */
+pub fn parse_policy_request(
+    scope: &SignedScope,
+    data: &str,
+) -> Result<SerdeMultisigPolicy, ServiceError> {
+    let method = &scope.method;
+    let request: SerdeMultisigPolicy = serde_json::from_str(data)
+        .map_err(|e| InvalidDataFormatForMethodType(f!("{method}: {e}")))?;
+
+    request.signed.check(scope)?;
+
+    let key = if request.scope == MULTISIG_SCOPE_TOKEN_KEY {
+        &scope.token_key
+    } else if request.scope == MULTISIG_SCOPE_DATA_KEY {
+        &scope.data_key
+    } else {
+        return Err(InvalidDataFormatForMethodType(f!(
+            "unknown multisig scope {request.scope}"
+        )));
+    };
+
+    if key.is_empty() {
+        return Err(InvalidDataFormatForMethodType(f!(
+            "missing {request.scope}"
+        )));
+    }
+
+    if request.threshold > 0 {
+        if request.threshold as usize > request.signers.len() {
+            return Err(InvalidDataFormatForMethodType(format!(
+                "threshold {} above {} signers",
+                request.threshold,
+                request.signers.len()
+            )));
+        }
+
+        for (i, signer) in request.signers.iter().enumerate() {
+            if request.signers[..i].contains(signer) {
+                return Err(RecordFound(f!("multisig signer {signer}")));
+            }
+        }
+    }
+
+    Ok(request)
+}
+
+/**
+ * A policy payload is applied at most once, a new nonce is needed to set the
+ * same policy again
+ */
+fn parse_fresh_policy_request<F>(
+    scope: &SignedScope,
+    data: &str,
+    is_applied: F,
+) -> Result<SerdeMultisigPolicy, ServiceError>
+where
+    F: FnOnce() -> Result<bool, ServiceError>,
+{
+    let request = parse_policy_request(scope, data)?;
+    scope.check_not_applied(is_applied)?;
+
+    Ok(request)
+}
+
+/**
+ * Checks a "multisig_policy" transaction before it is recorded. Without a
+ * policy only the owner can attach one, changing an existing policy goes
+ * through the policy itself.
+ */
+pub fn check_policy_request(
+    storage: &Storage,
+    scope: &SignedScope,
+    public_key: &str,
+    data: &str,
+) -> Result<(), ServiceError> {
+    let request = parse_fresh_policy_request(scope, data, || {
+        storage.is_applied_payload(
+            public_key.to_string(),
+            scope.method.clone(),
+            data.to_string(),
+        )
+    })?;
+
+    for signer in request.signers.iter() {
+        if !get_public_key_type(signer).success {
+            return Err(ServiceError::InvalidEncryption(signer.clone()));
+        }
+    }
+
+    match find_scoped_policy(storage, &request.scope, scope, public_key)? {
+        Some(policy) if policy_alias(&request, scope) == policy.alias => {
+            find_slot(storage, &policy.signers, public_key)?;
+        }
+        _ => check_owner(storage, &request.scope, scope, public_key)?,
+    }
+
+    Ok(())
+}
+
+/**
+ * Alias a policy is attached to, token key policies have none
+ */
+fn policy_alias(request: &SerdeMultisigPolicy, scope: &SignedScope) -> String {
+    if request.scope == MULTISIG_SCOPE_TOKEN_KEY {
+        "".to_string()
+    } else {
+        scope.alias.clone()
+    }
+}
+
+/**
+ * A token key policy is attached by the meta contract owner, a data key
+ * policy by the owner of the rows it covers
+ */
+fn check_owner(
+    storage: &Storage,
+    policy_scope: &str,
+    scope: &SignedScope,
+    public_key: &str,
+) -> Result<(), ServiceError> {
+    if policy_scope == MULTISIG_SCOPE_TOKEN_KEY {
+        let contract = storage.get_meta_contract(scope.token_key.clone())?;
+
+        if storage.is_same_owner(contract.public_key, public_key.to_string())? {
+            return Ok(());
+        }
+    } else {
+        for metadata in storage.get_metadata_by_datakey(scope.data_key.clone())? {
+            let covered = scope.alias.is_empty() || metadata.alias == scope.alias;
+
+            if covered && storage.is_same_owner(metadata.public_key, public_key.to_string())? {
+                return Ok(());
+            }
+        }
+    }
+
+    Err(InvalidOwner(f!(
+        "not owner of {policy_scope}: {public_key}"
+    )))
+}
+
+/**
+ * Validated "multisig_policy" method type
+ */
+pub fn validate_policy(transaction_hash: String) {
+    let storage = get_storage().expect("Internal error to database connector");
+    let mut transaction = storage.get_transaction(transaction_hash).unwrap().clone();
+
+    match apply_policy(&storage, &transaction) {
+        Ok(()) => {
+            transaction.status = STATUS_SUCCESS;
+            transaction.error_text = "".to_string();
+        }
+        Err(e) => {
+            transaction.status = STATUS_FAILED;
+            transaction.error_text = e.to_string();
+        }
+    }
+
+    let _ = storage.update_transaction_status(
+        transaction.hash.clone(),
+        transaction.status,
+        transaction.error_text.clone(),
+    );
+}
+
+fn apply_policy(storage: &Storage, transaction: &Transaction) -> Result<(), ServiceError> {
+    let scope = SignedScope::from(transaction);
+
+    check_policy_request(storage, &scope, &transaction.public_key, &transaction.data)?;
+
+    let request = parse_policy_request(&scope, &transaction.data)?;
+    let alias = policy_alias(&request, &scope);
+
+    let (key, owner) = if request.scope == MULTISIG_SCOPE_TOKEN_KEY {
+        (transaction.token_key.clone(), "".to_string())
+    } else {
+        // a change keeps the owner the policy was attached by
+        let owner =
+            match find_scoped_policy(storage, &request.scope, &scope, &transaction.public_key)? {
+                Some(policy) if policy.alias == alias => policy.owner,
+                _ => transaction.public_key.clone(),
+            };
+
+        (transaction.data_key.clone(), owner)
+    };
+
+    let policy = MultisigPolicy {
+        scope: request.scope,
+        key,
+        alias,
+        owner,
+        threshold: request.threshold,
+        signers: request.signers,
+        ttl: request.ttl,
+        transaction_hash: transaction.hash.clone(),
+        timestamp: transaction.timestamp,
+    };
+
+    if policy.threshold == 0 {
+        storage.delete_multisig_policy(&policy)
+    } else {
+        storage.write_multisig_policy(policy)
+    }
+}
+
+fn to_json(signers: &[String]) -> String {
+    serde_json::to_string(signers).unwrap_or_else(|_| "[]".to_string())
+}
+
+fn from_json(signers: &str) -> Vec<String> {
+    serde_json::from_str(signers).unwrap_or_default()
+}
+
+pub fn read_policy(statement: &Statement) -> Result<MultisigPolicy, ServiceError> {
+    Ok(MultisigPolicy {
+        scope: statement.read::<String>(0)?,
+        key: statement.read::<String>(1)?,
+        alias: statement.read::<String>(2)?,
+        owner: statement.read::<String>(3)?,
+        threshold: statement.read::<i64>(4)? as u32,
+        signers: from_json(&statement.read::<String>(5)?),
+        ttl: statement.read::<i64>(6)? as u64,
+        transaction_hash: statement.read::<String>(7)?,
+        timestamp: statement.read::<i64>(8)? as u64,
+    })
+}
+
+pub fn read_request(statement: &Statement) -> Result<MultisigRequest, ServiceError> {
+    Ok(MultisigRequest {
+        transaction_hash: statement.read::<String>(0)?,
+        scope: statement.read::<String>(1)?,
+        key: statement.read::<String>(2)?,
+        threshold: statement.read::<i64>(3)? as u32,
+        signers: from_json(&statement.read::<String>(4)?),
+        expires_at: statement.read::<i64>(5)? as u64,
+        signed_by: vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy_data(signed: &SignedScope, scope: &str, threshold: u32, signers: &[&str]) -> String {
+        serde_json::json!({
+            "scope": scope,
+            "threshold": threshold,
+            "signers": signers,
+            "method": signed.method,
+            "token_key": signed.token_key,
+            "data_key": signed.data_key,
+            "alias": signed.alias,
+            "nonce": signed.nonce,
+        })
+        .to_string()
+    }
+
+    fn not_applied() -> Result<bool, ServiceError> {
+        Ok(false)
+    }
+
+    #[test]
+    fn policy_needs_the_key_of_its_scope() {
+        let with_key = SignedScope::new(METHOD_MULTISIG_POLICY, "", "dk", "", 1);
+        let without_key = SignedScope::new(METHOD_MULTISIG_POLICY, "tk", "", "", 1);
+
+        let data = policy_data(&with_key, MULTISIG_SCOPE_DATA_KEY, 1, &["0xa"]);
+        assert!(parse_policy_request(&with_key, &data).is_ok());
+
+        let data = policy_data(&without_key, MULTISIG_SCOPE_DATA_KEY, 1, &["0xa"]);
+        assert!(parse_policy_request(&without_key, &data).is_err());
+    }
+
+    #[test]
+    fn policy_shape_is_checked() {
+        let scope = SignedScope::new(METHOD_MULTISIG_POLICY, "tk", "", "", 1);
+
+        let above = policy_data(&scope, MULTISIG_SCOPE_TOKEN_KEY, 3, &["0xa", "0xb"]);
+        let duplicate = policy_data(&scope, MULTISIG_SCOPE_TOKEN_KEY, 2, &["0xa", "0xa"]);
+        let unknown = policy_data(&scope, "owner", 1, &["0xa"]);
+        let removal = policy_data(&scope, MULTISIG_SCOPE_TOKEN_KEY, 0, &[]);
+
+        assert!(parse_policy_request(&scope, &above).is_err());
+        assert!(parse_policy_request(&scope, &duplicate).is_err());
+        assert!(parse_policy_request(&scope, &unknown).is_err());
+        assert!(parse_policy_request(&scope, &removal).is_ok());
+    }
+
+    #[test]
+    fn policy_is_bound_to_the_transaction_it_was_signed_for() {
+        let signed = SignedScope::new(METHOD_MULTISIG_POLICY, "", "dk", "profile", 1);
+        let data = policy_data(&signed, MULTISIG_SCOPE_DATA_KEY, 1, &["0xa"]);
+        let sent = [
+            SignedScope::new(METHOD_METADATA, "", "dk", "profile", 1),
+            SignedScope::new(METHOD_MULTISIG_POLICY, "", "other", "profile", 1),
+            SignedScope::new(METHOD_MULTISIG_POLICY, "", "dk", "", 1),
+            SignedScope::new(METHOD_MULTISIG_POLICY, "", "dk", "profile", 2),
+        ];
+
+        for scope in sent.iter() {
+            assert!(parse_policy_request(scope, &data).is_err());
+        }
+
+        let unscoped = r#"{"scope":"data_key","threshold":1,"signers":["0xa"]}"#;
+        assert!(parse_policy_request(&signed, unscoped).is_err());
+    }
+
+    #[test]
+    fn replayed_policy_is_rejected() {
+        let scope = SignedScope::new(METHOD_MULTISIG_POLICY, "tk", "", "", 1);
+        let data = policy_data(&scope, MULTISIG_SCOPE_TOKEN_KEY, 0, &[]);
+
+        assert!(parse_fresh_policy_request(&scope, &data, not_applied).is_ok());
+        assert!(matches!(
+            parse_fresh_policy_request(&scope, &data, || Ok(true)),
+            Err(ServiceError::ReplayedPayload(_))
+        ));
+    }
+
+    #[test]
+    fn token_key_policies_have_no_alias() {
+        let scope = SignedScope::new(METHOD_MULTISIG_POLICY, "tk", "dk", "profile", 1);
+        let token = policy_data(&scope, MULTISIG_SCOPE_TOKEN_KEY, 0, &[]);
+        let data = policy_data(&scope, MULTISIG_SCOPE_DATA_KEY, 0, &[]);
+        let token = parse_policy_request(&scope, &token);
+        let data = parse_policy_request(&scope, &data);
+
+        assert_eq!(policy_alias(&token.unwrap(), &scope), "");
+        assert_eq!(policy_alias(&data.unwrap(), &scope), "profile");
+    }
+
+    #[test]
+    fn guarded_methods_map_to_scopes() {
+        assert_eq!(
+            guarded_scopes(METHOD_CONTRACT),
+            vec![MULTISIG_SCOPE_TOKEN_KEY]
+        );
+        assert_eq!(
+            guarded_scopes(METHOD_METADATA),
+            vec![MULTISIG_SCOPE_DATA_KEY]
+        );
+        assert_eq!(
+            guarded_scopes(METHOD_TRANSFER),
+            vec![MULTISIG_SCOPE_TOKEN_KEY, MULTISIG_SCOPE_DATA_KEY]
+        );
+        assert_eq!(
+            guarded_scopes(METHOD_PRIVACY),
+            vec![MULTISIG_SCOPE_DATA_KEY]
+        );
+        for method in [METHOD_SESSION_GRANT, METHOD_SESSION_REVOKE] {
+            assert_eq!(
+                guarded_scopes(method),
+                vec![MULTISIG_SCOPE_TOKEN_KEY, MULTISIG_SCOPE_DATA_KEY]
+            );
+        }
+        assert!(guarded_scopes(METHOD_MULTISIG_POLICY).is_empty());
+    }
+}
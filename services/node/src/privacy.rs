@@ -0,0 +1,714 @@
+use marine_rs_sdk::marine;
+use marine_sqlite_connector::{State, Statement, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::clock::physical_now;
+use crate::defaults::{
+    ACL_PERMISSION_READ_PRIVATE, CUSTODIAN_KEY_ENV, ENCRYPTION_TYPE_ED25519,
+    ENCRYPTION_TYPE_SECP256K1, KEY_FORMAT_ADDRESS, KEY_FORMAT_DID_PKH, METHOD_PRIVACY,
+    PRIVATE_ALIASES_TABLE_NAME, PRIVATE_ENVELOPES_TABLE_NAME, STATUS_FAILED, STATUS_SUCCESS,
+};
+use crate::error::ServiceError;
+use crate::error::ServiceError::{
+    InternalError, InvalidDataFormatForMethodType, InvalidEncryption, InvalidMethod,
+    RecordNotFound,
+};
+use crate::receipt::Receipt;
+use crate::storage_impl::{get_storage, Storage};
+use crate::transaction::{SignedScope, Transaction, TransactionSubset};
+use crate::{decrypt, encrypt_for_recipients, get_public_key_type, put_block, rewrap};
+
+/**
+ * Alias whose content is stored encrypted for its owner and the
+ * "read_private" grantees
+ */
+#[marine]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PrivateAlias {
+    pub data_key: String,
+    pub alias: String,
+    pub owner: String,
+    pub transaction_hash: String,
+    pub timestamp: u64,
+}
+
+/**
+ * Current envelope of a private content block. The block in IPFS keeps the
+ * recipients it was written with, `envelope` carries the keys as re-wrapped
+ * after the last grant or revoke.
+ */
+#[marine]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PrivateEnvelope {
+    pub cid: String,
+    pub data_key: String,
+    pub alias: String,
+    pub owner: String,
+    pub envelope: String,
+    pub timestamp: u64,
+}
+
+/**
+ * `data` of a "privacy" transaction, carrying the signed scope
+ */
+#[derive(Debug, Default, Deserialize)]
+pub struct SerdePrivacy {
+    pub private: bool,
+    #[serde(flatten)]
+    pub scope: SignedScope,
+}
+
+impl Storage {
+    pub fn create_privacy_tables(&self) {
+        let schemas = [
+            format!(
+                "
+                CREATE TABLE IF NOT EXISTS {} (
+                    data_key TEXT NOT NULL,
+                    alias varchar(255) NOT NULL,
+                    owner TEXT NOT NULL,
+                    transaction_hash TEXT NOT NULL,
+                    timestamp INTEGER NOT NULL,
+                    UNIQUE(data_key, alias, owner)
+                );",
+                PRIVATE_ALIASES_TABLE_NAME
+            ),
+            format!(
+                "
+                CREATE TABLE IF NOT EXISTS {} (
+                    cid TEXT PRIMARY KEY UNIQUE,
+                    data_key TEXT NOT NULL,
+                    alias varchar(255) NOT NULL,
+                    owner TEXT NOT NULL,
+                    envelope TEXT NOT NULL,
+                    timestamp INTEGER NOT NULL
+                );",
+                PRIVATE_ENVELOPES_TABLE_NAME
+            ),
+        ];
+
+        for schema in schemas {
+            if let Err(error) = self.connection.execute(schema) {
+                println!("create_privacy_tables error: {}", error);
+            }
+        }
+    }
+
+    pub fn write_private_alias(&self, private_alias: PrivateAlias) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "INSERT OR REPLACE INTO {PRIVATE_ALIASES_TABLE_NAME} (data_key, alias, owner, transaction_hash, timestamp) VALUES (?, ?, ?, ?, ?)"
+        ))?;
+
+        statement.bind(1, &Value::String(private_alias.data_key))?;
+        statement.bind(2, &Value::String(private_alias.alias))?;
+        statement.bind(3, &Value::String(private_alias.owner))?;
+        statement.bind(4, &Value::String(private_alias.transaction_hash))?;
+        statement.bind(5, &Value::Integer(private_alias.timestamp as i64))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    pub fn delete_private_alias(
+        &self,
+        data_key: String,
+        alias: String,
+        owner: String,
+    ) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "DELETE FROM {PRIVATE_ALIASES_TABLE_NAME} WHERE data_key = ? AND alias = ? AND owner = ?"
+        ))?;
+
+        statement.bind(1, &Value::String(data_key))?;
+        statement.bind(2, &Value::String(alias))?;
+        statement.bind(3, &Value::String(owner))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    pub fn is_private_alias(
+        &self,
+        data_key: String,
+        alias: String,
+        owner: String,
+    ) -> Result<bool, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {PRIVATE_ALIASES_TABLE_NAME} WHERE data_key = ? AND alias = ? AND owner = ?"
+        ))?;
+
+        statement.bind(1, &Value::String(data_key))?;
+        statement.bind(2, &Value::String(alias))?;
+        statement.bind(3, &Value::String(owner))?;
+
+        Ok(matches!(statement.next()?, State::Row))
+    }
+
+    /**
+     * Private aliases of the owner under a data key, an empty alias matches
+     * every alias
+     */
+    pub fn get_private_aliases(
+        &self,
+        data_key: String,
+        alias: String,
+        owner: String,
+    ) -> Result<Vec<PrivateAlias>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {PRIVATE_ALIASES_TABLE_NAME} WHERE data_key = ? AND owner = ? AND (alias = ? OR ? = '') ORDER BY alias ASC"
+        ))?;
+
+        statement.bind(1, &Value::String(data_key))?;
+        statement.bind(2, &Value::String(owner))?;
+        statement.bind(3, &Value::String(alias.clone()))?;
+        statement.bind(4, &Value::String(alias))?;
+
+        let mut private_aliases = Vec::new();
+
+        while let State::Row = statement.next()? {
+            private_aliases.push(read_private_alias(&statement)?);
+        }
+
+        Ok(private_aliases)
+    }
+
+    pub fn get_all_private_aliases(&self) -> Result<Vec<PrivateAlias>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {PRIVATE_ALIASES_TABLE_NAME} ORDER BY timestamp ASC"
+        ))?;
+
+        let mut private_aliases = Vec::new();
+
+        while let State::Row = statement.next()? {
+            private_aliases.push(read_private_alias(&statement)?);
+        }
+
+        Ok(private_aliases)
+    }
+
+    pub fn write_private_envelope(&self, envelope: PrivateEnvelope) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "INSERT OR REPLACE INTO {PRIVATE_ENVELOPES_TABLE_NAME} (cid, data_key, alias, owner, envelope, timestamp) VALUES (?, ?, ?, ?, ?, ?)"
+        ))?;
+
+        statement.bind(1, &Value::String(envelope.cid))?;
+        statement.bind(2, &Value::String(envelope.data_key))?;
+        statement.bind(3, &Value::String(envelope.alias))?;
+        statement.bind(4, &Value::String(envelope.owner))?;
+        statement.bind(5, &Value::String(envelope.envelope))?;
+        statement.bind(6, &Value::Integer(envelope.timestamp as i64))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    pub fn get_private_envelope(&self, cid: String) -> Result<PrivateEnvelope, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {PRIVATE_ENVELOPES_TABLE_NAME} WHERE cid = ?"
+        ))?;
+
+        statement.bind(1, &Value::String(cid.clone()))?;
+
+        if let State::Row = statement.next()? {
+            read_envelope(&statement)
+        } else {
+            Err(RecordNotFound(f!("envelope {cid}")))
+        }
+    }
+
+    pub fn get_all_private_envelopes(&self) -> Result<Vec<PrivateEnvelope>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {PRIVATE_ENVELOPES_TABLE_NAME} ORDER BY timestamp ASC"
+        ))?;
+
+        let mut envelopes = Vec::new();
+
+        while let State::Row = statement.next()? {
+            envelopes.push(read_envelope(&statement)?);
+        }
+
+        Ok(envelopes)
+    }
+
+    /**
+     * Envelopes of the owner's rows of a data key, an empty alias matches
+     * every alias
+     */
+    pub fn get_private_envelopes(
+        &self,
+        data_key: String,
+        alias: String,
+        owner: String,
+    ) -> Result<Vec<PrivateEnvelope>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {PRIVATE_ENVELOPES_TABLE_NAME} WHERE data_key = ? AND owner = ? AND (alias = ? OR ? = '') ORDER BY timestamp ASC"
+        ))?;
+
+        statement.bind(1, &Value::String(data_key))?;
+        statement.bind(2, &Value::String(owner))?;
+        statement.bind(3, &Value::String(alias.clone()))?;
+        statement.bind(4, &Value::String(alias))?;
+
+        let mut envelopes = Vec::new();
+
+        while let State::Row = statement.next()? {
+            envelopes.push(read_envelope(&statement)?);
+        }
+
+        Ok(envelopes)
+    }
+}
+
+/**
+ * Node key every private envelope is also wrapped for, so keys can be
+ * re-wrapped and content re-encrypted when readers change. It is the base58
+ * 64 byte ed25519 secret key `generate_keypair` returns, read from the module
+ * env so the secret is never written to the database or a snapshot. Every
+ * node opening the same envelopes needs the same key.
+ */
+fn custodian() -> Result<(String, String), ServiceError> {
+    let private_key = std::env::var(CUSTODIAN_KEY_ENV).unwrap_or_default();
+
+    if private_key.is_empty() {
+        return Err(InvalidEncryption(f!(
+            "{CUSTODIAN_KEY_ENV} is not configured"
+        )));
+    }
+
+    let bytes = bs58::decode(&private_key)
+        .into_vec()
+        .map_err(|_| InvalidEncryption(f!("{CUSTODIAN_KEY_ENV} is not base58")))?;
+
+    if bytes.len() != 64 {
+        return Err(InvalidEncryption(f!(
+            "{CUSTODIAN_KEY_ENV} is not a 64 byte ed25519 secret key"
+        )));
+    }
+
+    // the secret key is the seed followed by the public key
+    Ok((bs58::encode(&bytes[32..]).into_string(), private_key))
+}
+
+/**
+ * A "privacy" request is only valid for the alias and nonce it was signed
+ * for, and only once
+ */
+pub fn parse_privacy<F>(
+    scope: &SignedScope,
+    data: &str,
+    is_applied: F,
+) -> Result<SerdePrivacy, ServiceError>
+where
+    F: FnOnce() -> Result<bool, ServiceError>,
+{
+    let method = scope.method.as_str();
+
+    if method != METHOD_PRIVACY {
+        return Err(InvalidMethod(f!("{method} is not a privacy method")));
+    }
+
+    let request: SerdePrivacy = serde_json::from_str(data)
+        .map_err(|e| InvalidDataFormatForMethodType(f!("{method}: {e}")))?;
+
+    request.scope.check(scope)?;
+
+    if scope.data_key.is_empty() || scope.alias.is_empty() {
+        return Err(InvalidDataFormatForMethodType(method.to_string()));
+    }
+
+    scope.check_not_applied(is_applied)?;
+
+    Ok(request)
+}
+
+/**
+ * Checks a "privacy" transaction before it is recorded. The signer marks its
+ * own alias, which does not need to exist yet.
+ */
+pub fn check_privacy_request(
+    storage: &Storage,
+    scope: &SignedScope,
+    public_key: &str,
+    data: &str,
+) -> Result<SerdePrivacy, ServiceError> {
+    parse_privacy(scope, data, || {
+        storage.is_applied_payload(
+            public_key.to_string(),
+            scope.method.clone(),
+            data.to_string(),
+        )
+    })
+}
+
+/**
+ * Validated "privacy" method type. Only later writes are affected, content
+ * already in IPFS stays as it was written.
+ */
+pub fn validate_privacy(transaction_hash: String) {
+    let storage = get_storage().expect("Internal error to database connector");
+    let mut transaction = storage.get_transaction(transaction_hash).unwrap().clone();
+
+    match apply_privacy(&storage, &transaction) {
+        Ok(()) => {
+            transaction.status = STATUS_SUCCESS;
+            transaction.error_text = "".to_string();
+        }
+        Err(e) => {
+            transaction.status = STATUS_FAILED;
+            transaction.error_text = e.to_string();
+        }
+    }
+
+    let _ = storage.update_transaction_status(
+        transaction.hash.clone(),
+        transaction.status,
+        transaction.error_text.clone(),
+    );
+}
+
+fn apply_privacy(storage: &Storage, transaction: &Transaction) -> Result<(), ServiceError> {
+    let request = check_privacy_request(
+        storage,
+        &SignedScope::from(transaction),
+        &transaction.public_key,
+        &transaction.data,
+    )?;
+
+    if request.private {
+        storage.write_private_alias(PrivateAlias {
+            data_key: transaction.data_key.clone(),
+            alias: transaction.alias.clone(),
+            owner: transaction.public_key.clone(),
+            transaction_hash: transaction.hash.clone(),
+            timestamp: transaction.timestamp,
+        })
+    } else {
+        storage.delete_private_alias(
+            transaction.data_key.clone(),
+            transaction.alias.clone(),
+            transaction.public_key.clone(),
+        )
+    }
+}
+
+/**
+ * Envelope to store in place of `content` when the owner's alias is private,
+ * None when it is public. The cid is filled in once the block is written.
+ */
+pub fn seal_content(
+    storage: &Storage,
+    data_key: &str,
+    alias: &str,
+    owner: &str,
+    content: &str,
+    timestamp: u64,
+) -> Result<Option<PrivateEnvelope>, ServiceError> {
+    if !storage.is_private_alias(data_key.to_string(), alias.to_string(), owner.to_string())? {
+        return Ok(None);
+    }
+
+    let recipients = private_recipients(storage, data_key, alias, owner)?;
+    let result = encrypt_for_recipients(recipients, content.to_string());
+
+    if !result.success {
+        return Err(InternalError(f!("encrypt {data_key} {alias}: {result.err_msg}")));
+    }
+
+    Ok(Some(PrivateEnvelope {
+        cid: "".to_string(),
+        data_key: data_key.to_string(),
+        alias: alias.to_string(),
+        owner: owner.to_string(),
+        envelope: result.envelope,
+        timestamp,
+    }))
+}
+
+/**
+ * Wraps the content keys of the owner's private envelopes again for the
+ * current readers. Only the copies kept by the node change, the blocks in
+ * IPFS are not rewritten, see `reseal_private_heads` for revokes.
+ */
+pub fn rewrap_private_envelopes(
+    storage: &Storage,
+    data_key: &str,
+    alias: &str,
+    owner: &str,
+) -> Result<(), ServiceError> {
+    let envelopes =
+        storage.get_private_envelopes(data_key.to_string(), alias.to_string(), owner.to_string())?;
+
+    if envelopes.is_empty() {
+        return Ok(());
+    }
+
+    let (_, custodian_sk) = custodian()?;
+
+    for mut envelope in envelopes {
+        let recipients = private_recipients(storage, data_key, &envelope.alias, owner)?;
+        let result = rewrap(
+            envelope.envelope.clone(),
+            custodian_sk.clone(),
+            ENCRYPTION_TYPE_ED25519.to_string(),
+            recipients,
+        );
+
+        if !result.success {
+            return Err(InternalError(f!("rewrap {envelope.cid}: {result.err_msg}")));
+        }
+
+        envelope.envelope = result.envelope;
+        envelope.timestamp = physical_now();
+        storage.write_private_envelope(envelope)?;
+    }
+
+    Ok(())
+}
+
+/**
+ * Re-encrypts the current content of the owner's private aliases under a
+ * fresh content key, after a reader was revoked. Re-wrapping alone is not
+ * enough, the revoked reader may already hold the old content key. Each
+ * alias gets a new block chained to its head, recorded with a receipt of
+ * `transaction`. Older blocks stay readable with the keys they had.
+ */
+pub fn reseal_private_heads(
+    storage: &Storage,
+    transaction: &Transaction,
+) -> Result<(), ServiceError> {
+    let private_aliases = storage.get_private_aliases(
+        transaction.data_key.clone(),
+        transaction.alias.clone(),
+        transaction.public_key.clone(),
+    )?;
+
+    if private_aliases.is_empty() {
+        return Ok(());
+    }
+
+    let (_, custodian_sk) = custodian()?;
+
+    for private_alias in private_aliases {
+        let head = match storage.get_owner_metadata_by_datakey_and_alias(
+            private_alias.data_key.clone(),
+            private_alias.owner.clone(),
+            private_alias.alias.clone(),
+        ) {
+            Ok(metadata) => metadata,
+            Err(RecordNotFound(_)) => continue,
+            Err(e) => return Err(e),
+        };
+
+        // the head was written before the alias turned private
+        let current = match storage.get_private_envelope(head.cid.clone()) {
+            Ok(envelope) => envelope,
+            Err(RecordNotFound(_)) => continue,
+            Err(e) => return Err(e),
+        };
+
+        let opened = decrypt(
+            current.envelope,
+            custodian_sk.clone(),
+            ENCRYPTION_TYPE_ED25519.to_string(),
+        );
+
+        if !opened.success {
+            return Err(InternalError(f!("decrypt {head.cid}: {opened.err_msg}")));
+        }
+
+        let Some(mut envelope) = seal_content(
+            storage,
+            &head.data_key,
+            &head.alias,
+            &head.public_key,
+            &opened.plaintext,
+            transaction.timestamp,
+        )?
+        else {
+            continue;
+        };
+
+        let tx = TransactionSubset {
+            hash: transaction.hash.clone(),
+            timestamp: transaction.timestamp,
+            hlc: transaction.hlc,
+            meta_contract_id: transaction.meta_contract_id.clone(),
+            method: transaction.method.clone(),
+            value: "".to_string(),
+        };
+
+        let result = put_block(
+            envelope.envelope.clone(),
+            head.cid.clone(),
+            serde_json::to_string(&tx).unwrap(),
+            "".to_string(),
+            0,
+        );
+
+        if !result.success || result.cid.is_empty() {
+            return Err(InternalError(f!("ipfs put failed: {result.error}")));
+        }
+
+        envelope.cid = result.cid.clone();
+        storage.write_private_envelope(envelope)?;
+
+        storage.write_receipt(Receipt {
+            transaction_hash: transaction.hash.clone(),
+            data_key: head.data_key.clone(),
+            alias: head.alias.clone(),
+            public_key: head.public_key.clone(),
+            cid: result.cid.clone(),
+        })?;
+
+        storage.update_cid(head.data_key, head.alias, head.public_key, result.cid)?;
+    }
+
+    Ok(())
+}
+
+/**
+ * The custodian key, the active keys of the owner's identity and the
+ * unexpired "read_private" grantees on the alias or the whole data key.
+ * Addresses carry no public key and are left out.
+ */
+fn private_recipients(
+    storage: &Storage,
+    data_key: &str,
+    alias: &str,
+    owner: &str,
+) -> Result<Vec<String>, ServiceError> {
+    let (custodian_pk, _) = custodian()?;
+    let mut recipients = vec![custodian_pk];
+
+    for key in storage.get_account_keys(owner.to_string())? {
+        if !storage.is_revoked_key(key.clone())? && is_encryptable(&key) {
+            recipients.push(key);
+        }
+    }
+
+    if recipients.len() == 1 {
+        return Err(InvalidEncryption(f!(
+            "{owner} has no key private content can be encrypted to"
+        )));
+    }
+
+    let now = physical_now();
+
+    for acl in storage.get_acls(data_key.to_string(), owner.to_string())? {
+        if acl.permission == ACL_PERMISSION_READ_PRIVATE
+            && (acl.alias == alias || acl.alias.is_empty())
+            && (acl.expires_at == 0 || acl.expires_at > now)
+            && !recipients.contains(&acl.grantee)
+            && is_encryptable(&acl.grantee)
+        {
+            recipients.push(acl.grantee);
+        }
+    }
+
+    Ok(recipients)
+}
+
+fn is_encryptable(public_key: &str) -> bool {
+    let key_type = get_public_key_type(public_key);
+
+    key_type.success
+        && (key_type.enc == ENCRYPTION_TYPE_ED25519
+            || (key_type.enc == ENCRYPTION_TYPE_SECP256K1
+                && key_type.format != KEY_FORMAT_ADDRESS
+                && key_type.format != KEY_FORMAT_DID_PKH))
+}
+
+pub fn read_private_alias(statement: &Statement) -> Result<PrivateAlias, ServiceError> {
+    Ok(PrivateAlias {
+        data_key: statement.read::<String>(0)?,
+        alias: statement.read::<String>(1)?,
+        owner: statement.read::<String>(2)?,
+        transaction_hash: statement.read::<String>(3)?,
+        timestamp: statement.read::<i64>(4)? as u64,
+    })
+}
+
+pub fn read_envelope(statement: &Statement) -> Result<PrivateEnvelope, ServiceError> {
+    Ok(PrivateEnvelope {
+        cid: statement.read::<String>(0)?,
+        data_key: statement.read::<String>(1)?,
+        alias: statement.read::<String>(2)?,
+        owner: statement.read::<String>(3)?,
+        envelope: statement.read::<String>(4)?,
+        timestamp: statement.read::<i64>(5)? as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn privacy(private: bool, data_key: &str, alias: &str, nonce: i64) -> String {
+        serde_json::json!({
+            "private": private,
+            "method": METHOD_PRIVACY,
+            "data_key": data_key,
+            "alias": alias,
+            "nonce": nonce,
+        })
+        .to_string()
+    }
+
+    fn not_applied() -> Result<bool, ServiceError> {
+        Ok(false)
+    }
+
+    #[test]
+    fn privacy_is_bound_to_its_alias_and_nonce() {
+        let scope = SignedScope::new(METHOD_PRIVACY, "", "dk", "profile", 1);
+
+        let request = parse_privacy(&scope, &privacy(true, "dk", "profile", 1), not_applied);
+        assert!(request.unwrap().private);
+
+        for data in [
+            privacy(true, "other", "profile", 1),
+            privacy(true, "dk", "other", 1),
+            privacy(true, "dk", "profile", 2),
+            r#"{"private":true}"#.to_string(),
+        ] {
+            assert!(parse_privacy(&scope, &data, not_applied).is_err());
+        }
+    }
+
+    #[test]
+    fn privacy_needs_a_data_key_and_alias() {
+        let scope = SignedScope::new(METHOD_PRIVACY, "", "dk", "", 1);
+
+        assert!(parse_privacy(&scope, &privacy(true, "dk", "", 1), not_applied).is_err());
+    }
+
+    #[test]
+    fn replayed_privacy_is_rejected() {
+        let scope = SignedScope::new(METHOD_PRIVACY, "", "dk", "profile", 4);
+        let data = privacy(false, "dk", "profile", 4);
+
+        assert!(parse_privacy(&scope, &data, not_applied).is_ok());
+        assert!(matches!(
+            parse_privacy(&scope, &data, || Ok(true)),
+            Err(ServiceError::ReplayedPayload(_))
+        ));
+    }
+
+    #[test]
+    fn custodian_comes_from_the_env() {
+        let public_key = [2u8; 32];
+        let private_key = bs58::encode([[1u8; 32], public_key].concat()).into_string();
+
+        std::env::set_var(CUSTODIAN_KEY_ENV, &private_key);
+        assert_eq!(
+            custodian().unwrap(),
+            (bs58::encode(public_key).into_string(), private_key)
+        );
+
+        std::env::set_var(CUSTODIAN_KEY_ENV, bs58::encode(public_key).into_string());
+        assert!(custodian().is_err());
+
+        std::env::set_var(CUSTODIAN_KEY_ENV, "");
+        assert!(custodian().is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use marine_rs_sdk::marine;
+use marine_sqlite_connector::{State, Statement, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::defaults::RECEIPTS_TABLE_NAME;
+use crate::error::ServiceError;
+use crate::storage_impl::Storage;
+
+/**
+ * Result of a transaction on a single metadata alias. Validators record one
+ * receipt per block they write, so metadata rows can be derived again from
+ * the transaction log.
+ */
+#[marine]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Receipt {
+    pub transaction_hash: String,
+    pub data_key: String,
+    pub alias: String,
+    pub public_key: String,
+    pub cid: String,
+}
+
+impl Storage {
+    pub fn create_receipts_table(&self) {
+        let table_schema = format!(
+            "
+            CREATE TABLE IF NOT EXISTS {} (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                transaction_hash TEXT NOT NULL,
+                data_key TEXT NOT NULL,
+                alias varchar(255),
+                public_key TEXT NOT NULL,
+                cid TEXT NOT NULL
+            );",
+            RECEIPTS_TABLE_NAME
+        );
+
+        let result = self.connection.execute(table_schema);
+
+        if let Err(error) = result {
+            println!("create_receipts_table error: {}", error);
+        }
+    }
+
+    pub fn write_receipt(&self, receipt: Receipt) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "INSERT INTO {RECEIPTS_TABLE_NAME} (transaction_hash, data_key, alias, public_key, cid) VALUES (?, ?, ?, ?, ?)"
+        ))?;
+
+        statement.bind(1, &Value::String(receipt.transaction_hash))?;
+        statement.bind(2, &Value::String(receipt.data_key))?;
+        statement.bind(3, &Value::String(receipt.alias))?;
+        statement.bind(4, &Value::String(receipt.public_key))?;
+        statement.bind(5, &Value::String(receipt.cid))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    pub fn get_all_receipts(&self) -> Result<Vec<Receipt>, ServiceError> {
+        let mut statement = self
+            .connection
+            .prepare(f!("SELECT * FROM {RECEIPTS_TABLE_NAME} ORDER BY id ASC"))?;
+
+        let mut receipts = Vec::new();
+
+        while let State::Row = statement.next()? {
+            receipts.push(read(&statement)?);
+        }
+
+        Ok(receipts)
+    }
+
+    /**
+     * Receipts written for one metadata alias, newest first
+     */
+    pub fn get_receipts_by_metadata(
+        &self,
+        data_key: String,
+        alias: String,
+        public_key: String,
+    ) -> Result<Vec<Receipt>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {RECEIPTS_TABLE_NAME} WHERE data_key = ? AND alias = ? AND public_key = ? ORDER BY id DESC"
+        ))?;
+
+        statement.bind(1, &Value::String(data_key))?;
+        statement.bind(2, &Value::String(alias))?;
+        statement.bind(3, &Value::String(public_key))?;
+
+        let mut receipts = Vec::new();
+
+        while let State::Row = statement.next()? {
+            receipts.push(read(&statement)?);
+        }
+
+        Ok(receipts)
+    }
+
+    pub fn get_receipts_by_transaction(
+        &self,
+        transaction_hash: String,
+    ) -> Result<Vec<Receipt>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {RECEIPTS_TABLE_NAME} WHERE transaction_hash = ? ORDER BY id ASC"
+        ))?;
+
+        statement.bind(1, &Value::String(transaction_hash))?;
+
+        let mut receipts = Vec::new();
+
+        while let State::Row = statement.next()? {
+            receipts.push(read(&statement)?);
+        }
+
+        Ok(receipts)
+    }
+}
+
+pub fn read(statement: &Statement) -> Result<Receipt, ServiceError> {
+    Ok(Receipt {
+        transaction_hash: statement.read::<String>(1)?,
+        data_key: statement.read::<String>(2)?,
+        alias: statement.read::<String>(3)?,
+        public_key: statement.read::<String>(4)?,
+        cid: statement.read::<String>(5)?,
+    })
+}
@@ -0,0 +1,516 @@
+use std::collections::BTreeMap;
+
+use marine_rs_sdk::marine;
+
+use crate::clock::physical_now;
+use crate::cron::{Cron, SerdeCron};
+use crate::defaults::{
+    CRON_ACTION_CREATE, CRON_ACTION_UPDATE, CRON_ACTION_UPDATE_STATUS, CRON_STATUS_ACTIVE,
+    METHOD_ACCEPT_TRANSFER, METHOD_CLONE, METHOD_CONTRACT, METHOD_CRON, METHOD_METADATA,
+    METHOD_REVOKE, METHOD_TRANSFER, REPLAY_ISSUE_MISMATCH, REPLAY_ISSUE_MISSING,
+    REPLAY_ISSUE_NO_RECEIPT, REPLAY_ISSUE_UNTRACKED, REPLAY_KIND_CRON, REPLAY_KIND_METADATA,
+    REPLAY_KIND_META_CONTRACT, TRANSFER_KIND_META_CONTRACT,
+};
+use crate::error::ServiceError;
+use crate::error::ServiceError::InvalidReplayRange;
+use crate::meta_contract::MetaContract;
+use crate::metadatas::Metadata;
+use crate::receipt::Receipt;
+use crate::storage_impl::{get_storage, Storage};
+use crate::transfer::Transfer;
+
+#[marine]
+#[derive(Debug, Default, Clone)]
+pub struct ReplayDivergence {
+    pub kind: String,
+    pub key: String,
+    pub issue: String,
+    pub current: String,
+    pub expected: String,
+}
+
+#[marine]
+#[derive(Debug, Default, Clone)]
+pub struct ReplayReport {
+    pub replayed: u64,
+    pub skipped: u64,
+    pub applied: bool,
+    pub divergences: Vec<ReplayDivergence>,
+}
+
+/**
+ * State derived from the transaction log, keyed the same way as the tables
+ */
+#[derive(Default)]
+struct DerivedState {
+    meta_contracts: BTreeMap<String, MetaContract>,
+    metadatas: BTreeMap<String, Metadata>,
+    crons: BTreeMap<String, Cron>,
+}
+
+/**
+ * Re-derives meta contract bindings, metadata rows and crons from successful
+ * transactions with a timestamp between `from` and `to` and compares them to
+ * the current tables. Unless `dry_run` is set, diverging rows are overwritten
+ * with the derived values.
+ *
+ * Rows that no transaction in the range accounts for are only reported when
+ * replaying from the start of the log, and are never removed.
+ */
+pub fn replay_transactions(
+    from: i64,
+    to: i64,
+    dry_run: bool,
+) -> Result<ReplayReport, ServiceError> {
+    check_replay_range(from, to, dry_run)?;
+
+    let storage = get_storage()?;
+    let to = if to == 0 { physical_now() as i64 } else { to };
+
+    let mut report = ReplayReport::default();
+    let derived = derive_state(&storage, from, to, &mut report)?;
+
+    compare_meta_contracts(&storage, &derived, dry_run, &mut report)?;
+    compare_metadatas(&storage, &derived, dry_run, &mut report)?;
+    compare_crons(&storage, &derived, dry_run, &mut report)?;
+
+    if from == 0 {
+        report_untracked(&storage, &derived, &mut report)?;
+    }
+
+    report.applied = !dry_run;
+
+    Ok(report)
+}
+
+/**
+ * A partial range only sees part of the history of a row, so writing what it
+ * derives would roll rows back to an older value or drop later changes.
+ * Applying is therefore limited to a rebuild of the whole log, partial ranges
+ * can only be inspected with `dry_run`.
+ */
+fn check_replay_range(from: i64, to: i64, dry_run: bool) -> Result<(), ServiceError> {
+    if dry_run || (from == 0 && to == 0) {
+        return Ok(());
+    }
+
+    Err(InvalidReplayRange(f!(
+        "{from}..{to} is a partial range, only a dry run is allowed"
+    )))
+}
+
+fn derive_state(
+    storage: &Storage,
+    from: i64,
+    to: i64,
+    report: &mut ReplayReport,
+) -> Result<DerivedState, ServiceError> {
+    let mut state = DerivedState::default();
+
+    for transaction in storage.get_success_transactions(from, to)? {
+        let method = transaction.method.as_str();
+
+        if method == METHOD_CONTRACT {
+            state
+                .meta_contracts
+                .entry(transaction.token_key.clone())
+                .and_modify(|contract| {
+                    contract.meta_contract_id = transaction.meta_contract_id.clone()
+                })
+                .or_insert(MetaContract {
+                    token_key: transaction.token_key.clone(),
+                    meta_contract_id: transaction.meta_contract_id.clone(),
+                    public_key: transaction.public_key.clone(),
+                });
+        } else if method == METHOD_METADATA || method == METHOD_CLONE {
+            let receipts = storage.get_receipts_by_transaction(transaction.hash.clone())?;
+
+            if receipts.is_empty() {
+                report.skipped += 1;
+                report.divergences.push(ReplayDivergence {
+                    kind: REPLAY_KIND_METADATA.to_string(),
+                    key: transaction.hash.clone(),
+                    issue: REPLAY_ISSUE_NO_RECEIPT.to_string(),
+                    current: "".to_string(),
+                    expected: "".to_string(),
+                });
+                continue;
+            }
+
+            derive_receipts(&mut state, receipts);
+        } else if method == METHOD_REVOKE {
+            // revoking a private reader re-encrypts the alias heads into new blocks
+            let receipts = storage.get_receipts_by_transaction(transaction.hash.clone())?;
+
+            if receipts.is_empty() {
+                report.skipped += 1;
+                continue;
+            }
+
+            derive_receipts(&mut state, receipts);
+        } else if method == METHOD_TRANSFER || method == METHOD_ACCEPT_TRANSFER {
+            match storage.get_transfer_completed_by(transaction.hash.clone()) {
+                Ok(transfer) => derive_transfer(storage, &mut state, transfer)?,
+                Err(ServiceError::RecordNotFound(_)) => {
+                    // transfer still waiting for the recipient
+                    report.skipped += 1;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        } else if method == METHOD_CRON {
+            let serde_cron: SerdeCron = match serde_json::from_str(&transaction.data) {
+                Ok(serde_cron) => serde_cron,
+                Err(_) => {
+                    report.skipped += 1;
+                    continue;
+                }
+            };
+
+            derive_cron(
+                &mut state,
+                serde_cron,
+                &transaction.token_key,
+                &transaction.public_key,
+            );
+        } else {
+            report.skipped += 1;
+            continue;
+        }
+
+        report.replayed += 1;
+    }
+
+    Ok(state)
+}
+
+/**
+ * Metadata rows pointing at the blocks a transaction wrote
+ */
+fn derive_receipts(state: &mut DerivedState, receipts: Vec<Receipt>) {
+    for receipt in receipts {
+        let metadata = Metadata::new(
+            receipt.data_key,
+            receipt.alias,
+            receipt.cid,
+            receipt.public_key,
+        );
+
+        state.metadatas.insert(metadata.hash.clone(), metadata);
+    }
+}
+
+/**
+ * Mirrors `validate_cron`: a create keyed by address, chain and topic, then
+ * updates addressed by the cron hash
+ */
+fn derive_cron(state: &mut DerivedState, serde_cron: SerdeCron, token_key: &str, public_key: &str) {
+    match serde_cron.action.as_str() {
+        CRON_ACTION_CREATE => {
+            let mut cron = Cron::new(
+                token_key.to_string(),
+                serde_cron.address,
+                serde_cron.topic,
+                serde_cron.token_type,
+                serde_cron.chain,
+                serde_cron.status,
+                serde_cron.meta_contract_id,
+                serde_cron.node_url,
+                public_key.to_string(),
+            );
+            cron.status = CRON_STATUS_ACTIVE;
+
+            state.crons.entry(cron.hash.clone()).or_insert(cron);
+        }
+        CRON_ACTION_UPDATE => {
+            if let Some(cron) = state.crons.get_mut(&serde_cron.hash) {
+                cron.meta_contract_id = serde_cron.meta_contract_id;
+                cron.node_url = serde_cron.node_url;
+            }
+        }
+        CRON_ACTION_UPDATE_STATUS => {
+            if let Some(cron) = state.crons.get_mut(&serde_cron.hash) {
+                cron.status = serde_cron.status;
+            }
+        }
+        _ => {}
+    }
+}
+
+/**
+ * Moves a derived entry to the recipient of a completed transfer
+ */
+fn derive_transfer(
+    storage: &Storage,
+    state: &mut DerivedState,
+    transfer: Transfer,
+) -> Result<(), ServiceError> {
+    if transfer.kind == TRANSFER_KIND_META_CONTRACT {
+        if let Some(contract) = state.meta_contracts.get_mut(&transfer.token_key) {
+            contract.public_key = transfer.to_key;
+        }
+    } else {
+        let previous = Metadata::generate_hash(
+            transfer.data_key.clone(),
+            transfer.alias.clone(),
+            transfer.from_key.clone(),
+        );
+        state.metadatas.remove(&previous);
+
+        for receipt in storage.get_receipts_by_transaction(transfer.completed_by)? {
+            let metadata = Metadata::new(
+                receipt.data_key,
+                receipt.alias,
+                receipt.cid,
+                receipt.public_key,
+            );
+
+            state.metadatas.insert(metadata.hash.clone(), metadata);
+        }
+    }
+
+    Ok(())
+}
+
+fn compare_meta_contracts(
+    storage: &Storage,
+    derived: &DerivedState,
+    dry_run: bool,
+    report: &mut ReplayReport,
+) -> Result<(), ServiceError> {
+    for (token_key, expected) in derived.meta_contracts.iter() {
+        let expected_value = f!("{expected.meta_contract_id}#{expected.public_key}");
+
+        match storage.get_meta_contract(token_key.clone()) {
+            Ok(current) => {
+                let current_value = f!("{current.meta_contract_id}#{current.public_key}");
+
+                if current_value != expected_value {
+                    push(
+                        report,
+                        REPLAY_KIND_META_CONTRACT,
+                        token_key,
+                        REPLAY_ISSUE_MISMATCH,
+                        current_value,
+                        expected_value,
+                    );
+
+                    if !dry_run {
+                        storage.update_meta_contract(expected.clone())?;
+                    }
+                }
+            }
+            Err(ServiceError::RecordNotFound(_)) => {
+                push(
+                    report,
+                    REPLAY_KIND_META_CONTRACT,
+                    token_key,
+                    REPLAY_ISSUE_MISSING,
+                    "".to_string(),
+                    expected_value,
+                );
+
+                if !dry_run {
+                    storage.write_meta_contract(expected.clone())?;
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+fn compare_metadatas(
+    storage: &Storage,
+    derived: &DerivedState,
+    dry_run: bool,
+    report: &mut ReplayReport,
+) -> Result<(), ServiceError> {
+    for (hash, expected) in derived.metadatas.iter() {
+        let result = storage.get_owner_metadata_by_datakey_and_alias(
+            expected.data_key.clone(),
+            expected.public_key.clone(),
+            expected.alias.clone(),
+        );
+
+        match result {
+            Ok(current) => {
+                if current.cid != expected.cid {
+                    push(
+                        report,
+                        REPLAY_KIND_METADATA,
+                        hash,
+                        REPLAY_ISSUE_MISMATCH,
+                        current.cid,
+                        expected.cid.clone(),
+                    );
+
+                    if !dry_run {
+                        storage.update_cid(
+                            expected.data_key.clone(),
+                            expected.alias.clone(),
+                            expected.public_key.clone(),
+                            expected.cid.clone(),
+                        )?;
+                    }
+                }
+            }
+            Err(ServiceError::RecordNotFound(_)) => {
+                push(
+                    report,
+                    REPLAY_KIND_METADATA,
+                    hash,
+                    REPLAY_ISSUE_MISSING,
+                    "".to_string(),
+                    expected.cid.clone(),
+                );
+
+                if !dry_run {
+                    storage.write_metadata(expected.clone())?;
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+fn compare_crons(
+    storage: &Storage,
+    derived: &DerivedState,
+    dry_run: bool,
+    report: &mut ReplayReport,
+) -> Result<(), ServiceError> {
+    for (hash, expected) in derived.crons.iter() {
+        let expected_value =
+            f!("{expected.meta_contract_id}#{expected.node_url}#{expected.status}");
+
+        match storage.get_cron_by_hash(hash.clone()) {
+            Ok(current) => {
+                let current_value =
+                    f!("{current.meta_contract_id}#{current.node_url}#{current.status}");
+
+                if current_value != expected_value {
+                    push(
+                        report,
+                        REPLAY_KIND_CRON,
+                        hash,
+                        REPLAY_ISSUE_MISMATCH,
+                        current_value,
+                        expected_value,
+                    );
+
+                    if !dry_run {
+                        storage.update_cron(hash.clone(), expected.clone())?;
+                        storage.update_cron_status(hash.clone(), expected.status)?;
+                    }
+                }
+            }
+            Err(ServiceError::RecordNotFound(_)) => {
+                push(
+                    report,
+                    REPLAY_KIND_CRON,
+                    hash,
+                    REPLAY_ISSUE_MISSING,
+                    "".to_string(),
+                    expected_value,
+                );
+
+                if !dry_run {
+                    storage.write_cron(expected.clone())?;
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+fn report_untracked(
+    storage: &Storage,
+    derived: &DerivedState,
+    report: &mut ReplayReport,
+) -> Result<(), ServiceError> {
+    for contract in storage.get_all_meta_contracts()? {
+        if !derived.meta_contracts.contains_key(&contract.token_key) {
+            push(
+                report,
+                REPLAY_KIND_META_CONTRACT,
+                &contract.token_key,
+                REPLAY_ISSUE_UNTRACKED,
+                contract.meta_contract_id,
+                "".to_string(),
+            );
+        }
+    }
+
+    for metadata in storage.search_metadatas(vec![], vec![], 0, 0)? {
+        if !derived.metadatas.contains_key(&metadata.hash) {
+            push(
+                report,
+                REPLAY_KIND_METADATA,
+                &metadata.hash,
+                REPLAY_ISSUE_UNTRACKED,
+                metadata.cid,
+                "".to_string(),
+            );
+        }
+    }
+
+    for cron in storage.get_all_crons()? {
+        if !derived.crons.contains_key(&cron.hash) {
+            push(
+                report,
+                REPLAY_KIND_CRON,
+                &cron.hash,
+                REPLAY_ISSUE_UNTRACKED,
+                cron.meta_contract_id,
+                "".to_string(),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn push(
+    report: &mut ReplayReport,
+    kind: &str,
+    key: &str,
+    issue: &str,
+    current: String,
+    expected: String,
+) {
+    report.divergences.push(ReplayDivergence {
+        kind: kind.to_string(),
+        key: key.to_string(),
+        issue: issue.to_string(),
+        current,
+        expected,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_range_can_be_applied() {
+        assert!(check_replay_range(0, 0, false).is_ok());
+        assert!(check_replay_range(0, 0, true).is_ok());
+    }
+
+    #[test]
+    fn partial_ranges_are_dry_run_only() {
+        for (from, to) in [(1, 0), (0, 1_700_000_000_000), (5, 10)] {
+            assert!(check_replay_range(from, to, true).is_ok());
+            assert!(matches!(
+                check_replay_range(from, to, false),
+                Err(InvalidReplayRange(_))
+            ));
+        }
+    }
+}
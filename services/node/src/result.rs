@@ -2,24 +2,88 @@ use eyre::Result;
 use marine_rs_sdk::marine;
 
 use crate::{
+    acl::Acl,
+    auth::Operator,
+    clock::HybridTimestamp,
     cron::{Cron, CronResult},
     cron_tx::CronTx,
     error::ServiceError,
+    identity::IdentityKey,
+    integrity::IntegrityReport,
     meta_contract::MetaContract,
     metadatas::Metadata,
+    multisig::{MultisigPolicy, MultisigRequest},
+    privacy::PrivateEnvelope,
+    replay::ReplayReport,
+    session::Session,
+    snapshot::SnapshotSummary,
     transaction::Transaction,
+    transfer::Transfer,
 };
 
+/**
+ * `success` is false when the transaction could not be recorded at all, a
+ * transaction rejected by the checks is recorded as failed under its hash
+ */
 #[marine]
 #[derive(Debug)]
 pub struct FdbResult {
+    pub success: bool,
+    pub err_msg: String,
     pub transaction_hash: String,
 }
 
+#[marine]
+#[derive(Debug)]
+pub struct FdbStatusResult {
+    pub success: bool,
+    pub err_msg: String,
+}
+
+impl From<Result<(), ServiceError>> for FdbStatusResult {
+    fn from(result: Result<(), ServiceError>) -> Self {
+        match result {
+            Ok(()) => Self {
+                success: true,
+                err_msg: "".to_string(),
+            },
+            Err(err) => Self {
+                success: false,
+                err_msg: err.to_string(),
+            },
+        }
+    }
+}
+
 #[marine]
 #[derive(Debug)]
 pub struct FdbClock {
+    pub success: bool,
+    pub err_msg: String,
     pub timestamp: i64,
+    pub logical: u64,
+    pub hlc: u64,
+}
+
+impl From<Result<HybridTimestamp, ServiceError>> for FdbClock {
+    fn from(result: Result<HybridTimestamp, ServiceError>) -> Self {
+        match result {
+            Ok(clock) => Self {
+                success: true,
+                err_msg: "".to_string(),
+                timestamp: clock.wall as i64,
+                logical: clock.logical,
+                hlc: clock.pack(),
+            },
+            Err(err) => Self {
+                success: false,
+                err_msg: err.to_string(),
+                timestamp: 0,
+                logical: 0,
+                hlc: 0,
+            },
+        }
+    }
 }
 
 #[marine]
@@ -249,3 +313,303 @@ impl From<Result<CronTx, ServiceError>> for FdbCronTxResult {
         }
     }
 }
+
+#[marine]
+#[derive(Debug)]
+pub struct FdbSnapshotResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub snapshot: SnapshotSummary,
+}
+
+impl From<Result<SnapshotSummary, ServiceError>> for FdbSnapshotResult {
+    fn from(result: Result<SnapshotSummary, ServiceError>) -> Self {
+        match result {
+            Ok(snapshot) => Self {
+                success: true,
+                err_msg: "".to_string(),
+                snapshot,
+            },
+            Err(err) => Self {
+                success: false,
+                err_msg: err.to_string(),
+                snapshot: SnapshotSummary::default(),
+            },
+        }
+    }
+}
+
+#[marine]
+#[derive(Debug)]
+pub struct FdbReplayResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub report: ReplayReport,
+}
+
+impl From<Result<ReplayReport, ServiceError>> for FdbReplayResult {
+    fn from(result: Result<ReplayReport, ServiceError>) -> Self {
+        match result {
+            Ok(report) => Self {
+                success: true,
+                err_msg: "".to_string(),
+                report,
+            },
+            Err(err) => Self {
+                success: false,
+                err_msg: err.to_string(),
+                report: ReplayReport::default(),
+            },
+        }
+    }
+}
+
+#[marine]
+#[derive(Debug)]
+pub struct FdbIntegrityResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub report: IntegrityReport,
+}
+
+impl From<Result<IntegrityReport, ServiceError>> for FdbIntegrityResult {
+    fn from(result: Result<IntegrityReport, ServiceError>) -> Self {
+        match result {
+            Ok(report) => Self {
+                success: true,
+                err_msg: "".to_string(),
+                report,
+            },
+            Err(err) => Self {
+                success: false,
+                err_msg: err.to_string(),
+                report: IntegrityReport::default(),
+            },
+        }
+    }
+}
+
+#[marine]
+#[derive(Debug)]
+pub struct FdbOperatorsResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub operators: Vec<Operator>,
+}
+
+impl From<Result<Vec<Operator>, ServiceError>> for FdbOperatorsResult {
+    fn from(result: Result<Vec<Operator>, ServiceError>) -> Self {
+        match result {
+            Ok(operators) => Self {
+                success: true,
+                err_msg: "".to_string(),
+                operators,
+            },
+            Err(err) => Self {
+                success: false,
+                err_msg: err.to_string(),
+                operators: Vec::new(),
+            },
+        }
+    }
+}
+
+#[marine]
+#[derive(Debug)]
+pub struct FdbAclsResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub acls: Vec<Acl>,
+}
+
+impl From<Result<Vec<Acl>, ServiceError>> for FdbAclsResult {
+    fn from(result: Result<Vec<Acl>, ServiceError>) -> Self {
+        match result {
+            Ok(acls) => Self {
+                success: true,
+                err_msg: "".to_string(),
+                acls,
+            },
+            Err(err) => Self {
+                success: false,
+                err_msg: err.to_string(),
+                acls: Vec::new(),
+            },
+        }
+    }
+}
+
+#[marine]
+#[derive(Debug)]
+pub struct FdbTransferResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub transfer: Transfer,
+}
+
+impl From<Result<Transfer, ServiceError>> for FdbTransferResult {
+    fn from(result: Result<Transfer, ServiceError>) -> Self {
+        match result {
+            Ok(transfer) => Self {
+                success: true,
+                err_msg: "".to_string(),
+                transfer,
+            },
+            Err(err) => Self {
+                success: false,
+                err_msg: err.to_string(),
+                transfer: Transfer::default(),
+            },
+        }
+    }
+}
+
+#[marine]
+#[derive(Debug)]
+pub struct FdbTransfersResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub transfers: Vec<Transfer>,
+}
+
+impl From<Result<Vec<Transfer>, ServiceError>> for FdbTransfersResult {
+    fn from(result: Result<Vec<Transfer>, ServiceError>) -> Self {
+        match result {
+            Ok(transfers) => Self {
+                success: true,
+                err_msg: "".to_string(),
+                transfers,
+            },
+            Err(err) => Self {
+                success: false,
+                err_msg: err.to_string(),
+                transfers: Vec::new(),
+            },
+        }
+    }
+}
+
+#[marine]
+#[derive(Debug)]
+pub struct FdbIdentityResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub keys: Vec<IdentityKey>,
+}
+
+impl From<Result<Vec<IdentityKey>, ServiceError>> for FdbIdentityResult {
+    fn from(result: Result<Vec<IdentityKey>, ServiceError>) -> Self {
+        match result {
+            Ok(keys) => Self {
+                success: true,
+                err_msg: "".to_string(),
+                keys,
+            },
+            Err(err) => Self {
+                success: false,
+                err_msg: err.to_string(),
+                keys: Vec::new(),
+            },
+        }
+    }
+}
+
+#[marine]
+#[derive(Debug)]
+pub struct FdbMultisigPolicyResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub policy: MultisigPolicy,
+}
+
+impl From<Result<MultisigPolicy, ServiceError>> for FdbMultisigPolicyResult {
+    fn from(result: Result<MultisigPolicy, ServiceError>) -> Self {
+        match result {
+            Ok(policy) => Self {
+                success: true,
+                err_msg: "".to_string(),
+                policy,
+            },
+            Err(err) => Self {
+                success: false,
+                err_msg: err.to_string(),
+                policy: MultisigPolicy::default(),
+            },
+        }
+    }
+}
+
+#[marine]
+#[derive(Debug)]
+pub struct FdbMultisigRequestResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub request: MultisigRequest,
+}
+
+impl From<Result<MultisigRequest, ServiceError>> for FdbMultisigRequestResult {
+    fn from(result: Result<MultisigRequest, ServiceError>) -> Self {
+        match result {
+            Ok(request) => Self {
+                success: true,
+                err_msg: "".to_string(),
+                request,
+            },
+            Err(err) => Self {
+                success: false,
+                err_msg: err.to_string(),
+                request: MultisigRequest::default(),
+            },
+        }
+    }
+}
+
+#[marine]
+#[derive(Debug)]
+pub struct FdbSessionsResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub sessions: Vec<Session>,
+}
+
+impl From<Result<Vec<Session>, ServiceError>> for FdbSessionsResult {
+    fn from(result: Result<Vec<Session>, ServiceError>) -> Self {
+        match result {
+            Ok(sessions) => Self {
+                success: true,
+                err_msg: "".to_string(),
+                sessions,
+            },
+            Err(err) => Self {
+                success: false,
+                err_msg: err.to_string(),
+                sessions: Vec::new(),
+            },
+        }
+    }
+}
+
+#[marine]
+#[derive(Debug)]
+pub struct FdbEnvelopeResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub envelope: PrivateEnvelope,
+}
+
+impl From<Result<PrivateEnvelope, ServiceError>> for FdbEnvelopeResult {
+    fn from(result: Result<PrivateEnvelope, ServiceError>) -> Self {
+        match result {
+            Ok(envelope) => Self {
+                success: true,
+                err_msg: "".to_string(),
+                envelope,
+            },
+            Err(err) => Self {
+                success: false,
+                err_msg: err.to_string(),
+                envelope: PrivateEnvelope::default(),
+            },
+        }
+    }
+}
@@ -0,0 +1,584 @@
+use marine_rs_sdk::marine;
+use marine_sqlite_connector::{State, Statement, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::clock::physical_now;
+use crate::defaults::{
+    ENCRYPTION_TYPE_ED25519, METHOD_CLONE, METHOD_CRON, METHOD_METADATA, METHOD_SESSION_GRANT,
+    METHOD_SESSION_REVOKE, SESSIONS_TABLE_NAME, SESSION_RATE_WINDOW, SESSION_STATUS_ACTIVE,
+    SESSION_STATUS_REVOKED, STATUS_FAILED, STATUS_SUCCESS,
+};
+use crate::error::ServiceError;
+use crate::error::ServiceError::{
+    InvalidDataFormatForMethodType, InvalidEncryption, InvalidMethod, InvalidOwner,
+    InvalidSignature, RecordFound, RecordNotFound, SessionDenied,
+};
+use crate::storage_impl::{get_storage, Storage};
+use crate::transaction::{SignedScope, Transaction};
+use crate::{get_public_key_type, verify};
+
+/**
+ * Ephemeral ed25519 key allowed to sign transactions on behalf of
+ * `public_key`. Empty `token_keys`, `methods` or `aliases` do not restrict
+ * that dimension, a `rate_limit` of 0 means no cap per `SESSION_RATE_WINDOW`.
+ */
+#[marine]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub session_key: String,
+    pub public_key: String,
+    pub token_keys: Vec<String>,
+    pub methods: Vec<String>,
+    pub aliases: Vec<String>,
+    pub expires_at: u64,
+    pub rate_limit: u32,
+    pub window_start: u64,
+    pub window_count: u32,
+    pub status: i64,
+    pub transaction_hash: String,
+    pub timestamp: u64,
+}
+
+/**
+ * `data` of a "session_grant" transaction. `session_signature` is the session
+ * key's signature of `session_grant_message`, proving the granter holds it.
+ * For "session_revoke" only `session_key` is read.
+ */
+#[derive(Debug, Default, Deserialize)]
+pub struct SerdeSession {
+    pub session_key: String,
+    #[serde(default)]
+    pub session_signature: String,
+    #[serde(default)]
+    pub token_keys: Vec<String>,
+    #[serde(default)]
+    pub methods: Vec<String>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub expires_at: u64,
+    #[serde(default)]
+    pub rate_limit: u32,
+}
+
+impl Storage {
+    pub fn create_sessions_table(&self) {
+        let table_schema = format!(
+            "
+            CREATE TABLE IF NOT EXISTS {} (
+                session_key TEXT PRIMARY KEY UNIQUE,
+                public_key TEXT NOT NULL,
+                token_keys TEXT NOT NULL,
+                methods TEXT NOT NULL,
+                aliases TEXT NOT NULL,
+                expires_at INTEGER NOT NULL,
+                rate_limit INTEGER NOT NULL,
+                window_start INTEGER NOT NULL,
+                window_count INTEGER NOT NULL,
+                status INTEGER NOT NULL,
+                transaction_hash TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            );",
+            SESSIONS_TABLE_NAME
+        );
+
+        let result = self.connection.execute(table_schema);
+
+        if let Err(error) = result {
+            println!("create_sessions_table error: {}", error);
+        }
+    }
+
+    pub fn write_session(&self, session: Session) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "INSERT OR REPLACE INTO {SESSIONS_TABLE_NAME} (session_key, public_key, token_keys, methods, aliases, expires_at, rate_limit, window_start, window_count, status, transaction_hash, timestamp) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        ))?;
+
+        statement.bind(1, &Value::String(session.session_key))?;
+        statement.bind(2, &Value::String(session.public_key))?;
+        statement.bind(3, &Value::String(to_json(&session.token_keys)))?;
+        statement.bind(4, &Value::String(to_json(&session.methods)))?;
+        statement.bind(5, &Value::String(to_json(&session.aliases)))?;
+        statement.bind(6, &Value::Integer(session.expires_at as i64))?;
+        statement.bind(7, &Value::Integer(session.rate_limit as i64))?;
+        statement.bind(8, &Value::Integer(session.window_start as i64))?;
+        statement.bind(9, &Value::Integer(session.window_count as i64))?;
+        statement.bind(10, &Value::Integer(session.status))?;
+        statement.bind(11, &Value::String(session.transaction_hash))?;
+        statement.bind(12, &Value::Integer(session.timestamp as i64))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    pub fn get_session(&self, session_key: String) -> Result<Session, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {SESSIONS_TABLE_NAME} WHERE session_key = ?"
+        ))?;
+
+        statement.bind(1, &Value::String(session_key.clone()))?;
+
+        if let State::Row = statement.next()? {
+            read(&statement)
+        } else {
+            Err(RecordNotFound(f!("session {session_key}")))
+        }
+    }
+
+    pub fn get_sessions(&self, public_key: String) -> Result<Vec<Session>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {SESSIONS_TABLE_NAME} WHERE public_key = ? ORDER BY timestamp ASC"
+        ))?;
+
+        statement.bind(1, &Value::String(public_key))?;
+
+        let mut sessions = Vec::new();
+
+        while let State::Row = statement.next()? {
+            sessions.push(read(&statement)?);
+        }
+
+        Ok(sessions)
+    }
+
+    pub fn get_all_sessions(&self) -> Result<Vec<Session>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {SESSIONS_TABLE_NAME} ORDER BY timestamp ASC"
+        ))?;
+
+        let mut sessions = Vec::new();
+
+        while let State::Row = statement.next()? {
+            sessions.push(read(&statement)?);
+        }
+
+        Ok(sessions)
+    }
+
+    pub fn update_session_usage(
+        &self,
+        session_key: String,
+        window_start: u64,
+        window_count: u32,
+    ) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "UPDATE {SESSIONS_TABLE_NAME} SET window_start = ?, window_count = ? WHERE session_key = ?"
+        ))?;
+
+        statement.bind(1, &Value::Integer(window_start as i64))?;
+        statement.bind(2, &Value::Integer(window_count as i64))?;
+        statement.bind(3, &Value::String(session_key))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    pub fn update_session_status(
+        &self,
+        session_key: String,
+        status: i64,
+    ) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "UPDATE {SESSIONS_TABLE_NAME} SET status = ? WHERE session_key = ?"
+        ))?;
+
+        statement.bind(1, &Value::Integer(status))?;
+        statement.bind(2, &Value::String(session_key))?;
+        statement.next()?;
+
+        Ok(())
+    }
+}
+
+/**
+ * Methods a session key may sign. Grants, transfers and key management always
+ * need the main key.
+ */
+pub fn is_session_method(method: &str) -> bool {
+    method == METHOD_METADATA || method == METHOD_CLONE || method == METHOD_CRON
+}
+
+/**
+ * Session `signer` signs for, if it is a session key at all
+ */
+pub fn find_session(storage: &Storage, signer: &str) -> Result<Option<Session>, ServiceError> {
+    match storage.get_session(signer.to_string()) {
+        Ok(session) => Ok(Some(session)),
+        Err(RecordNotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/**
+ * Checks a transaction signed by a session key against the grant. Returns the
+ * rate window the transaction counts in, recorded by `use_session` once the
+ * transaction is accepted.
+ */
+pub fn check_session(
+    session: &Session,
+    method: &str,
+    token_key: &str,
+    alias: &str,
+    now: u64,
+) -> Result<(u64, u32), ServiceError> {
+    if session.status != SESSION_STATUS_ACTIVE {
+        return Err(SessionDenied(f!("{session.session_key} is revoked")));
+    }
+
+    if session.expires_at <= now {
+        return Err(SessionDenied(f!("{session.session_key} expired")));
+    }
+
+    if !is_session_method(method) || !in_scope(&session.methods, method) {
+        return Err(SessionDenied(f!("method {method} not granted")));
+    }
+
+    if !in_scope(&session.token_keys, token_key) {
+        return Err(SessionDenied(f!("token_key {token_key} not granted")));
+    }
+
+    if !in_scope(&session.aliases, alias) {
+        return Err(SessionDenied(f!("alias {alias} not granted")));
+    }
+
+    let (window_start, window_count) = if now >= session.window_start + SESSION_RATE_WINDOW {
+        (now, 1)
+    } else {
+        (session.window_start, session.window_count + 1)
+    };
+
+    if session.rate_limit > 0 && window_count > session.rate_limit {
+        return Err(SessionDenied(f!(
+            "rate limit of {session.rate_limit} reached"
+        )));
+    }
+
+    Ok((window_start, window_count))
+}
+
+/**
+ * Counts an accepted transaction towards the rate cap of its session
+ */
+pub fn use_session(
+    storage: &Storage,
+    session: &Session,
+    (window_start, window_count): (u64, u32),
+) -> Result<(), ServiceError> {
+    storage.update_session_usage(session.session_key.clone(), window_start, window_count)
+}
+
+fn in_scope(granted: &[String], value: &str) -> bool {
+    granted.is_empty() || granted.iter().any(|g| g == value)
+}
+
+/**
+ * Message a session key signs to accept a grant. It covers the granter, the
+ * whole scope of the grant and the nonce of the transaction.
+ */
+pub fn session_grant_message(granter: &str, request: &SerdeSession, nonce: i64) -> String {
+    format!(
+        "{}:{}:{}:{}:{}:{}:{}:{}",
+        METHOD_SESSION_GRANT,
+        granter,
+        to_json(&request.token_keys),
+        to_json(&request.methods),
+        to_json(&request.aliases),
+        request.rate_limit,
+        request.expires_at,
+        nonce
+    )
+}
+
+/**
+ * Parses the `data` of a "session_grant" or "session_revoke" transaction and
+ * checks what can be checked without the database
+ */
+pub fn parse_session_request(
+    method: &str,
+    public_key: &str,
+    data: &str,
+) -> Result<SerdeSession, ServiceError> {
+    let request: SerdeSession = serde_json::from_str(data)
+        .map_err(|e| InvalidDataFormatForMethodType(f!("{method}: {e}")))?;
+
+    if method != METHOD_SESSION_GRANT {
+        return Ok(request);
+    }
+
+    if request.session_key.is_empty() || request.session_key == public_key {
+        return Err(InvalidDataFormatForMethodType(f!(
+            "invalid session key {request.session_key}"
+        )));
+    }
+
+    if request.session_signature.is_empty() {
+        return Err(InvalidDataFormatForMethodType(f!(
+            "{method} needs the signature of {request.session_key}"
+        )));
+    }
+
+    if request.expires_at <= physical_now() {
+        return Err(InvalidDataFormatForMethodType(f!(
+            "expires_at {request.expires_at} is in the past"
+        )));
+    }
+
+    for method in request.methods.iter() {
+        if !is_session_method(method) {
+            return Err(InvalidDataFormatForMethodType(f!(
+                "method {method} cannot be granted to a session"
+            )));
+        }
+    }
+
+    Ok(request)
+}
+
+/**
+ * Checks a "session_grant" or "session_revoke" transaction before it is
+ * recorded. A session key must be fresh: it owns no data, belongs to no
+ * identity and was never granted before, by anyone.
+ */
+pub fn check_session_request(
+    storage: &Storage,
+    scope: &SignedScope,
+    public_key: &str,
+    data: &str,
+) -> Result<(), ServiceError> {
+    let method = scope.method.as_str();
+    let request = parse_session_request(method, public_key, data)?;
+
+    if method == METHOD_SESSION_GRANT {
+        let key_type = get_public_key_type(&request.session_key);
+
+        if key_type.enc != ENCRYPTION_TYPE_ED25519 {
+            return Err(InvalidEncryption(request.session_key));
+        }
+
+        let message = session_grant_message(public_key, &request, scope.nonce);
+
+        if !verify(
+            key_type.public_key,
+            request.session_signature.clone(),
+            message,
+            key_type.enc,
+        ) {
+            return Err(InvalidSignature(f!(
+                "{request.session_key} did not accept the session"
+            )));
+        }
+
+        if !storage
+            .get_metadatas_by_public_key(request.session_key.clone())?
+            .is_empty()
+        {
+            return Err(RecordFound(f!("metadata owned by {request.session_key}")));
+        }
+
+        match storage.get_identity_key(request.session_key.clone()) {
+            Ok(_) => return Err(RecordFound(f!("identity key {request.session_key}"))),
+            Err(RecordNotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+
+        match storage.get_session(request.session_key.clone()) {
+            Ok(_) => Err(RecordFound(f!("session {request.session_key}"))),
+            Err(RecordNotFound(_)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    } else if method == METHOD_SESSION_REVOKE {
+        let session = storage.get_session(request.session_key)?;
+
+        if !storage.is_same_owner(session.public_key, public_key.to_string())? {
+            return Err(InvalidOwner(f!("not owner of session: {public_key}")));
+        }
+
+        Ok(())
+    } else {
+        Err(InvalidMethod(f!("{method} is not a session method")))
+    }
+}
+
+/**
+ * Validated "session_grant" and "session_revoke" method types
+ */
+pub fn validate_session(transaction_hash: String) {
+    let storage = get_storage().expect("Internal error to database connector");
+    let mut transaction = storage.get_transaction(transaction_hash).unwrap().clone();
+
+    match apply_session(&storage, &transaction) {
+        Ok(()) => {
+            transaction.status = STATUS_SUCCESS;
+            transaction.error_text = "".to_string();
+        }
+        Err(e) => {
+            transaction.status = STATUS_FAILED;
+            transaction.error_text = e.to_string();
+        }
+    }
+
+    let _ = storage.update_transaction_status(
+        transaction.hash.clone(),
+        transaction.status,
+        transaction.error_text.clone(),
+    );
+}
+
+fn apply_session(storage: &Storage, transaction: &Transaction) -> Result<(), ServiceError> {
+    check_session_request(
+        storage,
+        &SignedScope::from(transaction),
+        &transaction.public_key,
+        &transaction.data,
+    )?;
+
+    let request: SerdeSession = serde_json::from_str(&transaction.data)
+        .map_err(|e| InvalidDataFormatForMethodType(e.to_string()))?;
+
+    if transaction.method == METHOD_SESSION_REVOKE {
+        return storage.update_session_status(request.session_key, SESSION_STATUS_REVOKED);
+    }
+
+    storage.write_session(Session {
+        session_key: request.session_key,
+        public_key: transaction.public_key.clone(),
+        token_keys: request.token_keys,
+        methods: request.methods,
+        aliases: request.aliases,
+        expires_at: request.expires_at,
+        rate_limit: request.rate_limit,
+        window_start: 0,
+        window_count: 0,
+        status: SESSION_STATUS_ACTIVE,
+        transaction_hash: transaction.hash.clone(),
+        timestamp: transaction.timestamp,
+    })
+}
+
+fn to_json(values: &[String]) -> String {
+    serde_json::to_string(values).unwrap_or_else(|_| "[]".to_string())
+}
+
+fn from_json(values: &str) -> Vec<String> {
+    serde_json::from_str(values).unwrap_or_default()
+}
+
+pub fn read(statement: &Statement) -> Result<Session, ServiceError> {
+    Ok(Session {
+        session_key: statement.read::<String>(0)?,
+        public_key: statement.read::<String>(1)?,
+        token_keys: from_json(&statement.read::<String>(2)?),
+        methods: from_json(&statement.read::<String>(3)?),
+        aliases: from_json(&statement.read::<String>(4)?),
+        expires_at: statement.read::<i64>(5)? as u64,
+        rate_limit: statement.read::<i64>(6)? as u32,
+        window_start: statement.read::<i64>(7)? as u64,
+        window_count: statement.read::<i64>(8)? as u32,
+        status: statement.read::<i64>(9)?,
+        transaction_hash: statement.read::<String>(10)?,
+        timestamp: statement.read::<i64>(11)? as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grant(session_key: &str, signature: &str, methods: &[&str]) -> String {
+        serde_json::json!({
+            "session_key": session_key,
+            "session_signature": signature,
+            "methods": methods,
+            "expires_at": physical_now() + 60_000,
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn grant_needs_the_session_key_signature() {
+        let signed = grant("0xsession", "0xsig", &[METHOD_METADATA]);
+        let unsigned = grant("0xsession", "", &[METHOD_METADATA]);
+
+        assert!(parse_session_request(METHOD_SESSION_GRANT, "0xmain", &signed).is_ok());
+        assert!(parse_session_request(METHOD_SESSION_GRANT, "0xmain", &unsigned).is_err());
+        assert!(parse_session_request(METHOD_SESSION_REVOKE, "0xmain", &unsigned).is_ok());
+    }
+
+    #[test]
+    fn grant_cannot_name_the_main_key_or_key_management() {
+        let own = grant("0xmain", "0xsig", &[]);
+        let rotate = grant("0xsession", "0xsig", &["rotate_key"]);
+
+        assert!(parse_session_request(METHOD_SESSION_GRANT, "0xmain", &own).is_err());
+        assert!(parse_session_request(METHOD_SESSION_GRANT, "0xmain", &rotate).is_err());
+    }
+
+    fn session(rate_limit: u32, window_start: u64, window_count: u32) -> Session {
+        Session {
+            session_key: "0xsession".to_string(),
+            public_key: "0xmain".to_string(),
+            methods: vec![METHOD_METADATA.to_string()],
+            expires_at: SESSION_RATE_WINDOW * 10,
+            rate_limit,
+            window_start,
+            window_count,
+            status: SESSION_STATUS_ACTIVE,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn session_checks_the_grant_without_counting() {
+        let active = session(0, 0, 0);
+
+        assert!(check_session(&active, METHOD_METADATA, "tk", "profile", 1_000).is_ok());
+        assert!(check_session(&active, METHOD_SESSION_GRANT, "tk", "profile", 1_000).is_err());
+        assert!(check_session(
+            &active,
+            METHOD_METADATA,
+            "tk",
+            "profile",
+            SESSION_RATE_WINDOW * 10
+        )
+        .is_err());
+
+        let revoked = Session {
+            status: SESSION_STATUS_REVOKED,
+            ..session(0, 0, 0)
+        };
+        assert!(check_session(&revoked, METHOD_METADATA, "tk", "profile", 1_000).is_err());
+    }
+
+    #[test]
+    fn session_rate_window_is_returned_for_recording() {
+        let now = SESSION_RATE_WINDOW * 3;
+        let within = session(2, now - 1, 1);
+
+        assert_eq!(
+            check_session(&within, METHOD_METADATA, "", "", now).unwrap(),
+            (now - 1, 2)
+        );
+        assert!(check_session(&session(2, now - 1, 2), METHOD_METADATA, "", "", now).is_err());
+        assert_eq!(
+            check_session(&session(2, 0, 2), METHOD_METADATA, "", "", now).unwrap(),
+            (now, 1)
+        );
+    }
+
+    #[test]
+    fn grant_message_covers_granter_scope_and_nonce() {
+        let data = grant("0xsession", "0xsig", &[METHOD_METADATA]);
+        let request = parse_session_request(METHOD_SESSION_GRANT, "0xmain", &data).unwrap();
+        let message = session_grant_message("0xmain", &request, 1);
+
+        assert_eq!(
+            message,
+            format!(
+                "session_grant:0xmain:[]:[\"metadata\"]:[]:0:{}:1",
+                request.expires_at
+            )
+        );
+        assert_ne!(message, session_grant_message("0xother", &request, 1));
+        assert_ne!(message, session_grant_message("0xmain", &request, 2));
+    }
+}
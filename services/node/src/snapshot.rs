@@ -0,0 +1,596 @@
+use std::collections::HashMap;
+
+use marine_rs_sdk::marine;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::acl::Acl;
+use crate::block::Block;
+use crate::clock::HybridTimestamp;
+use crate::cron::{Cron, CronResult};
+use crate::cron_tx::CronTx;
+use crate::defaults::{
+    ACL_TABLE_NAME, CLOCK_TABLE_NAME, CRON_TABLE_NAME, CRON_TX_TABLE_NAME, IDENTITIES_TABLE_NAME,
+    METADATAS_TABLE_NAME, META_CONTRACT_TABLE_NAME, MULTISIG_POLICIES_TABLE_NAME,
+    MULTISIG_REQUESTS_TABLE_NAME, MULTISIG_SIGNATURES_TABLE_NAME, OPERATORS_TABLE_NAME,
+    PRIVATE_ALIASES_TABLE_NAME, PRIVATE_ENVELOPES_TABLE_NAME, RECEIPTS_TABLE_NAME,
+    SESSIONS_TABLE_NAME, SNAPSHOT_CHUNK_BYTES, SNAPSHOT_FORMAT, SNAPSHOT_VERSION,
+    TRANSACTIONS_TABLE_NAME, TRANSFERS_TABLE_NAME,
+};
+use crate::error::ServiceError;
+use crate::error::ServiceError::{InternalError, InvalidSnapshot};
+use crate::identity::IdentityKey;
+use crate::meta_contract::MetaContract;
+use crate::metadatas::Metadata;
+use crate::multisig::{MultisigPolicy, MultisigRequest, MultisigSignature};
+use crate::privacy::{PrivateAlias, PrivateEnvelope};
+use crate::receipt::Receipt;
+use crate::session::Session;
+use crate::storage_impl::{get_storage, Storage};
+use crate::transaction::{Transaction, TransactionOrdering};
+use crate::transfer::Transfer;
+use crate::{get, put_block};
+
+/**
+ * Tables covered by a snapshot, in the order they are restored
+ */
+const SNAPSHOT_TABLES: &[&str] = &[
+    META_CONTRACT_TABLE_NAME,
+    TRANSACTIONS_TABLE_NAME,
+    METADATAS_TABLE_NAME,
+    CRON_TABLE_NAME,
+    CRON_TX_TABLE_NAME,
+    RECEIPTS_TABLE_NAME,
+    ACL_TABLE_NAME,
+    TRANSFERS_TABLE_NAME,
+    IDENTITIES_TABLE_NAME,
+    MULTISIG_POLICIES_TABLE_NAME,
+    MULTISIG_REQUESTS_TABLE_NAME,
+    MULTISIG_SIGNATURES_TABLE_NAME,
+    SESSIONS_TABLE_NAME,
+    PRIVATE_ALIASES_TABLE_NAME,
+    PRIVATE_ENVELOPES_TABLE_NAME,
+];
+
+/**
+ * Tables left out of a snapshot on purpose. The clock is carried by the
+ * manifest `created_at` and merged on restore instead of being copied.
+ * Operators are set up by each host for itself and never travel with the data.
+ * The privacy custodian key is not a table at all, it comes from the module
+ * env, so private envelopes travel without the key able to open them.
+ */
+const SNAPSHOT_EXCLUDED_TABLES: &[&str] = &[CLOCK_TABLE_NAME, OPERATORS_TABLE_NAME];
+
+/**
+ * Manifest versions `restore_snapshot` knows how to read. Older archives hold
+ * a different set of tables and are refused rather than partially restored.
+ */
+const SNAPSHOT_KNOWN_VERSIONS: &[u32] = &[SNAPSHOT_VERSION];
+
+#[marine]
+#[derive(Debug, Default, Clone)]
+pub struct SnapshotSummary {
+    pub cid: String,
+    pub version: u32,
+    pub created_at: u64,
+    pub rows: u64,
+}
+
+/**
+ * Head block of a snapshot. It describes every table and is linked through
+ * `previous` to the chain of chunk blocks holding the rows.
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub format: String,
+    pub version: u32,
+    pub created_at: u64,
+    pub host_id: String,
+    pub tables: Vec<SnapshotTable>,
+    pub chunks: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotTable {
+    pub name: String,
+    pub schema: String,
+    pub columns: Vec<String>,
+    pub rows: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotChunk {
+    pub table: String,
+    pub index: u64,
+    pub rows: Vec<Value>,
+}
+
+/**
+ * Writes every table as a chain of chunk blocks followed by a manifest block
+ * and returns the manifest cid
+ */
+pub fn export_snapshot() -> Result<SnapshotSummary, ServiceError> {
+    let storage = get_storage()?;
+    let clock = storage.tick_clock()?;
+
+    let mut tables = vec![];
+
+    for table in SNAPSHOT_TABLES {
+        tables.push((*table, export_rows(&storage, table)?));
+    }
+
+    let mut previous_cid = "".to_string();
+    let mut chunks = 0;
+    let mut rows = 0;
+    let mut manifest_tables = vec![];
+
+    for (name, table_rows) in tables {
+        manifest_tables.push(SnapshotTable {
+            name: name.to_string(),
+            schema: storage.get_table_schema(name.to_string())?,
+            columns: storage.get_table_columns(name.to_string())?,
+            rows: table_rows.len() as u64,
+        });
+        rows += table_rows.len() as u64;
+
+        for (index, chunk_rows) in split_rows(table_rows)?.into_iter().enumerate() {
+            let chunk = SnapshotChunk {
+                table: name.to_string(),
+                index: index as u64,
+                rows: chunk_rows,
+            };
+
+            previous_cid = put_snapshot_block(to_json(&chunk)?, previous_cid)?;
+            chunks += 1;
+        }
+    }
+
+    let manifest = SnapshotManifest {
+        format: SNAPSHOT_FORMAT.to_string(),
+        version: SNAPSHOT_VERSION,
+        created_at: clock.pack(),
+        host_id: marine_rs_sdk::get_call_parameters().host_id,
+        tables: manifest_tables,
+        chunks,
+    };
+
+    let cid = put_snapshot_block(to_json(&manifest)?, previous_cid)?;
+
+    Ok(SnapshotSummary {
+        cid,
+        version: SNAPSHOT_VERSION,
+        created_at: manifest.created_at,
+        rows,
+    })
+}
+
+/**
+ * Loads a snapshot into an empty node. The archive is fully read and checked
+ * against its manifest before anything is written.
+ */
+pub fn restore_snapshot(cid: String) -> Result<SnapshotSummary, ServiceError> {
+    let storage = get_storage()?;
+
+    for table in SNAPSHOT_TABLES {
+        if storage.count_rows(table.to_string())? > 0 {
+            return Err(InvalidSnapshot(f!(
+                "node is not empty, table {table} has rows"
+            )));
+        }
+    }
+
+    let head = read_snapshot_block(cid.clone())?;
+    let manifest: SnapshotManifest = from_value(head.content)?;
+
+    let mut local_columns = vec![];
+
+    for table in SNAPSHOT_TABLES {
+        local_columns.push(storage.get_table_columns(table.to_string())?);
+    }
+
+    check_manifest(&manifest, &local_columns)?;
+
+    let mut chunks: Vec<SnapshotChunk> = vec![];
+    let mut next_cid = previous_link(&head.previous);
+
+    while let Some(chunk_cid) = next_cid {
+        if chunks.len() as u64 >= manifest.chunks {
+            return Err(InvalidSnapshot(f!(
+                "more chunks than the {manifest.chunks} declared"
+            )));
+        }
+
+        let block = read_snapshot_block(chunk_cid)?;
+        chunks.push(from_value(block.content)?);
+        next_cid = previous_link(&block.previous);
+    }
+
+    if chunks.len() as u64 != manifest.chunks {
+        return Err(InvalidSnapshot(format!(
+            "expected {} chunks, found {}",
+            manifest.chunks,
+            chunks.len()
+        )));
+    }
+
+    chunks.reverse();
+
+    let mut rows_by_table: HashMap<String, Vec<Value>> = HashMap::new();
+    let mut next_index: HashMap<String, u64> = HashMap::new();
+
+    for chunk in chunks {
+        if !SNAPSHOT_TABLES.contains(&chunk.table.as_str())
+            || SNAPSHOT_EXCLUDED_TABLES.contains(&chunk.table.as_str())
+        {
+            return Err(InvalidSnapshot(f!("unknown table: {chunk.table}")));
+        }
+
+        let expected_index = next_index.entry(chunk.table.clone()).or_default();
+
+        if chunk.index != *expected_index {
+            return Err(InvalidSnapshot(f!(
+                "chunk {chunk.index} of {chunk.table} is out of order"
+            )));
+        }
+
+        *expected_index += 1;
+
+        rows_by_table
+            .entry(chunk.table)
+            .or_default()
+            .extend(chunk.rows);
+    }
+
+    let mut total = 0;
+
+    for table in manifest.tables.iter() {
+        let found = rows_by_table.get(&table.name).map(|r| r.len()).unwrap_or(0) as u64;
+
+        if found != table.rows {
+            return Err(InvalidSnapshot(f!(
+                "table {table.name} declares {table.rows} rows, found {found}"
+            )));
+        }
+
+        total += found;
+    }
+
+    let stored: usize = rows_by_table.values().map(|rows| rows.len()).sum();
+
+    if stored as u64 != total {
+        return Err(InvalidSnapshot(
+            "archive holds rows for tables missing from the manifest".to_string(),
+        ));
+    }
+
+    storage.connection.execute("BEGIN TRANSACTION;")?;
+
+    match restore_rows(&storage, &mut rows_by_table) {
+        Ok(latest) => {
+            storage.connection.execute("COMMIT;")?;
+            storage.merge_clock(latest.max(HybridTimestamp::unpack(manifest.created_at)))?;
+        }
+        Err(e) => {
+            let _ = storage.connection.execute("ROLLBACK;");
+            return Err(e);
+        }
+    }
+
+    Ok(SnapshotSummary {
+        cid,
+        version: manifest.version,
+        created_at: manifest.created_at,
+        rows: total,
+    })
+}
+
+/**
+ * Checks the manifest header and that it lists exactly the snapshot tables,
+ * in restore order, with the columns this node has for each of them
+ */
+fn check_manifest(
+    manifest: &SnapshotManifest,
+    local_columns: &[Vec<String>],
+) -> Result<(), ServiceError> {
+    if manifest.format != SNAPSHOT_FORMAT {
+        return Err(InvalidSnapshot(f!("unknown format: {manifest.format}")));
+    }
+
+    if !SNAPSHOT_KNOWN_VERSIONS.contains(&manifest.version) {
+        return Err(InvalidSnapshot(f!(
+            "unsupported version: {manifest.version}"
+        )));
+    }
+
+    if manifest.tables.len() != SNAPSHOT_TABLES.len() {
+        return Err(InvalidSnapshot(format!(
+            "expected {} tables, manifest lists {}",
+            SNAPSHOT_TABLES.len(),
+            manifest.tables.len()
+        )));
+    }
+
+    for ((table, expected), columns) in manifest
+        .tables
+        .iter()
+        .zip(SNAPSHOT_TABLES)
+        .zip(local_columns)
+    {
+        if table.name != *expected {
+            return Err(InvalidSnapshot(f!(
+                "unexpected table {table.name}, expected {expected}"
+            )));
+        }
+
+        if table.columns != *columns {
+            return Err(InvalidSnapshot(f!(
+                "columns of table {table.name} do not match this node"
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn export_rows(storage: &Storage, table: &str) -> Result<Vec<Value>, ServiceError> {
+    match table {
+        t if t == META_CONTRACT_TABLE_NAME => to_rows(storage.get_all_meta_contracts()?),
+        t if t == TRANSACTIONS_TABLE_NAME => {
+            let ordering = vec![TransactionOrdering {
+                column: "hlc".to_string(),
+                sort: "ASC".to_string(),
+            }];
+            to_rows(storage.get_transactions(vec![], ordering, 0, 0)?)
+        }
+        t if t == METADATAS_TABLE_NAME => {
+            to_rows(storage.search_metadatas(vec![], vec![], 0, 0)?)
+        }
+        t if t == CRON_TABLE_NAME => to_rows(storage.get_all_crons()?),
+        t if t == CRON_TX_TABLE_NAME => to_rows(storage.get_all_cron_txs()?),
+        t if t == RECEIPTS_TABLE_NAME => to_rows(storage.get_all_receipts()?),
+        t if t == ACL_TABLE_NAME => to_rows(storage.get_all_acls()?),
+        t if t == TRANSFERS_TABLE_NAME => to_rows(storage.get_all_transfers()?),
+        t if t == IDENTITIES_TABLE_NAME => to_rows(storage.get_all_identity_keys()?),
+        t if t == MULTISIG_POLICIES_TABLE_NAME => to_rows(storage.get_all_multisig_policies()?),
+        t if t == MULTISIG_REQUESTS_TABLE_NAME => to_rows(storage.get_all_multisig_requests()?),
+        t if t == MULTISIG_SIGNATURES_TABLE_NAME => to_rows(storage.get_all_multisig_signatures()?),
+        t if t == SESSIONS_TABLE_NAME => to_rows(storage.get_all_sessions()?),
+        t if t == PRIVATE_ALIASES_TABLE_NAME => to_rows(storage.get_all_private_aliases()?),
+        t if t == PRIVATE_ENVELOPES_TABLE_NAME => to_rows(storage.get_all_private_envelopes()?),
+        _ => Err(InternalError(f!("no snapshot export for table {table}"))),
+    }
+}
+
+/**
+ * Writes the rows and returns the latest transaction timestamp seen, so the
+ * node clock can be merged past every imported transaction
+ */
+fn restore_rows(
+    storage: &Storage,
+    rows_by_table: &mut HashMap<String, Vec<Value>>,
+) -> Result<HybridTimestamp, ServiceError> {
+    let mut latest = HybridTimestamp::default();
+
+    for table in SNAPSHOT_TABLES {
+        let rows = rows_by_table.remove(*table).unwrap_or_default();
+
+        for row in rows {
+            match *table {
+                t if t == META_CONTRACT_TABLE_NAME => {
+                    storage.write_meta_contract(from_value::<MetaContract>(row)?)?
+                }
+                t if t == TRANSACTIONS_TABLE_NAME => {
+                    let transaction: Transaction = from_value(row)?;
+                    latest = latest.max(HybridTimestamp::unpack(transaction.hlc));
+                    storage.write_transaction(transaction)?;
+                }
+                t if t == METADATAS_TABLE_NAME => {
+                    storage.write_metadata(from_value::<Metadata>(row)?)?
+                }
+                t if t == CRON_TABLE_NAME => {
+                    storage.write_cron(Cron::from(from_value::<CronResult>(row)?))?
+                }
+                t if t == CRON_TX_TABLE_NAME => {
+                    storage.write_cron_tx(from_value::<CronTx>(row)?)?
+                }
+                t if t == RECEIPTS_TABLE_NAME => {
+                    storage.write_receipt(from_value::<Receipt>(row)?)?
+                }
+                t if t == ACL_TABLE_NAME => storage.write_acl(from_value::<Acl>(row)?)?,
+                t if t == TRANSFERS_TABLE_NAME => {
+                    storage.write_transfer(from_value::<Transfer>(row)?)?
+                }
+                t if t == IDENTITIES_TABLE_NAME => {
+                    storage.write_identity_key(from_value::<IdentityKey>(row)?)?
+                }
+                t if t == MULTISIG_POLICIES_TABLE_NAME => {
+                    storage.write_multisig_policy(from_value::<MultisigPolicy>(row)?)?
+                }
+                t if t == MULTISIG_REQUESTS_TABLE_NAME => {
+                    storage.write_multisig_request(&from_value::<MultisigRequest>(row)?)?
+                }
+                t if t == MULTISIG_SIGNATURES_TABLE_NAME => {
+                    storage.write_multisig_signature(from_value::<MultisigSignature>(row)?)?
+                }
+                t if t == SESSIONS_TABLE_NAME => {
+                    storage.write_session(from_value::<Session>(row)?)?
+                }
+                t if t == PRIVATE_ALIASES_TABLE_NAME => {
+                    storage.write_private_alias(from_value::<PrivateAlias>(row)?)?
+                }
+                t if t == PRIVATE_ENVELOPES_TABLE_NAME => {
+                    storage.write_private_envelope(from_value::<PrivateEnvelope>(row)?)?
+                }
+                _ => return Err(InternalError(f!("no snapshot restore for table {table}"))),
+            }
+        }
+    }
+
+    Ok(latest)
+}
+
+/**
+ * Groups rows so that each block stays well below the command line limit of
+ * the ipfs put call
+ */
+fn split_rows(rows: Vec<Value>) -> Result<Vec<Vec<Value>>, ServiceError> {
+    let mut chunks = vec![];
+    let mut current = vec![];
+    let mut current_size = 0;
+
+    for row in rows {
+        let size = to_json(&row)?.len();
+
+        if size > SNAPSHOT_CHUNK_BYTES {
+            return Err(InternalError(f!(
+                "row of {size} bytes exceeds the snapshot chunk size"
+            )));
+        }
+
+        if current_size + size > SNAPSHOT_CHUNK_BYTES {
+            chunks.push(current);
+            current = vec![];
+            current_size = 0;
+        }
+
+        current_size += size;
+        current.push(row);
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    Ok(chunks)
+}
+
+fn put_snapshot_block(content: String, previous_cid: String) -> Result<String, ServiceError> {
+    let transaction = serde_json::json!({
+        "type": SNAPSHOT_FORMAT,
+        "version": SNAPSHOT_VERSION,
+    })
+    .to_string();
+
+    let result = put_block(content, previous_cid, transaction, "".to_string(), 0);
+
+    if !result.success || result.cid.is_empty() {
+        return Err(InternalError(f!(
+            "snapshot block put failed: {result.error}"
+        )));
+    }
+
+    Ok(result.cid)
+}
+
+fn read_snapshot_block(cid: String) -> Result<Block, ServiceError> {
+    let result = get(cid.clone(), "".to_string(), 0);
+
+    if !result.success {
+        return Err(InvalidSnapshot(f!("block {cid} not found: {result.error}")));
+    }
+
+    serde_json::from_str(&result.block)
+        .map_err(|e| InvalidSnapshot(f!("block {cid} is not a snapshot block: {e}")))
+}
+
+fn previous_link(previous: &Value) -> Option<String> {
+    previous
+        .get("/")
+        .and_then(|v| v.as_str())
+        .filter(|cid| !cid.is_empty())
+        .map(|cid| cid.to_string())
+}
+
+fn to_rows<T: Serialize>(records: Vec<T>) -> Result<Vec<Value>, ServiceError> {
+    records
+        .into_iter()
+        .map(|record| serde_json::to_value(record).map_err(|e| InternalError(e.to_string())))
+        .collect()
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, ServiceError> {
+    serde_json::to_string(value).map_err(|e| InternalError(e.to_string()))
+}
+
+fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, ServiceError> {
+    serde_json::from_value(value).map_err(|e| InvalidSnapshot(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns() -> Vec<Vec<String>> {
+        SNAPSHOT_TABLES
+            .iter()
+            .map(|table| vec!["id".to_string(), f!("{table}_value")])
+            .collect()
+    }
+
+    fn manifest(format: &str, version: u32) -> SnapshotManifest {
+        SnapshotManifest {
+            format: format.to_string(),
+            version,
+            created_at: 0,
+            host_id: "".to_string(),
+            tables: SNAPSHOT_TABLES
+                .iter()
+                .zip(columns())
+                .map(|(table, columns)| SnapshotTable {
+                    name: table.to_string(),
+                    schema: "".to_string(),
+                    columns,
+                    rows: 0,
+                })
+                .collect(),
+            chunks: 0,
+        }
+    }
+
+    #[test]
+    fn known_versions_are_accepted() {
+        assert!(check_manifest(&manifest(SNAPSHOT_FORMAT, SNAPSHOT_VERSION), &columns()).is_ok());
+    }
+
+    #[test]
+    fn unknown_versions_and_formats_are_rejected() {
+        for version in [0, 1, 2, SNAPSHOT_VERSION + 1] {
+            assert!(check_manifest(&manifest(SNAPSHOT_FORMAT, version), &columns()).is_err());
+        }
+        assert!(check_manifest(&manifest("other", SNAPSHOT_VERSION), &columns()).is_err());
+    }
+
+    #[test]
+    fn mismatched_tables_are_rejected() {
+        let mut missing = manifest(SNAPSHOT_FORMAT, SNAPSHOT_VERSION);
+        missing.tables.pop();
+        assert!(check_manifest(&missing, &columns()).is_err());
+
+        let mut renamed = manifest(SNAPSHOT_FORMAT, SNAPSHOT_VERSION);
+        renamed.tables[0].name = CLOCK_TABLE_NAME.to_string();
+        assert!(check_manifest(&renamed, &columns()).is_err());
+
+        let mut reordered = manifest(SNAPSHOT_FORMAT, SNAPSHOT_VERSION);
+        reordered.tables.swap(0, 1);
+        assert!(check_manifest(&reordered, &columns()).is_err());
+    }
+
+    #[test]
+    fn mismatched_columns_are_rejected() {
+        let mut extra = manifest(SNAPSHOT_FORMAT, SNAPSHOT_VERSION);
+        extra.tables[1].columns.push("signer".to_string());
+        assert!(check_manifest(&extra, &columns()).is_err());
+
+        let mut missing = manifest(SNAPSHOT_FORMAT, SNAPSHOT_VERSION);
+        missing.tables[2].columns.pop();
+        assert!(check_manifest(&missing, &columns()).is_err());
+    }
+
+    #[test]
+    fn excluded_tables_are_not_exported() {
+        for table in SNAPSHOT_EXCLUDED_TABLES {
+            assert!(!SNAPSHOT_TABLES.contains(table));
+        }
+    }
+}
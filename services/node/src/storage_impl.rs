@@ -27,6 +27,56 @@ impl Storage {
         }
     }
 
+    /**
+     * Column names of a table in declaration order, migrated columns last
+     */
+    pub fn get_table_columns(&self, table_name: String) -> Result<Vec<String>, ServiceError> {
+        let mut statement = self
+            .connection
+            .prepare(f!("PRAGMA table_info({table_name});"))?;
+
+        let mut columns = vec![];
+
+        while let State::Row = statement.next()? {
+            columns.push(statement.read::<String>(1)?);
+        }
+
+        Ok(columns)
+    }
+
+    pub fn count_rows(&self, table_name: String) -> Result<i64, ServiceError> {
+        let mut statement = self
+            .connection
+            .prepare(f!("SELECT COUNT(*) FROM {table_name};"))?;
+
+        if let State::Row = statement.next()? {
+            Ok(statement.read::<i64>(0)?)
+        } else {
+            Ok(0)
+        }
+    }
+
+    /**
+     * Adds a column to a table created by an older version of the service.
+     * Returns true when the column was missing and has been added.
+     */
+    pub fn ensure_column(
+        &self,
+        table_name: String,
+        column: String,
+        definition: String,
+    ) -> Result<bool, ServiceError> {
+        let schema = self.get_table_schema(table_name.clone())?;
+
+        if schema.is_empty() || schema.contains(&f!(" {column} ")) {
+            return Ok(false);
+        }
+
+        self.connection
+            .execute(f!("ALTER TABLE {table_name} ADD COLUMN {column} {definition};"))?;
+        Ok(true)
+    }
+
     pub fn delete_table(&self, table_name: String) -> Result<(), ServiceError> {
         self.connection
             .execute(f!("DROP TABLE IF EXISTS {table_name};"))?;
@@ -1,10 +1,13 @@
+use crate::clock::HybridTimestamp;
 use crate::defaults::STATUS_PENDING;
+use crate::error::ServiceError;
+use crate::error::ServiceError::{InvalidDataFormatForMethodType, ReplayedPayload};
 use marine_rs_sdk::marine;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
+use types::transaction_hash;
 
 #[marine]
-#[derive(Debug, Default, Clone, Serialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Transaction {
     pub hash: String,
     pub token_key: String,
@@ -22,6 +25,8 @@ pub struct Transaction {
     pub error_text: String,
     pub token_id: String,
     pub version: i64,
+    pub hlc: u64,
+    pub signer: String,
 }
 
 #[marine]
@@ -37,17 +42,108 @@ pub struct TransactionRequest {
   pub method: String,
   pub nonce: i64,
   pub version: i64,
+  pub hlc: u64,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct TransactionSubset {
     pub hash: String,
     pub timestamp: u64,
+    pub hlc: u64,
     pub meta_contract_id: String,
     pub method: String,
     pub value: String,
 }
 
+/**
+ * Transaction fields repeated inside the signed `data` of a transaction, so
+ * that a signature only holds for the key, alias, method and nonce it was
+ * made for
+ */
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SignedScope {
+    pub method: String,
+    #[serde(default)]
+    pub token_key: String,
+    #[serde(default)]
+    pub data_key: String,
+    #[serde(default)]
+    pub alias: String,
+    pub nonce: i64,
+}
+
+impl SignedScope {
+    pub fn new(method: &str, token_key: &str, data_key: &str, alias: &str, nonce: i64) -> Self {
+        Self {
+            method: method.to_string(),
+            token_key: token_key.to_string(),
+            data_key: data_key.to_string(),
+            alias: alias.to_string(),
+            nonce,
+        }
+    }
+
+    /**
+     * Checks the signed fields against the ones the transaction was sent with
+     */
+    pub fn check(&self, sent: &SignedScope) -> Result<(), ServiceError> {
+        let fields = [
+            ("method", &self.method, &sent.method),
+            ("token_key", &self.token_key, &sent.token_key),
+            ("data_key", &self.data_key, &sent.data_key),
+            ("alias", &self.alias, &sent.alias),
+        ];
+
+        for (name, signed, sent) in fields {
+            if signed != sent {
+                return Err(InvalidDataFormatForMethodType(f!(
+                    "signed {name} {signed} does not match {sent}"
+                )));
+            }
+        }
+
+        if self.nonce != sent.nonce {
+            return Err(InvalidDataFormatForMethodType(format!(
+                "signed nonce {} does not match {}",
+                self.nonce, sent.nonce
+            )));
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Rejects a payload that was already applied for its signer, so a signed
+     * request takes effect at most once. `is_applied` is usually
+     * `Storage::is_applied_payload`.
+     */
+    pub fn check_not_applied<F>(&self, is_applied: F) -> Result<(), ServiceError>
+    where
+        F: FnOnce() -> Result<bool, ServiceError>,
+    {
+        if is_applied()? {
+            return Err(ReplayedPayload(format!(
+                "{} {} {} nonce {}",
+                self.method, self.data_key, self.alias, self.nonce
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl From<&Transaction> for SignedScope {
+    fn from(transaction: &Transaction) -> Self {
+        Self::new(
+            &transaction.method,
+            &transaction.token_key,
+            &transaction.data_key,
+            &transaction.alias,
+            transaction.nonce,
+        )
+    }
+}
+
 #[marine]
 #[derive(Debug)]
 pub struct TransactionQuery {
@@ -73,12 +169,13 @@ impl Transaction {
         data: String,
         public_key: String,
         alias: String,
-        timestamp: u64,
+        clock: HybridTimestamp,
         meta_contract_id: String,
         method: String,
         token_id: String,
         version: i64,
     ) -> Self {
+        let signer = public_key.clone();
         let hash = Self::generate_hash(
             token_key.clone(),
             data_key.clone(),
@@ -102,12 +199,14 @@ impl Transaction {
             data,
             public_key,
             alias,
-            timestamp,
+            timestamp: clock.wall,
             meta_contract_id,
             method,
             error_text: "".to_string(),
             token_id,
             version,
+            hlc: clock.pack(),
+            signer,
         }
     }
 
@@ -122,22 +221,54 @@ impl Transaction {
         token_id: String,
         version: i64,
     ) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(
-            format!(
-                "{}{}{}{}{}{}{}{}{}",
-                token_key,
-                data_key,
-                nonce,
-                data,
-                public_key,
-                alias,
-                method,
-                token_id,
-                version
-            )
-            .as_bytes(),
-        );
-        bs58::encode(hasher.finalize()).into_string()
+        transaction_hash(
+            &token_key,
+            &data_key,
+            &data,
+            nonce,
+            &public_key,
+            &alias,
+            &method,
+            &token_id,
+            version,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope() -> SignedScope {
+        SignedScope::new("grant", "", "dk", "profile", 7)
+    }
+
+    #[test]
+    fn matching_scope_passes() {
+        assert!(scope().check(&scope()).is_ok());
+    }
+
+    #[test]
+    fn any_mismatch_is_rejected() {
+        let sent = [
+            SignedScope::new("revoke", "", "dk", "profile", 7),
+            SignedScope::new("grant", "tk", "dk", "profile", 7),
+            SignedScope::new("grant", "", "other", "profile", 7),
+            SignedScope::new("grant", "", "dk", "", 7),
+            SignedScope::new("grant", "", "dk", "profile", 8),
+        ];
+
+        for sent in sent.iter() {
+            assert!(scope().check(sent).is_err());
+        }
+    }
+
+    #[test]
+    fn method_and_nonce_are_required() {
+        let missing_nonce = r#"{"method":"grant","data_key":"dk"}"#;
+        let missing_method = r#"{"data_key":"dk","nonce":1}"#;
+
+        assert!(serde_json::from_str::<SignedScope>(missing_nonce).is_err());
+        assert!(serde_json::from_str::<SignedScope>(missing_method).is_err());
     }
 }
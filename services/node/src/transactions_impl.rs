@@ -1,3 +1,4 @@
+use crate::clock::LOGICAL_BITS;
 use crate::defaults::{STATUS_PENDING, STATUS_SUCCESS, TRANSACTIONS_TABLE_NAME};
 use crate::error::ServiceError;
 use crate::error::ServiceError::InternalError;
@@ -25,7 +26,9 @@ impl Storage {
                 method TEXT NOT NULL,
                 nonce INTEGER NOT NULL,
                 token_id TEXT,
-                version INTEGER NOT NULL
+                version INTEGER NOT NULL,
+                hlc INTEGER NOT NULL DEFAULT 0,
+                signer TEXT NOT NULL DEFAULT ''
             );",
             TRANSACTIONS_TABLE_NAME
         );
@@ -35,11 +38,66 @@ impl Storage {
         if let Err(error) = result {
             println!("create_transactions_table error: {}", error);
         }
+
+        self.migrate_transactions_hlc();
+        self.migrate_transactions_signer();
+    }
+
+    /**
+     * Transactions written before the hybrid clock existed get an hlc derived
+     * from their wall clock timestamp so they keep their relative order.
+     */
+    fn migrate_transactions_hlc(&self) {
+        let result = self.ensure_column(
+            TRANSACTIONS_TABLE_NAME.to_string(),
+            "hlc".to_string(),
+            "INTEGER NOT NULL DEFAULT 0".to_string(),
+        );
+
+        match result {
+            Ok(true) => {
+                let backfill = self.connection.execute(f!(
+                    "UPDATE {TRANSACTIONS_TABLE_NAME} SET hlc = timestamp << {LOGICAL_BITS} WHERE hlc = 0;"
+                ));
+
+                if let Err(error) = backfill {
+                    println!("migrate_transactions_hlc error: {}", error);
+                }
+            }
+            Ok(false) => {}
+            Err(error) => println!("migrate_transactions_hlc error: {}", error),
+        }
+    }
+
+    /**
+     * Transactions written before session keys were recorded were signed by
+     * the key they are recorded under.
+     */
+    fn migrate_transactions_signer(&self) {
+        let result = self.ensure_column(
+            TRANSACTIONS_TABLE_NAME.to_string(),
+            "signer".to_string(),
+            "TEXT NOT NULL DEFAULT ''".to_string(),
+        );
+
+        match result {
+            Ok(true) => {
+                let backfill = self.connection.execute(f!(
+                    "UPDATE {TRANSACTIONS_TABLE_NAME} SET signer = public_key WHERE signer = '';"
+                ));
+
+                if let Err(error) = backfill {
+                    println!("migrate_transactions_signer error: {}", error);
+                }
+            }
+            Ok(false) => {}
+            Err(error) => println!("migrate_transactions_signer error: {}", error),
+        }
     }
 
     pub fn write_transaction(&self, transaction: Transaction) -> Result<String, ServiceError> {
         let s = format!(
-            "insert into {} (hash, token_key, token_id, from_peer_id, host_id, status, data_key, data, public_key, alias, timestamp, meta_contract_id, method, error_text, nonce, version) values ('{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}');",
+            "insert into {} (hash, token_key, token_id, from_peer_id, host_id, status, data_key, data, public_key, alias, timestamp, meta_contract_id, method, error_text, nonce, version, hlc, signer) values ('{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}');",
             TRANSACTIONS_TABLE_NAME,
             transaction.hash,
             transaction.token_key,
@@ -57,6 +115,8 @@ impl Storage {
             transaction.error_text,
             transaction.nonce,
             transaction.version,
+            transaction.hlc,
+            transaction.signer,
         );
 
         let result = self.connection.execute(s);
@@ -88,6 +148,22 @@ impl Storage {
         Ok(())
     }
 
+    /**
+     * Replaces the payload kept for a transaction, used to drop content that
+     * must not stay readable once it is applied
+     */
+    pub fn update_transaction_data(&self, hash: String, data: String) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "UPDATE {TRANSACTIONS_TABLE_NAME} SET data = ? WHERE hash = ?"
+        ))?;
+
+        statement.bind(1, &Value::String(data))?;
+        statement.bind(2, &Value::String(hash))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
     pub fn get_transaction(&self, hash: String) -> Result<Transaction, ServiceError> {
         let mut statement = self
             .connection
@@ -106,7 +182,7 @@ impl Storage {
 
     pub fn get_pending_transactions(&self) -> Result<Vec<Transaction>, ServiceError> {
         let mut statement = self.connection.prepare(f!(
-            "SELECT * FROM {TRANSACTIONS_TABLE_NAME} WHERE status = ?"
+            "SELECT * FROM {TRANSACTIONS_TABLE_NAME} WHERE status = ? ORDER BY hlc ASC"
         ))?;
 
         statement.bind(1, &Value::Integer(STATUS_PENDING))?;
@@ -137,7 +213,7 @@ impl Storage {
       
         ordering_str = format!("ORDER BY {}",orders.join(", "));
       } else {
-        ordering_str = format!("ORDER BY timestamp DESC");
+        ordering_str = format!("ORDER BY hlc DESC");
       }
       if to > 0 {
         limit_str = format!("LIMIT {},{}", from, to);
@@ -162,13 +238,35 @@ impl Storage {
       Ok(transactions)
     }
 
+    /**
+     * Whether `public_key` already had a transaction of `method` with exactly
+     * this signed `data` applied
+     */
+    pub fn is_applied_payload(
+        &self,
+        public_key: String,
+        method: String,
+        data: String,
+    ) -> Result<bool, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT hash FROM {TRANSACTIONS_TABLE_NAME} WHERE public_key = ? AND method = ? AND data = ? AND status = ? LIMIT 1"
+        ))?;
+
+        statement.bind(1, &Value::String(public_key))?;
+        statement.bind(2, &Value::String(method))?;
+        statement.bind(3, &Value::String(data))?;
+        statement.bind(4, &Value::Integer(STATUS_SUCCESS))?;
+
+        Ok(matches!(statement.next()?, State::Row))
+    }
+
     pub fn get_success_transactions(
         &self,
         from: i64,
         to: i64,
     ) -> Result<Vec<Transaction>, ServiceError> {
         let mut statement = self.connection.prepare(f!(
-            "SELECT * FROM {TRANSACTIONS_TABLE_NAME} WHERE status = ? AND timestamp BETWEEN ? AND ?"
+            "SELECT * FROM {TRANSACTIONS_TABLE_NAME} WHERE status = ? AND timestamp BETWEEN ? AND ? ORDER BY hlc ASC"
         ))?;
 
         statement.bind(1, &Value::Integer(STATUS_SUCCESS))?;
@@ -203,5 +301,7 @@ pub fn read(statement: &Statement) -> Result<Transaction, ServiceError> {
         nonce: statement.read::<i64>(13)?,
         token_id: statement.read::<String>(14)?,
         version: statement.read::<i64>(15)?,
+        hlc: statement.read::<i64>(16)? as u64,
+        signer: statement.read::<String>(17)?,
     })
 }
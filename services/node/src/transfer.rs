@@ -0,0 +1,620 @@
+use marine_rs_sdk::marine;
+use marine_sqlite_connector::{State, Statement, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::defaults::{
+    METHOD_ACCEPT_TRANSFER, METHOD_TRANSFER, STATUS_FAILED, STATUS_SUCCESS, TRANSFERS_TABLE_NAME,
+    TRANSFER_KIND_METADATA, TRANSFER_KIND_META_CONTRACT, TRANSFER_STATUS_COMPLETED,
+    TRANSFER_STATUS_PENDING,
+};
+use crate::error::ServiceError;
+use crate::error::ServiceError::{
+    InvalidDataFormatForMethodType, InvalidMethod, InvalidOwner, RecordFound, RecordNotFound,
+    ReplayedPayload,
+};
+use crate::get_public_key_type;
+use crate::receipt::Receipt;
+use crate::storage_impl::{get_storage, Storage};
+use crate::transaction::{SignedScope, Transaction};
+
+/**
+ * Hand over of a metadata alias or a meta contract binding. `transaction_hash`
+ * is the "transfer" transaction, `completed_by` the transaction that moved the
+ * ownership, which is the transfer itself unless the recipient had to accept.
+ * Completed transfers are the ownership history of the entry.
+ */
+#[marine]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Transfer {
+    pub transaction_hash: String,
+    pub kind: String,
+    pub token_key: String,
+    pub data_key: String,
+    pub alias: String,
+    pub from_key: String,
+    pub to_key: String,
+    pub status: i64,
+    pub completed_by: String,
+    pub timestamp: u64,
+}
+
+/**
+ * `data` of a "transfer" transaction
+ */
+#[derive(Debug, Default, Deserialize)]
+pub struct SerdeTransfer {
+    pub kind: String,
+    pub to: String,
+    #[serde(default)]
+    pub require_acceptance: bool,
+    #[serde(flatten)]
+    pub scope: SignedScope,
+}
+
+/**
+ * `data` of an "accept_transfer" transaction, signed for the coordinates of
+ * the transferred entry
+ */
+#[derive(Debug, Default, Deserialize)]
+pub struct SerdeAcceptTransfer {
+    pub transfer: String,
+    #[serde(flatten)]
+    pub scope: SignedScope,
+}
+
+impl Storage {
+    pub fn create_transfers_table(&self) {
+        let table_schema = format!(
+            "
+            CREATE TABLE IF NOT EXISTS {} (
+                transaction_hash TEXT PRIMARY KEY UNIQUE,
+                kind varchar(32) NOT NULL,
+                token_key TEXT NOT NULL,
+                data_key TEXT NOT NULL,
+                alias varchar(255) NOT NULL,
+                from_key TEXT NOT NULL,
+                to_key TEXT NOT NULL,
+                status INTEGER NOT NULL,
+                completed_by TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            );",
+            TRANSFERS_TABLE_NAME
+        );
+
+        let result = self.connection.execute(table_schema);
+
+        if let Err(error) = result {
+            println!("create_transfers_table error: {}", error);
+        }
+    }
+
+    pub fn write_transfer(&self, transfer: Transfer) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "INSERT OR REPLACE INTO {TRANSFERS_TABLE_NAME} (transaction_hash, kind, token_key, data_key, alias, from_key, to_key, status, completed_by, timestamp) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        ))?;
+
+        statement.bind(1, &Value::String(transfer.transaction_hash))?;
+        statement.bind(2, &Value::String(transfer.kind))?;
+        statement.bind(3, &Value::String(transfer.token_key))?;
+        statement.bind(4, &Value::String(transfer.data_key))?;
+        statement.bind(5, &Value::String(transfer.alias))?;
+        statement.bind(6, &Value::String(transfer.from_key))?;
+        statement.bind(7, &Value::String(transfer.to_key))?;
+        statement.bind(8, &Value::Integer(transfer.status))?;
+        statement.bind(9, &Value::String(transfer.completed_by))?;
+        statement.bind(10, &Value::Integer(transfer.timestamp as i64))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    pub fn get_transfer(&self, transaction_hash: String) -> Result<Transfer, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {TRANSFERS_TABLE_NAME} WHERE transaction_hash = ?"
+        ))?;
+
+        statement.bind(1, &Value::String(transaction_hash.clone()))?;
+
+        if let State::Row = statement.next()? {
+            read(&statement)
+        } else {
+            Err(RecordNotFound(f!("transfer {transaction_hash}")))
+        }
+    }
+
+    pub fn get_transfer_completed_by(
+        &self,
+        transaction_hash: String,
+    ) -> Result<Transfer, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {TRANSFERS_TABLE_NAME} WHERE completed_by = ?"
+        ))?;
+
+        statement.bind(1, &Value::String(transaction_hash.clone()))?;
+
+        if let State::Row = statement.next()? {
+            read(&statement)
+        } else {
+            Err(RecordNotFound(f!(
+                "transfer completed by {transaction_hash}"
+            )))
+        }
+    }
+
+    pub fn get_all_transfers(&self) -> Result<Vec<Transfer>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT * FROM {TRANSFERS_TABLE_NAME} ORDER BY timestamp ASC"
+        ))?;
+
+        let mut transfers = Vec::new();
+
+        while let State::Row = statement.next()? {
+            transfers.push(read(&statement)?);
+        }
+
+        Ok(transfers)
+    }
+
+    /**
+     * Completed transfers of an entry, oldest first. `key` is the token key
+     * for a meta contract and the data key for metadata.
+     */
+    pub fn get_ownership_history(
+        &self,
+        kind: String,
+        key: String,
+        alias: String,
+    ) -> Result<Vec<Transfer>, ServiceError> {
+        let column = if kind == TRANSFER_KIND_META_CONTRACT {
+            "token_key"
+        } else {
+            "data_key"
+        };
+
+        let mut statement = self.connection.prepare(format!(
+            "SELECT * FROM {} WHERE kind = ? AND {} = ? AND alias = ? AND status = ? ORDER BY timestamp ASC",
+            TRANSFERS_TABLE_NAME, column
+        ))?;
+
+        statement.bind(1, &Value::String(kind))?;
+        statement.bind(2, &Value::String(key))?;
+        statement.bind(3, &Value::String(alias))?;
+        statement.bind(4, &Value::Integer(TRANSFER_STATUS_COMPLETED))?;
+
+        let mut transfers = Vec::new();
+
+        while let State::Row = statement.next()? {
+            transfers.push(read(&statement)?);
+        }
+
+        Ok(transfers)
+    }
+}
+
+/**
+ * Parses the `data` of a "transfer" transaction and checks it against the
+ * transaction fields it was signed for
+ */
+pub fn parse_transfer_request(
+    scope: &SignedScope,
+    public_key: &str,
+    data: &str,
+) -> Result<SerdeTransfer, ServiceError> {
+    let method = &scope.method;
+    let request: SerdeTransfer = serde_json::from_str(data)
+        .map_err(|e| InvalidDataFormatForMethodType(f!("{method}: {e}")))?;
+
+    request.scope.check(scope)?;
+
+    if request.to.is_empty() || request.to == public_key {
+        return Err(InvalidDataFormatForMethodType(f!(
+            "invalid recipient {request.to}"
+        )));
+    }
+
+    let coordinates = if request.kind == TRANSFER_KIND_METADATA {
+        !scope.data_key.is_empty()
+    } else if request.kind == TRANSFER_KIND_META_CONTRACT {
+        !scope.token_key.is_empty()
+    } else {
+        return Err(InvalidDataFormatForMethodType(f!(
+            "unknown transfer kind {request.kind}"
+        )));
+    };
+
+    if !coordinates {
+        return Err(InvalidDataFormatForMethodType(f!(
+            "{request.kind} transfer without its key"
+        )));
+    }
+
+    Ok(request)
+}
+
+/**
+ * Parses the `data` of an "accept_transfer" transaction and checks it against
+ * the transaction fields it was signed for
+ */
+pub fn parse_accept_request(
+    scope: &SignedScope,
+    data: &str,
+) -> Result<SerdeAcceptTransfer, ServiceError> {
+    let method = &scope.method;
+    let request: SerdeAcceptTransfer = serde_json::from_str(data)
+        .map_err(|e| InvalidDataFormatForMethodType(f!("{method}: {e}")))?;
+
+    request.scope.check(scope)?;
+
+    Ok(request)
+}
+
+/**
+ * Checks a "transfer" or "accept_transfer" transaction before it is recorded.
+ * A payload is applied at most once, a new nonce is needed to transfer again.
+ */
+pub fn check_transfer_request(
+    storage: &Storage,
+    scope: &SignedScope,
+    public_key: &str,
+    data: &str,
+) -> Result<(), ServiceError> {
+    let SignedScope {
+        method,
+        token_key,
+        data_key,
+        alias,
+        nonce,
+    } = scope;
+
+    if storage.is_applied_payload(public_key.to_string(), method.clone(), data.to_string())? {
+        return Err(ReplayedPayload(f!("{method} nonce {nonce}")));
+    }
+
+    if method == METHOD_ACCEPT_TRANSFER {
+        let request = parse_accept_request(scope, data)?;
+        let transfer = storage.get_transfer(request.transfer)?;
+
+        check_transfer_coordinates(&transfer, scope)?;
+        return check_acceptable(storage, &transfer, public_key);
+    }
+
+    let request = parse_transfer_request(scope, public_key, data)?;
+
+    if !get_public_key_type(&request.to).success {
+        return Err(ServiceError::InvalidEncryption(request.to));
+    }
+
+    if request.kind == TRANSFER_KIND_METADATA {
+        storage.get_owner_metadata_by_datakey_and_alias(
+            data_key.to_string(),
+            public_key.to_string(),
+            alias.to_string(),
+        )?;
+
+        check_recipient_free(storage, data_key, alias, &request.to)
+    } else {
+        let contract = storage.get_meta_contract(token_key.to_string())?;
+
+        if !storage.is_same_owner(contract.public_key, public_key.to_string())? {
+            return Err(InvalidOwner(f!("not owner of token_key: {public_key}")));
+        }
+
+        Ok(())
+    }
+}
+
+/**
+ * Validated "transfer" and "accept_transfer" method types
+ */
+pub fn validate_transfer(transaction_hash: String) {
+    let storage = get_storage().expect("Internal error to database connector");
+    let mut transaction = storage.get_transaction(transaction_hash).unwrap().clone();
+
+    match apply_transfer(&storage, &transaction) {
+        Ok(()) => {
+            transaction.status = STATUS_SUCCESS;
+            transaction.error_text = "".to_string();
+        }
+        Err(e) => {
+            transaction.status = STATUS_FAILED;
+            transaction.error_text = e.to_string();
+        }
+    }
+
+    let _ = storage.update_transaction_status(
+        transaction.hash.clone(),
+        transaction.status,
+        transaction.error_text.clone(),
+    );
+}
+
+fn apply_transfer(storage: &Storage, transaction: &Transaction) -> Result<(), ServiceError> {
+    check_transfer_request(
+        storage,
+        &SignedScope::from(transaction),
+        &transaction.public_key,
+        &transaction.data,
+    )?;
+
+    if transaction.method == METHOD_TRANSFER {
+        let request: SerdeTransfer = serde_json::from_str(&transaction.data)
+            .map_err(|e| InvalidDataFormatForMethodType(e.to_string()))?;
+
+        let (token_key, data_key, alias) = if request.kind == TRANSFER_KIND_META_CONTRACT {
+            (
+                transaction.token_key.clone(),
+                "".to_string(),
+                "".to_string(),
+            )
+        } else {
+            (
+                "".to_string(),
+                transaction.data_key.clone(),
+                transaction.alias.clone(),
+            )
+        };
+
+        let transfer = Transfer {
+            transaction_hash: transaction.hash.clone(),
+            kind: request.kind,
+            token_key,
+            data_key,
+            alias,
+            from_key: transaction.public_key.clone(),
+            to_key: request.to,
+            status: TRANSFER_STATUS_PENDING,
+            completed_by: "".to_string(),
+            timestamp: transaction.timestamp,
+        };
+
+        if request.require_acceptance {
+            storage.write_transfer(transfer)
+        } else {
+            complete_transfer(storage, transfer, &transaction.hash)
+        }
+    } else if transaction.method == METHOD_ACCEPT_TRANSFER {
+        let request: SerdeAcceptTransfer = serde_json::from_str(&transaction.data)
+            .map_err(|e| InvalidDataFormatForMethodType(e.to_string()))?;
+
+        let transfer = storage.get_transfer(request.transfer)?;
+        complete_transfer(storage, transfer, &transaction.hash)
+    } else {
+        Err(InvalidMethod(f!(
+            "{transaction.method} is not a transfer method"
+        )))
+    }
+}
+
+/**
+ * Moves the entry to the recipient and records the transfer as completed, all
+ * together or none of it. Delegations granted by the previous owner stay with
+ * the previous owner and no longer apply.
+ */
+fn complete_transfer(
+    storage: &Storage,
+    mut transfer: Transfer,
+    completed_by: &str,
+) -> Result<(), ServiceError> {
+    transfer.status = TRANSFER_STATUS_COMPLETED;
+    transfer.completed_by = completed_by.to_string();
+
+    storage.connection.execute("BEGIN TRANSACTION;")?;
+
+    let result = move_entry(storage, &transfer, completed_by)
+        .and_then(|()| storage.write_transfer(transfer));
+
+    match result {
+        Ok(()) => {
+            storage.connection.execute("COMMIT;")?;
+            Ok(())
+        }
+        Err(e) => {
+            let _ = storage.connection.execute("ROLLBACK;");
+            Err(e)
+        }
+    }
+}
+
+fn move_entry(
+    storage: &Storage,
+    transfer: &Transfer,
+    completed_by: &str,
+) -> Result<(), ServiceError> {
+    if transfer.kind == TRANSFER_KIND_META_CONTRACT {
+        let mut contract = storage.get_meta_contract(transfer.token_key.clone())?;
+        contract.public_key = transfer.to_key.clone();
+        storage.update_meta_contract(contract)
+    } else {
+        let metadata = storage.get_owner_metadata_by_datakey_and_alias(
+            transfer.data_key.clone(),
+            transfer.from_key.clone(),
+            transfer.alias.clone(),
+        )?;
+
+        storage.update_metadata_owner(
+            transfer.data_key.clone(),
+            transfer.alias.clone(),
+            transfer.from_key.clone(),
+            transfer.to_key.clone(),
+        )?;
+
+        storage.write_receipt(Receipt {
+            transaction_hash: completed_by.to_string(),
+            data_key: transfer.data_key.clone(),
+            alias: transfer.alias.clone(),
+            public_key: transfer.to_key.clone(),
+            cid: metadata.cid,
+        })
+    }
+}
+
+/**
+ * A pending transfer can only be accepted by its recipient and only while the
+ * sender still owns the entry
+ */
+fn check_acceptable(
+    storage: &Storage,
+    transfer: &Transfer,
+    public_key: &str,
+) -> Result<(), ServiceError> {
+    if transfer.status != TRANSFER_STATUS_PENDING {
+        return Err(InvalidDataFormatForMethodType(f!(
+            "transfer {transfer.transaction_hash} is not pending"
+        )));
+    }
+
+    if transfer.to_key != public_key {
+        return Err(InvalidOwner(f!("not recipient of transfer: {public_key}")));
+    }
+
+    if transfer.kind == TRANSFER_KIND_META_CONTRACT {
+        let contract = storage.get_meta_contract(transfer.token_key.clone())?;
+
+        if !storage.is_same_owner(contract.public_key, transfer.from_key.clone())? {
+            return Err(InvalidOwner(f!(
+                "{transfer.from_key} no longer owns {transfer.token_key}"
+            )));
+        }
+
+        Ok(())
+    } else {
+        storage.get_owner_metadata_by_datakey_and_alias(
+            transfer.data_key.clone(),
+            transfer.from_key.clone(),
+            transfer.alias.clone(),
+        )?;
+
+        check_recipient_free(storage, &transfer.data_key, &transfer.alias, public_key)
+    }
+}
+
+/**
+ * An acceptance is signed for the entry it takes over, the token key of a
+ * meta contract or the data key and alias of metadata
+ */
+fn check_transfer_coordinates(
+    transfer: &Transfer,
+    scope: &SignedScope,
+) -> Result<(), ServiceError> {
+    let matches = if transfer.kind == TRANSFER_KIND_META_CONTRACT {
+        transfer.token_key == scope.token_key
+    } else {
+        transfer.data_key == scope.data_key && transfer.alias == scope.alias
+    };
+
+    if matches {
+        Ok(())
+    } else {
+        Err(InvalidDataFormatForMethodType(f!(
+            "transfer {transfer.transaction_hash} is not for the signed entry"
+        )))
+    }
+}
+
+fn check_recipient_free(
+    storage: &Storage,
+    data_key: &str,
+    alias: &str,
+    recipient: &str,
+) -> Result<(), ServiceError> {
+    let result = storage.get_owner_metadata_by_datakey_and_alias(
+        data_key.to_string(),
+        recipient.to_string(),
+        alias.to_string(),
+    );
+
+    match result {
+        Ok(_) => Err(RecordFound(f!("{data_key} {alias} {recipient}"))),
+        Err(RecordNotFound(_)) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn read(statement: &Statement) -> Result<Transfer, ServiceError> {
+    Ok(Transfer {
+        transaction_hash: statement.read::<String>(0)?,
+        kind: statement.read::<String>(1)?,
+        token_key: statement.read::<String>(2)?,
+        data_key: statement.read::<String>(3)?,
+        alias: statement.read::<String>(4)?,
+        from_key: statement.read::<String>(5)?,
+        to_key: statement.read::<String>(6)?,
+        status: statement.read::<i64>(7)?,
+        completed_by: statement.read::<String>(8)?,
+        timestamp: statement.read::<i64>(9)? as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer_data(kind: &str, scope: &SignedScope) -> String {
+        serde_json::json!({
+            "kind": kind,
+            "to": "0xrecipient",
+            "method": scope.method,
+            "token_key": scope.token_key,
+            "data_key": scope.data_key,
+            "alias": scope.alias,
+            "nonce": scope.nonce,
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn transfer_signed_for_the_asset_is_accepted() {
+        let scope = SignedScope::new(METHOD_TRANSFER, "", "dk", "profile", 3);
+        let data = transfer_data(TRANSFER_KIND_METADATA, &scope);
+
+        assert!(parse_transfer_request(&scope, "0xowner", &data).is_ok());
+    }
+
+    #[test]
+    fn transfer_replayed_on_another_asset_is_rejected() {
+        let signed = SignedScope::new(METHOD_TRANSFER, "", "dk", "profile", 3);
+        let data = transfer_data(TRANSFER_KIND_METADATA, &signed);
+        let sent = [
+            SignedScope::new(METHOD_TRANSFER, "", "dk", "other", 3),
+            SignedScope::new(METHOD_TRANSFER, "", "other", "profile", 3),
+            SignedScope::new(METHOD_TRANSFER, "tk", "dk", "profile", 3),
+            SignedScope::new(METHOD_TRANSFER, "", "dk", "profile", 4),
+        ];
+
+        for scope in sent.iter() {
+            assert!(parse_transfer_request(scope, "0xowner", &data).is_err());
+        }
+    }
+
+    #[test]
+    fn contract_transfer_needs_a_token_key() {
+        let scope = SignedScope::new(METHOD_TRANSFER, "", "", "", 1);
+        let data = transfer_data(TRANSFER_KIND_META_CONTRACT, &scope);
+
+        assert!(parse_transfer_request(&scope, "0xowner", &data).is_err());
+    }
+
+    #[test]
+    fn transfer_to_self_is_rejected() {
+        let scope = SignedScope::new(METHOD_TRANSFER, "", "dk", "profile", 1);
+        let data = transfer_data(TRANSFER_KIND_METADATA, &scope);
+
+        assert!(parse_transfer_request(&scope, "0xrecipient", &data).is_err());
+    }
+
+    #[test]
+    fn acceptance_must_name_the_transferred_entry() {
+        let transfer = Transfer {
+            kind: TRANSFER_KIND_METADATA.to_string(),
+            data_key: "dk".to_string(),
+            alias: "profile".to_string(),
+            ..Default::default()
+        };
+
+        let same = SignedScope::new(METHOD_ACCEPT_TRANSFER, "", "dk", "profile", 1);
+        let other = SignedScope::new(METHOD_ACCEPT_TRANSFER, "", "dk", "other", 1);
+
+        assert!(check_transfer_coordinates(&transfer, &same).is_ok());
+        assert!(check_transfer_coordinates(&transfer, &other).is_err());
+    }
+}
@@ -1,13 +1,17 @@
+use crate::acl::check_write_access;
 use crate::block::Block;
+use crate::clock::physical_now;
 use crate::cron::{Cron, SerdeCron};
 use crate::data_types::DataTypeClone;
 use crate::defaults::{CRON_ACTION_CREATE, CRON_ACTION_UPDATE, CRON_ACTION_UPDATE_STATUS, CRON_STATUS_ACTIVE};
 use crate::metadatas::{FinalMetadata, Metadata};
+use crate::privacy::{seal_content, PrivateEnvelope};
+use crate::receipt::Receipt;
 use crate::transaction::TransactionSubset;
 use crate::{defaults::STATUS_FAILED, defaults::STATUS_SUCCESS};
 use crate::{error::ServiceError, error::ServiceError::*};
 use crate::{get, put_block};
-use crate::{meta_contract::MetaContract, storage_impl::get_storage};
+use crate::{meta_contract::MetaContract, storage_impl::get_storage, storage_impl::Storage};
 
 /**
  * Validated meta contract method type
@@ -25,11 +29,13 @@ pub fn validate_meta_contract(transaction_hash: String) {
 
     match sm_result {
         Ok(contract) => {
-            if transaction.public_key != contract.public_key {
-                error = Some(InvalidOwner(f!("{transaction.public_key}")))
-            } else {
-                current_meta_contract = contract;
-                current_meta_contract.meta_contract_id = transaction.data.clone();
+            match storage.is_same_owner(contract.public_key.clone(), transaction.public_key.clone()) {
+                Ok(true) => {
+                    current_meta_contract = contract;
+                    current_meta_contract.meta_contract_id = transaction.data.clone();
+                }
+                Ok(false) => error = Some(InvalidOwner(f!("{transaction.public_key}"))),
+                Err(e) => error = Some(e),
             }
             is_update = true;
         }
@@ -97,116 +103,264 @@ pub fn validate_metadata(
             transaction.error_text = final_error_msg;
         }
     } else {
-        for data in metadatas {
-            let result = storage.get_owner_metadata_by_datakey_and_alias(
-                transaction.data_key.clone(),
-                data.public_key.clone(),
+        let tx = TransactionSubset {
+            hash: transaction.hash.clone(),
+            timestamp: transaction.timestamp,
+            hlc: transaction.hlc,
+            meta_contract_id: meta_contract_id.clone(),
+            method: transaction.method.clone(),
+            value: "".to_string(),
+        };
+
+        match prepare_metadatas(
+            &storage,
+            &transaction.data_key,
+            Some(&transaction.public_key),
+            transaction.timestamp,
+            &serde_json::to_string(&tx).unwrap(),
+            metadatas,
+        )
+        .and_then(|prepared| write_metadatas(&storage, &transaction.hash, prepared))
+        {
+            Ok(()) => {
+                transaction.status = STATUS_SUCCESS;
+                transaction.error_text = "".to_string();
+            }
+            Err(e) => {
+                transaction.status = STATUS_FAILED;
+                transaction.error_text = e.to_string();
+            }
+        }
+    }
+
+    let _ = storage.update_transaction_status(
+        transaction.hash.clone(),
+        transaction.status.clone(),
+        transaction.error_text.clone(),
+    );
+}
+
+/**
+ * Row change of one alias of a "metadata" transaction, worked out before
+ * anything is written
+ */
+struct PreparedMetadata {
+    metadata: Metadata,
+    exists: bool,
+    envelope: Option<PrivateEnvelope>,
+}
+
+/**
+ * Checks every alias of a write and puts its content block, with `tx` as the
+ * block's transaction payload. The signer's write access is checked, a write
+ * decided by the meta contract alone passes None. Nothing is written to the
+ * database, so a failure on any alias leaves no trace.
+ */
+fn prepare_metadatas(
+    storage: &Storage,
+    data_key: &str,
+    signer: Option<&str>,
+    timestamp: u64,
+    tx: &str,
+    metadatas: Vec<FinalMetadata>,
+) -> Result<Vec<PreparedMetadata>, ServiceError> {
+    let mut prepared = vec![];
+
+    for data in metadatas {
+        let result = storage.get_owner_metadata_by_datakey_and_alias(
+            data_key.to_string(),
+            data.public_key.clone(),
+            data.alias.clone(),
+        );
+
+        log::info!("{:?}", result);
+
+        let current = match result {
+            Ok(metadata) => Some(metadata),
+            Err(ServiceError::RecordNotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+
+        if let Some(signer) = signer {
+            check_write_access(
+                storage,
+                data_key,
+                &data.alias,
+                &data.public_key,
+                signer,
+                current.is_some(),
+            )?;
+        }
+
+        let envelope = seal_content(
+            storage,
+            data_key,
+            &data.alias,
+            &data.public_key,
+            &data.content,
+            timestamp,
+        )?;
+        let content = match &envelope {
+            Some(envelope) => envelope.envelope.clone(),
+            None => data.content,
+        };
+
+        let previous_cid = current
+            .as_ref()
+            .map(|metadata| metadata.cid.clone())
+            .unwrap_or_default();
+
+        let result_ipfs_dag_put =
+            put_block(content, previous_cid, tx.to_string(), "".to_string(), 0);
+
+        if !result_ipfs_dag_put.success || result_ipfs_dag_put.cid.is_empty() {
+            return Err(InternalError(f!(
+                "ipfs put failed: {result_ipfs_dag_put.error}"
+            )));
+        }
+
+        let exists = current.is_some();
+        let metadata = match current {
+            Some(mut metadata) => {
+                metadata.cid = result_ipfs_dag_put.cid;
+                metadata
+            }
+            None => Metadata::new(
+                data_key.to_string(),
                 data.alias.clone(),
-            );
+                result_ipfs_dag_put.cid,
+                data.public_key.clone(),
+            ),
+        };
 
-            log::info!("{:?}", result);
+        prepared.push(PreparedMetadata {
+            metadata,
+            exists,
+            envelope,
+        });
+    }
 
-            match result {
-                Ok(metadata) => {
-                    transaction.status = STATUS_SUCCESS;
+    Ok(prepared)
+}
 
-                    let tx = TransactionSubset {
-                        hash: transaction.hash.clone(),
-                        timestamp: transaction.timestamp.clone(),
-                        meta_contract_id: meta_contract_id.clone(),
-                        method: transaction.method.clone(),
-                        value: "".to_string(),
-                    };
+/**
+ * Writes the prepared rows of a "metadata" transaction all together, or none
+ * of them. When any alias was sealed, the plaintext payload kept in the
+ * transaction is replaced with the cids of the blocks it wrote, see
+ * `sealed_payload`.
+ */
+fn write_metadatas(
+    storage: &Storage,
+    transaction_hash: &str,
+    prepared: Vec<PreparedMetadata>,
+) -> Result<(), ServiceError> {
+    let sealed = sealed_payload(&prepared);
 
-                    let tx_serde = serde_json::to_string(&tx).unwrap();
+    storage.connection.execute("BEGIN TRANSACTION;")?;
 
-                    let result_ipfs_dag_put =
-                        put_block(data.content, metadata.cid, tx_serde, "".to_string(), 0);
-                    let content_cid = result_ipfs_dag_put.cid;
+    let result = prepared.into_iter().try_for_each(|item| {
+        let metadata = item.metadata;
 
-                    let _ = storage.update_cid(metadata.data_key, metadata.alias, metadata.public_key, content_cid);
-                }
-                Err(ServiceError::RecordNotFound(_)) => {
-                    transaction.status = STATUS_SUCCESS;
-
-                    let tx = TransactionSubset {
-                        hash: transaction.hash.clone(),
-                        timestamp: transaction.timestamp.clone(),
-                        meta_contract_id: meta_contract_id.clone(),
-                        method: transaction.method.clone(),
-                        value: "".to_string(),
-                    };
-
-                    let tx_serde = serde_json::to_string(&tx).unwrap();
-
-                    let result_ipfs_dag_put =
-                        put_block(data.content, "".to_string(), tx_serde, "".to_string(), 0);
-                    let content_cid = result_ipfs_dag_put.cid;
-
-                    let metadata = Metadata::new(
-                        transaction.data_key.clone(),
-                        data.alias.clone(),
-                        content_cid,
-                        data.public_key.clone(),
-                    );
-
-                    let _ = storage.write_metadata(metadata);
-                }
-                Err(e) => {
-                    transaction.error_text = e.to_string();
-                    transaction.status = STATUS_FAILED;
-                }
-            };
+        if let Some(mut envelope) = item.envelope {
+            envelope.cid = metadata.cid.clone();
+            storage.write_private_envelope(envelope)?;
+        }
+
+        storage.write_receipt(Receipt {
+            transaction_hash: transaction_hash.to_string(),
+            data_key: metadata.data_key.clone(),
+            alias: metadata.alias.clone(),
+            public_key: metadata.public_key.clone(),
+            cid: metadata.cid.clone(),
+        })?;
+
+        if item.exists {
+            storage.update_cid(
+                metadata.data_key,
+                metadata.alias,
+                metadata.public_key,
+                metadata.cid,
+            )
+        } else {
+            storage.write_metadata(metadata)
+        }
+    });
+
+    let result = result.and_then(|()| match sealed {
+        Some(data) => storage.update_transaction_data(transaction_hash.to_string(), data),
+        None => Ok(()),
+    });
+
+    match result {
+        Ok(()) => {
+            storage.connection.execute("COMMIT;")?;
+            Ok(())
+        }
+        Err(e) => {
+            let _ = storage.connection.execute("ROLLBACK;");
+            Err(e)
         }
     }
+}
 
-    let _ = storage.update_transaction_status(
-        transaction.hash.clone(),
-        transaction.status.clone(),
-        transaction.error_text.clone(),
-    );
+/**
+ * Payload to keep for a transaction that sealed private content: the JSON
+ * array of the cids it wrote, which the receipts map back to aliases. None
+ * when every alias was public.
+ */
+fn sealed_payload(prepared: &[PreparedMetadata]) -> Option<String> {
+    if prepared.iter().all(|item| item.envelope.is_none()) {
+        return None;
+    }
+
+    let cids: Vec<&str> = prepared
+        .iter()
+        .map(|item| item.metadata.cid.as_str())
+        .collect();
+
+    Some(serde_json::to_string(&cids).unwrap())
 }
 
 /**
- * Validated "metadata cron" method type
+ * Validated "metadata cron" method type. Only aliases without a row yet are
+ * written, all together with a receipt of the chain transaction `tx_hash`,
+ * or none of them.
  */
 pub fn validate_metadata_cron(
   data_key: String,
+  tx_hash: String,
   on_metacontract_result: bool,
   metadatas: Vec<FinalMetadata>,
 ) {
   let storage = get_storage().expect("Internal error to database connector");
 
-  if on_metacontract_result {
-      for data in metadatas {
-          let result = storage.get_owner_metadata_by_datakey_and_alias(
-              data_key.clone(),
-              data.public_key.clone(),
-              data.alias.clone(),
-          );
-
-          log::info!("{:?}", result);
-
-          match result {
-              Ok(_) => {}
-              Err(ServiceError::RecordNotFound(_)) => {
+  if !on_metacontract_result {
+      return;
+  }
 
-                  let result_ipfs_dag_put =
-                      put_block(data.content, "".to_string(), "{}".to_string(), "".to_string(), 0);
-                  let content_cid = result_ipfs_dag_put.cid;
+  let metadatas: Vec<FinalMetadata> = metadatas
+      .into_iter()
+      .filter(|data| {
+          matches!(
+              storage.get_owner_metadata_by_datakey_and_alias(
+                  data_key.clone(),
+                  data.public_key.clone(),
+                  data.alias.clone(),
+              ),
+              Err(ServiceError::RecordNotFound(_))
+          )
+      })
+      .collect();
+
+  if metadatas.is_empty() {
+      return;
+  }
 
-                  let metadata = Metadata::new(
-                      data_key.clone(),
-                      data.alias.clone(),
-                      content_cid,
-                      data.public_key.clone(),
-                  );
+  let result = prepare_metadatas(&storage, &data_key, None, physical_now(), "{}", metadatas)
+      .and_then(|prepared| write_metadatas(&storage, &tx_hash, prepared));
 
-                  let _ = storage.write_metadata(metadata);
-              }
-              Err(_) => {}
-          };
-      }
+  if let Err(e) = result {
+      log::info!("metadata cron {} failed: {}", tx_hash, e);
   }
 }
 
@@ -245,6 +399,7 @@ pub fn validate_clone(
         let tx = TransactionSubset {
             hash: transaction.hash.clone(),
             timestamp: transaction.timestamp.clone(),
+            hlc: transaction.hlc,
             meta_contract_id: meta_contract_id.clone(),
             method: transaction.method.clone(),
             value: serde_json::to_string(&data_clone).unwrap(),
@@ -265,16 +420,31 @@ pub fn validate_clone(
             0,
         );
 
-        let metadata = Metadata::new(
-            transaction.data_key.clone(),
-            origin_metadata.alias.clone(),
-            result_ipfs_dag_put.cid,
-            origin_metadata.public_key.clone(),
-        );
+        if !result_ipfs_dag_put.success || result_ipfs_dag_put.cid.is_empty() {
+            transaction.status = STATUS_FAILED;
+            transaction.error_text = f!("ipfs put failed: {result_ipfs_dag_put.error}");
+        } else {
+            let metadata = Metadata::new(
+                transaction.data_key.clone(),
+                origin_metadata.alias.clone(),
+                result_ipfs_dag_put.cid,
+                origin_metadata.public_key.clone(),
+            );
 
-        let _ = storage.write_metadata(metadata);
+            let prepared = vec![PreparedMetadata {
+                metadata,
+                exists: false,
+                envelope: None,
+            }];
 
-        transaction.status = STATUS_SUCCESS;
+            match write_metadatas(&storage, &transaction.hash, prepared) {
+                Ok(()) => transaction.status = STATUS_SUCCESS,
+                Err(e) => {
+                    transaction.status = STATUS_FAILED;
+                    transaction.error_text = e.to_string();
+                }
+            }
+        }
     }
 
     let _ = storage.update_transaction_status(
@@ -313,7 +483,10 @@ pub fn validate_cron(transaction_hash: String, data: String) {
 
     match result {
       Ok(row) => {
-        if transaction.public_key == row.public_key {
+        if storage
+          .is_same_owner(row.public_key.clone(), transaction.public_key.clone())
+          .unwrap_or(false)
+        {
           if !serde_cron.hash.is_empty() {
             match serde_cron.action.as_str() {
               CRON_ACTION_UPDATE => {
@@ -361,3 +534,37 @@ pub fn validate_cron(transaction_hash: String, data: String) {
         transaction.error_text.clone(),
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prepared(alias: &str, cid: &str, sealed: bool) -> PreparedMetadata {
+        PreparedMetadata {
+            metadata: Metadata::new(
+                "data_key".to_string(),
+                alias.to_string(),
+                cid.to_string(),
+                "owner".to_string(),
+            ),
+            exists: false,
+            envelope: sealed.then(PrivateEnvelope::default),
+        }
+    }
+
+    #[test]
+    fn sealed_payload_lists_the_written_cids() {
+        assert_eq!(
+            sealed_payload(&[
+                prepared("public", "cid1", false),
+                prepared("private", "cid2", true),
+            ]),
+            Some(r#"["cid1","cid2"]"#.to_string())
+        );
+    }
+
+    #[test]
+    fn public_payload_is_kept() {
+        assert_eq!(sealed_payload(&[prepared("public", "cid1", false)]), None);
+    }
+}
@@ -0,0 +1,50 @@
+use marine_rs_sdk::marine;
+use serde::{Deserialize, Serialize};
+
+pub const ENVELOPE_VERSION: u32 = 1;
+pub const ENVELOPE_CIPHER: &str = "chacha20-poly1305";
+pub const KEM_X25519: &str = "x25519-hkdf-sha256";
+pub const KEM_SECP256K1: &str = "secp256k1-hkdf-sha256";
+
+/**
+ * Encrypted payload shared by the crypto service, the node and clients.
+ * The content is encrypted once with a random content key, which is wrapped
+ * for every recipient. Binary fields are standard base64.
+ */
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub version: u32,
+    pub cipher: String,
+    pub nonce: String,
+    pub ciphertext: String,
+    pub recipients: Vec<EnvelopeRecipient>,
+}
+
+/**
+ * `key` is the recipient public key: base58 for ed25519 keys, which are
+ * converted to X25519, and compressed 0x hex for secp256k1 keys
+ */
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct EnvelopeRecipient {
+    pub key: String,
+    pub kem: String,
+    pub ephemeral: String,
+    pub nonce: String,
+    pub wrapped_key: String,
+}
+
+#[marine]
+#[derive(Debug, Default)]
+pub struct EncryptResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub envelope: String,
+}
+
+#[marine]
+#[derive(Debug, Default)]
+pub struct DecryptResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub plaintext: String,
+}
@@ -0,0 +1,85 @@
+use sha2::{Digest, Sha256};
+
+/**
+ * Transaction hash as the node derives it, base58 of the SHA-256 of the
+ * fields concatenated in this order
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn transaction_hash(
+    token_key: &str,
+    data_key: &str,
+    data: &str,
+    nonce: i64,
+    public_key: &str,
+    alias: &str,
+    method: &str,
+    token_id: &str,
+    version: i64,
+) -> String {
+    sha256_bs58(&format!(
+        "{}{}{}{}{}{}{}{}{}",
+        token_key, data_key, nonce, data, public_key, alias, method, token_id, version
+    ))
+}
+
+/**
+ * Cron hash, one cron per address, topic and chain
+ */
+pub fn cron_hash(address: &str, topic: &str, chain: &str) -> String {
+    sha256_bs58(&format!("{}{}{}", address, topic, chain))
+}
+
+/**
+ * Metadata hash, one row per data key, alias and owner
+ */
+pub fn metadata_hash(data_key: &str, alias: &str, public_key: &str) -> String {
+    sha256_bs58(&format!("{}{}{}", data_key, alias, public_key))
+}
+
+fn sha256_bs58(input: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(input.as_bytes());
+    bs58::encode(hasher.finalize()).into_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the strings the node's `generate_hash` produced before the move, so
+    // existing transaction, cron and metadata rows keep their keys
+
+    #[test]
+    fn transaction_hash_is_unchanged() {
+        assert_eq!(
+            transaction_hash(
+                "tk",
+                "dk",
+                r#"{"a":1}"#,
+                7,
+                "0xabc",
+                "profile",
+                "metadata",
+                "42",
+                1
+            ),
+            "Fk4dK81uC2g4M832NnFsv7TmTYYtTgzhj2M9aBqex4Pe"
+        );
+    }
+
+    #[test]
+    fn cron_hash_is_unchanged() {
+        assert_eq!(
+            cron_hash("0xcontract", "0xtopic", "filecoin"),
+            "AhrfrTjGZppLqLULzpuSYgnySoTTHLLRu9AqMtBDHCdH"
+        );
+    }
+
+    #[test]
+    fn metadata_hash_is_unchanged() {
+        assert_eq!(
+            metadata_hash("dk", "profile", "0xabc"),
+            "7BDFuZCmxCmvU742RXnRZhcfkw5PbqiuCH2CSFqxs9TC"
+        );
+    }
+}
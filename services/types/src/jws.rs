@@ -0,0 +1,15 @@
+use marine_rs_sdk::marine;
+
+/**
+ * Outcome of a compact JWS/JWT verification. `claims` is the JSON payload,
+ * filled in whenever the token could be decoded.
+ */
+#[marine]
+#[derive(Debug, Default)]
+pub struct JwsResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub alg: String,
+    pub kid: String,
+    pub claims: String,
+}
@@ -1,8 +1,35 @@
 use marine_rs_sdk::marine;
 
+/**
+ * Encodings per `enc`:
+ * - ed25519: `pk` is the base58 public key, `sk` the base58 64 byte secret key
+ * - secp256k1: `pk` is the 0x address, `sk` the 0x hex 32 byte secret key
+ * - bls12381: `pk` is the 0x hex 48 byte G1 key, `sk` the 0x hex 32 byte scalar
+ *
+ * `path` is the derivation path of keys derived from a mnemonic, empty for
+ * random keys.
+ */
 #[marine]
-#[derive(Debug)]
-pub struct Ed25519KeyPair {
+#[derive(Debug, Default)]
+pub struct KeyPair {
     pub pk: String,
     pub sk: String,
+    pub enc: String,
+    pub path: String,
+}
+
+#[marine]
+#[derive(Debug, Default)]
+pub struct KeyPairResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub keypair: KeyPair,
+}
+
+#[marine]
+#[derive(Debug, Default)]
+pub struct MnemonicResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub mnemonic: String,
 }
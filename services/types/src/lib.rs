@@ -1,9 +1,19 @@
 mod dht;
+mod envelope;
+mod hash;
 mod ipfs;
+mod jws;
 mod keypair;
 mod result;
+mod siwe;
 
 pub use dht::*;
+pub use envelope::*;
+pub use hash::{cron_hash, metadata_hash, transaction_hash};
 pub use ipfs::*;
-pub use keypair::Ed25519KeyPair;
-pub use result::FdbResult;
+pub use jws::JwsResult;
+pub use keypair::{KeyPair, KeyPairResult, MnemonicResult};
+pub use result::{
+    FdbResult, KeyTypeResult, KeyVerifyResult, SignResult, VerifyRequest, VerifyResult,
+};
+pub use siwe::SiweResult;
@@ -28,3 +28,48 @@ impl FdbResult {
         }
     }
 }
+
+#[marine]
+#[derive(Debug, Default)]
+pub struct VerifyResult {
+    pub success: bool,
+    pub err_msg: String,
+}
+
+#[marine]
+#[derive(Debug, Default)]
+pub struct KeyTypeResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub enc: String,
+    pub format: String,
+    pub public_key: String,
+}
+
+#[marine]
+#[derive(Debug, Default)]
+pub struct SignResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub signature: String,
+}
+
+#[marine]
+#[derive(Debug, Default)]
+pub struct VerifyRequest {
+    pub public_key: String,
+    pub signature: String,
+    pub message: String,
+    pub enc: String,
+}
+
+/**
+ * A `verify_batch` result: the key type the signature was checked with and
+ * the check itself
+ */
+#[marine]
+#[derive(Debug, Default)]
+pub struct KeyVerifyResult {
+    pub key_type: KeyTypeResult,
+    pub verify: VerifyResult,
+}
@@ -0,0 +1,25 @@
+use marine_rs_sdk::marine;
+
+/**
+ * Outcome of a Sign-In with Ethereum (EIP-4361) verification.
+ * Message fields are filled in whenever the message could be parsed, so a
+ * caller can still inspect a rejected message.
+ */
+#[marine]
+#[derive(Debug, Default)]
+pub struct SiweResult {
+    pub success: bool,
+    pub err_msg: String,
+    pub address: String,
+    pub domain: String,
+    pub statement: String,
+    pub uri: String,
+    pub version: String,
+    pub chain_id: u64,
+    pub nonce: String,
+    pub issued_at: String,
+    pub expiration_time: String,
+    pub not_before: String,
+    pub request_id: String,
+    pub resources: Vec<String>,
+}